@@ -0,0 +1,175 @@
+use crate::*;
+
+/// Maximum number of symbols a batch can carry, bounded by the width of the symbol bitmap.
+pub const MAX_BATCH_SYMBOLS: usize = 64;
+
+/// Compact encoding of a multi-symbol update.
+///
+/// Instead of repeating the symbol, timestamp and block numbers per row, a batch carries one
+/// shared `oracle_timestamp`, `ondo_block` and `traded_block`, a bitmap of the `TradingSymbol` ids present (bit `n` set means
+/// symbol id `n` is included) and a packed `[ondo_price, traded_price]` pair per set bit,
+/// in ascending symbol id order.
+#[derive(Clone, Default, Debug, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub struct RefreshOraclesBatchParams {
+    pub oracle_timestamp: i64,
+    pub ondo_block: u64,
+    pub traded_block: u64,
+    pub symbols: u64,
+    pub prices: Vec<u64>,
+}
+
+impl RefreshOraclesBatchParams {
+    /// Packs `rows` into a batch. Every row must have the same timestamp and block numbers,
+    /// which the batch shares, so no row is reported as newer than it is.
+    pub fn from_rows(rows: &[OracleDataWithTradingSymbol]) -> anchor_lang::Result<Self> {
+        let mut rows = rows.to_vec();
+        rows.sort_by_key(|row| u8::from(row.symbol));
+
+        let mut batch = Self::default();
+        if let Some(first) = rows.first() {
+            batch.oracle_timestamp = first.data.oracle_timestamp;
+            batch.ondo_block = first.data.ondo_block;
+            batch.traded_block = first.data.traded_block;
+        }
+        batch.prices.reserve(rows.len() * 2);
+        for row in rows.iter() {
+            let id = u8::from(row.symbol) as usize;
+            if id >= MAX_BATCH_SYMBOLS || batch.symbols & (1 << id) != 0 {
+                return Err(error!(USDY_USDC_ORACLEError::InvalidBatch));
+            }
+            if row.data.oracle_timestamp != batch.oracle_timestamp
+                || row.data.ondo_block != batch.ondo_block
+                || row.data.traded_block != batch.traded_block
+            {
+                return Err(error!(USDY_USDC_ORACLEError::InvalidBatch));
+            }
+            batch.symbols |= 1 << id;
+            batch.prices.push(row.data.ondo_price);
+            batch.prices.push(row.data.traded_price);
+        }
+
+        Ok(batch)
+    }
+
+    /// Checks the symbol bitmap only holds `TradingSymbol` ids and the packed prices line up
+    /// with it. Any other id would decode as `TradingSymbol::Unknown` and overwrite its row.
+    pub fn validate(&self) -> anchor_lang::Result<()> {
+        if self.prices.len() != 2 * self.symbols.count_ones() as usize {
+            return Err(error!(USDY_USDC_ORACLEError::InvalidBatch));
+        }
+        let unknown_id = (0..MAX_BATCH_SYMBOLS as u8)
+            .filter(|id| self.symbols & (1 << id) != 0)
+            .any(|id| u8::from(TradingSymbol::from(id)) != id);
+        if unknown_id {
+            return Err(error!(USDY_USDC_ORACLEError::InvalidBatch));
+        }
+        Ok(())
+    }
+
+    /// Unpacks the batch without allocating. Call `validate` first.
+    pub fn rows(&self) -> impl Iterator<Item = OracleDataWithTradingSymbol> + '_ {
+        (0..MAX_BATCH_SYMBOLS as u8)
+            .filter(move |id| self.symbols & (1 << id) != 0)
+            .zip(self.prices.chunks_exact(2))
+            .map(move |(id, prices)| OracleDataWithTradingSymbol {
+                symbol: id.into(),
+                data: OracleDataBorsh {
+                    oracle_timestamp: self.oracle_timestamp,
+                    ondo_price: prices[0],
+                    traded_price: prices[1],
+                    ondo_block: self.ondo_block,
+                    traded_block: self.traded_block,
+                },
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(symbol: TradingSymbol, oracle_timestamp: i64, price: u64) -> OracleDataWithTradingSymbol {
+        OracleDataWithTradingSymbol {
+            symbol,
+            data: OracleDataBorsh {
+                oracle_timestamp,
+                ondo_price: price,
+                traded_price: price + 1,
+                ondo_block: 18_000_000,
+                traded_block: 50_000_000,
+            },
+        }
+    }
+
+    #[test]
+    fn batch_round_trips_rows() {
+        let rows = vec![row(TradingSymbol::Usdy_usdc, 1_700_000_000, 1_050_000_000)];
+        let batch = RefreshOraclesBatchParams::from_rows(&rows).unwrap();
+        assert_eq!(batch.symbols, 1 << 1);
+        assert!(batch.validate().is_ok());
+
+        let decoded: Vec<_> = batch.rows().collect();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].symbol, TradingSymbol::Usdy_usdc);
+        assert_eq!(decoded[0].data.ondo_price, 1_050_000_000);
+        assert_eq!(decoded[0].data.traded_price, 1_050_000_001);
+        assert_eq!(decoded[0].data.ondo_block, 18_000_000);
+        assert_eq!(decoded[0].data.traded_block, 50_000_000);
+    }
+
+    #[test]
+    fn batch_rejects_duplicate_symbols() {
+        let rows = vec![
+            row(TradingSymbol::Usdy_usdc, 1, 1),
+            row(TradingSymbol::Usdy_usdc, 2, 2),
+        ];
+        assert!(RefreshOraclesBatchParams::from_rows(&rows).is_err());
+    }
+
+    #[test]
+    fn batch_rejects_rows_read_at_different_times() {
+        let rows = vec![
+            row(TradingSymbol::Unknown, 1_700_000_000, 1),
+            row(TradingSymbol::Usdy_usdc, 1_700_000_000, 2),
+        ];
+        assert!(RefreshOraclesBatchParams::from_rows(&rows).is_ok());
+
+        let mut older = rows.clone();
+        older[0].data.oracle_timestamp -= 1;
+        assert!(RefreshOraclesBatchParams::from_rows(&older).is_err());
+
+        let mut other_block = rows;
+        other_block[1].data.traded_block += 1;
+        assert!(RefreshOraclesBatchParams::from_rows(&other_block).is_err());
+    }
+
+    #[test]
+    fn batch_rejects_mismatched_prices() {
+        let batch = RefreshOraclesBatchParams {
+            oracle_timestamp: 1,
+            symbols: 0b110,
+            prices: vec![1, 2],
+            ..Default::default()
+        };
+        assert!(batch.validate().is_err());
+    }
+
+    #[test]
+    fn batch_rejects_unknown_symbol_ids() {
+        let known = RefreshOraclesBatchParams {
+            symbols: 0b11,
+            prices: vec![1, 2, 3, 4],
+            ..Default::default()
+        };
+        assert!(known.validate().is_ok());
+
+        for id in [2, MAX_BATCH_SYMBOLS - 1] {
+            let batch = RefreshOraclesBatchParams {
+                symbols: 1 << id,
+                prices: vec![1, 2],
+                ..Default::default()
+            };
+            assert!(batch.validate().is_err());
+        }
+    }
+}
@@ -4,6 +4,8 @@
 // program and store in an account. When a user interacts with our program they will reference
 // the price from the previous push.
 // - initialize:        Initializes the program and creates the accounts.
+// - update:            Points an initialized program at a function and creates its feeds.
+//                      Signed by the authority, and unavailable once the multisig is set.
 // - set_function:      Sets the Switchboard Function for our program. This is the only function
 //                      allowed to push data to our program.
 // - refresh_oracle:    This is the instruction our Switchboard Function will emit to update
@@ -11,12 +13,55 @@
 // - trigger_function:  Our Switchboard Function will be configured to push data on a pre-defined
 //                      schedule. This instruction will allow us to manually request a new price
 //                      from the off-chain oracles.
+// - trigger_function_request: Same as trigger_function but through a Switchboard function
+//                      request carrying FunctionRequestParams, for a targeted refresh. The
+//                      run passes the request to refresh_oracles, which only accepts requests
+//                      opened by the function authority.
+// - set_config:        Configures the optional cross-check against reference Switchboard V2
+//                      feeds, which refresh_oracles must then be passed as remaining accounts.
+//                      Each feed is checked against the traded price, the NAV or the USDC/USD
+//                      price they imply, as configured.
+//                      Pyth price accounts aren't supported as reference feeds.
+// - migrate:           Grows the program state and oracle accounts created by an older version
+//                      of the program to their current size.
+// - refresh_oracles_batch: Same as refresh_oracles but takes the compact
+//                      RefreshOraclesBatchParams encoding for multi-symbol updates.
+// - refresh_oracles_signed: Backup ingestion path for an off-chain publisher that signs the
+//                      RefreshOraclesParams, see signed_update_message, with the ed25519 key
+//                      set by set_trusted_signer.
+// - init_symbol_table: Creates the symbol table account holding one OracleData per symbol id.
+// - grow_symbol_table: Reallocates the symbol table as symbols are added.
+// - init_multisig:     Hands admin control to an M-of-N set of signers. Admin changes are then
+//                      made with propose_action, approve_action and execute_action.
+// - sync_feeds:        Creates the Ondo feed accounts of the current function, e.g. after the
+//                      multisig changed it, and gives them the program authority.
 
 pub use switchboard_solana::prelude::*;
 
 pub mod models;
 pub use models::*;
 
+pub mod reference;
+pub use reference::*;
+
+pub mod multisig;
+pub use multisig::*;
+
+pub mod signed;
+pub use signed::*;
+
+pub mod batch;
+pub use batch::*;
+
+pub mod symbol_table;
+pub use symbol_table::*;
+
+pub mod params;
+pub use params::*;
+
+pub mod request;
+pub use request::*;
+
 
 
 declare_id!("2LuPhyrumCFRXjeDuYp1bLNYp7EbzUraZcvrzN9ZBUkN");
@@ -53,17 +98,20 @@ pub mod usdy_usd_oracle {
     }
 
 
-    pub fn update(ctx: Context<Initialize>, bump: u8, bump2: u8) -> anchor_lang::Result<()> {
+    pub fn update(ctx: Context<Update>, bump: u8, bump2: u8) -> anchor_lang::Result<()> {
         let program = &mut ctx.accounts.program.load_mut()?;
         program.bump = bump;
-        program.authority = ctx.accounts.authority.key();
 
         // Optionally set the switchboard_function if provided
         program.switchboard_function = ctx.accounts.switchboard_function.key();
-        let ondo_price_feed = &mut ctx.accounts.ondo_price_feed.load_mut()?;
-        ondo_price_feed.authority = ctx.accounts.authority.key();
-        let ondo_traded_feed = &mut ctx.accounts.ondo_traded_feed.load_mut()?;
-        ondo_traded_feed.authority = ctx.accounts.authority.key();
+        for feed in [&ctx.accounts.ondo_price_feed, &ctx.accounts.ondo_traded_feed] {
+            // the feeds of a new function were just created
+            let mut feed = match feed.load_mut() {
+                Ok(feed) => feed,
+                Err(_) => feed.load_init()?,
+            };
+            feed.authority = ctx.accounts.authority.key();
+        }
         let oracle = &mut ctx.accounts.oracle.load_mut()?;
         oracle.bump = bump2;
         
@@ -76,35 +124,100 @@ pub mod usdy_usd_oracle {
         let oracle = &mut ctx.accounts.oracle.load_mut()?;
         msg!("saving oracle data");
         oracle.save_rows(&params.rows)?;
+        save_to_symbol_table(&ctx.accounts.symbol_table, params.rows.iter().copied())?;
         msg!("${}", {oracle.usdy_usd.ondo_price});
         msg!("${}", {oracle.usdy_usd.traded_price});
-        let ondo_price_feed = &mut ctx.accounts.ondo_price_feed.load_mut()?;
-        
-        let mut result = models::AggregatorRound::default();
-        result.num_success = 1;
-        result.num_error = 0;
-        result.result = models::SwitchboardDecimal::from_f64(oracle.usdy_usd.ondo_price as f64);
-        result.result.scale = 9;
-        result.round_open_timestamp = Clock::get()?.unix_timestamp;
-        result.round_open_slot = Clock::get()?.slot;
-        ondo_price_feed.latest_confirmed_round = result;
-        
-        let ondo_traded_feed = &mut ctx.accounts.ondo_traded_feed.load_mut()?;
+        let clock = Clock::get()?;
+        save_to_feeds(
+            &ctx.accounts.ondo_price_feed,
+            &ctx.accounts.ondo_traded_feed,
+            &oracle.usdy_usd,
+            &clock,
+        )?;
 
-        let mut result = models::AggregatorRound::default();
-        result.num_success = 1;
-        result.num_error = 0;
-        result.result = models::SwitchboardDecimal::from_f64(oracle.usdy_usd.traded_price as f64);
-        result.result.scale = 9;
-        result.round_open_timestamp =  Clock::get()?.unix_timestamp;
-        result.round_open_slot = Clock::get()?.slot;
+        // cross-check against the configured reference feeds, passed as remaining accounts
+        check_reference_feeds(
+            &ctx.accounts.program.load()?,
+            &oracle.usdy_usd,
+            ctx.remaining_accounts,
+            clock.unix_timestamp,
+        )?;
 
-        ondo_traded_feed.latest_confirmed_round = result;
+        Ok(())
+    }
 
-        // save the price
+    pub fn refresh_oracles_batch(
+        ctx: Context<RefreshOraclesBatch>,
+        params: RefreshOraclesBatchParams,
+    ) -> anchor_lang::Result<()> {
+        params.validate()?;
+        let oracle = &mut ctx.accounts.oracle.load_mut()?;
+        msg!("saving oracle batch");
+        for row in params.rows() {
+            oracle.save_row(&row);
+        }
+        save_to_symbol_table(&ctx.accounts.symbol_table, params.rows())?;
+        let clock = Clock::get()?;
+        save_to_feeds(
+            &ctx.accounts.ondo_price_feed,
+            &ctx.accounts.ondo_traded_feed,
+            &oracle.usdy_usd,
+            &clock,
+        )?;
 
+        check_reference_feeds(
+            &ctx.accounts.program.load()?,
+            &oracle.usdy_usd,
+            ctx.remaining_accounts,
+            clock.unix_timestamp,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn refresh_oracles_signed(
+        ctx: Context<RefreshOraclesSigned>,
+        params: RefreshOraclesParams,
+    ) -> anchor_lang::Result<()> {
+        let program = ctx.accounts.program.load()?;
+        if program.trusted_signer == Pubkey::default() {
+            return Err(error!(USDY_USDC_ORACLEError::InvalidTrustedSigner));
+        }
+        let params_data = params
+            .try_to_vec()
+            .map_err(|_| error!(USDY_USDC_ORACLEError::InvalidEd25519Instruction))?;
+        verify_ed25519_instruction(
+            &ctx.accounts.instructions_sysvar,
+            &program.trusted_signer,
+            &signed_update_message(&ctx.accounts.oracle.key(), &params_data),
+        )?;
+
+        let clock = Clock::get()?;
+        let oracle = &mut ctx.accounts.oracle.load_mut()?;
+        for row in params.rows.iter() {
+            check_signed_timestamp(
+                row.data.oracle_timestamp,
+                oracle.usdy_usd.oracle_timestamp,
+                clock.unix_timestamp,
+            )?;
+        }
+        msg!("saving signed oracle data");
+        oracle.save_rows(&params.rows)?;
+        save_to_symbol_table(&ctx.accounts.symbol_table, params.rows.iter().copied())?;
+        save_to_feeds(
+            &ctx.accounts.ondo_price_feed,
+            &ctx.accounts.ondo_traded_feed,
+            &oracle.usdy_usd,
+            &clock,
+        )?;
+
+        check_reference_feeds(
+            &program,
+            &oracle.usdy_usd,
+            ctx.remaining_accounts,
+            clock.unix_timestamp,
+        )?;
 
-        
         Ok(())
     }
 
@@ -115,6 +228,134 @@ pub mod usdy_usd_oracle {
         Ok(())
     }
 
+    pub fn set_config(ctx: Context<SetConfig>, params: SetConfigParams) -> anchor_lang::Result<()> {
+        let program = &mut ctx.accounts.program.load_mut()?;
+        program.apply_config(&params)?;
+
+        Ok(())
+    }
+
+    pub fn set_trusted_signer(
+        ctx: Context<SetTrustedSigner>,
+        trusted_signer: Pubkey,
+    ) -> anchor_lang::Result<()> {
+        let program = &mut ctx.accounts.program.load_mut()?;
+        program.trusted_signer = trusted_signer;
+
+        Ok(())
+    }
+
+    pub fn migrate(_ctx: Context<Migrate>) -> anchor_lang::Result<()> {
+        // the accounts were already reallocated, zeroing the new fields, by the realloc constraints
+        Ok(())
+    }
+
+    pub fn init_symbol_table(ctx: Context<InitSymbolTable>, capacity: u32) -> anchor_lang::Result<()> {
+        let symbol_table = &mut ctx.accounts.symbol_table.load_init()?;
+        symbol_table.bump = ctx.bumps.symbol_table;
+        symbol_table.capacity = capacity;
+
+        Ok(())
+    }
+
+    pub fn grow_symbol_table(ctx: Context<GrowSymbolTable>, capacity: u32) -> anchor_lang::Result<()> {
+        // the account was already reallocated and topped up for rent by the realloc constraint
+        let symbol_table = &mut ctx.accounts.symbol_table.load_mut()?;
+        symbol_table.capacity = capacity;
+
+        Ok(())
+    }
+
+    pub fn init_multisig(
+        ctx: Context<InitMultisig>,
+        signers: Vec<Pubkey>,
+        threshold: u8,
+    ) -> anchor_lang::Result<()> {
+        let multisig = &mut ctx.accounts.multisig.load_init()?;
+        multisig.bump = ctx.bumps.multisig;
+        multisig.set_signers(&signers, threshold)?;
+
+        let program = &mut ctx.accounts.program.load_mut()?;
+        program.multisig = ctx.accounts.multisig.key();
+
+        Ok(())
+    }
+
+    pub fn propose_action(ctx: Context<ProposeAction>, action: AdminAction) -> anchor_lang::Result<()> {
+        let multisig = &mut ctx.accounts.multisig.load_mut()?;
+        let signer_index = multisig
+            .signer_index(&ctx.accounts.proposer.key())
+            .ok_or(error!(USDY_USDC_ORACLEError::InvalidMultisigSigner))?;
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.multisig = ctx.accounts.multisig.key();
+        proposal.index = multisig.proposal_count;
+        proposal.nonce = multisig.nonce;
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.action = action;
+        proposal.bump = ctx.bumps.proposal;
+        // proposing counts as the proposer's approval
+        proposal.approve(signer_index);
+
+        multisig.proposal_count += 1;
+
+        Ok(())
+    }
+
+    pub fn approve_action(ctx: Context<ApproveAction>) -> anchor_lang::Result<()> {
+        let multisig = ctx.accounts.multisig.load()?;
+        let signer_index = multisig
+            .signer_index(&ctx.accounts.approver.key())
+            .ok_or(error!(USDY_USDC_ORACLEError::InvalidMultisigSigner))?;
+
+        let proposal = &mut ctx.accounts.proposal;
+        if proposal.executed {
+            return Err(error!(USDY_USDC_ORACLEError::ProposalAlreadyExecuted));
+        }
+        if proposal.nonce != multisig.nonce {
+            return Err(error!(USDY_USDC_ORACLEError::StaleProposal));
+        }
+        proposal.approve(signer_index);
+
+        Ok(())
+    }
+
+    pub fn execute_action(ctx: Context<ExecuteAction>) -> anchor_lang::Result<()> {
+        let multisig = &mut ctx.accounts.multisig.load_mut()?;
+        let program = &mut ctx.accounts.program.load_mut()?;
+        let switchboard_function = ctx.accounts.switchboard_function.as_ref().map(|f| f.key());
+        ctx.accounts.proposal.execute(
+            multisig,
+            program,
+            &ctx.accounts.executor.key(),
+            switchboard_function,
+        )?;
+
+        // the feeds of the current function follow the program authority, when they exist
+        if let AdminAction::SetAuthority { authority } = ctx.accounts.proposal.action {
+            let feeds = [&ctx.accounts.ondo_price_feed, &ctx.accounts.ondo_traded_feed];
+            for feed in feeds.into_iter().flatten() {
+                feed.load_mut()?.authority = authority;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn sync_feeds(ctx: Context<SyncFeeds>) -> anchor_lang::Result<()> {
+        let authority = ctx.accounts.program.load()?.authority;
+        for feed in [&ctx.accounts.ondo_price_feed, &ctx.accounts.ondo_traded_feed] {
+            // the feeds were just created unless they already have a discriminator
+            let mut feed = match feed.load_mut() {
+                Ok(feed) => feed,
+                Err(_) => feed.load_init()?,
+            };
+            feed.authority = authority;
+        }
+
+        Ok(())
+    }
+
     pub fn trigger_function(ctx: Context<TriggerFunction>) -> anchor_lang::Result<()> {
         FunctionTrigger {
             function: ctx.accounts.switchboard_function.to_account_info(),
@@ -124,6 +365,37 @@ pub mod usdy_usd_oracle {
         .invoke(ctx.accounts.attestation_program.clone())?;
         Ok(())
     }
+
+    pub fn trigger_function_request(
+        ctx: Context<TriggerFunctionRequest>,
+        params: FunctionRequestParams,
+    ) -> anchor_lang::Result<()> {
+        let container_params = params.encode()?;
+        FunctionRequestInitAndTrigger {
+            request: ctx.accounts.request.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+            function: ctx.accounts.switchboard_function.to_account_info(),
+            function_authority: Some(ctx.accounts.authority.to_account_info()),
+            escrow: ctx.accounts.escrow.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            state: ctx.accounts.state.to_account_info(),
+            attestation_queue: ctx.accounts.attestation_queue.to_account_info(),
+            payer: ctx.accounts.payer.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+        }
+        .invoke(
+            ctx.accounts.attestation_program.clone(),
+            None,
+            None,
+            Some(MAX_FUNCTION_REQUEST_PARAMS_LEN as u32),
+            Some(container_params),
+            None,
+            None,
+        )?;
+        Ok(())
+    }
 }
 
 #[derive(Accounts)]
@@ -173,6 +445,52 @@ pub struct Initialize<'info> {
     pub ondo_traded_feed: AccountLoader<'info, models::AggregatorAccountData>
 }
 
+/// Same accounts as `Initialize`, for an existing program whose authority signs. Once the
+/// multisig is set, changes go through `AdminAction` instead.
+#[derive(Accounts)]
+pub struct Update<'info> {
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+        has_one = authority,
+        constraint = program.load()?.multisig == Pubkey::default() @ USDY_USDC_ORACLEError::MultisigRequired
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+
+    #[account(
+        mut,
+        seeds = [ORACLE_SEED],
+        bump = oracle.load()?.bump
+    )]
+    pub oracle: AccountLoader<'info, MyOracleState>,
+
+    pub authority: Signer<'info>,
+
+    pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(init_if_needed,
+        seeds = [ORACLE_SEED, switchboard_function.key().as_ref(), b"ondo_price_feed"],
+        bump,
+        payer = payer,
+        space = 8 + std::mem::size_of::<AggregatorAccountData>(),
+    )]
+    pub ondo_price_feed: AccountLoader<'info, models::AggregatorAccountData>,
+
+    #[account(init_if_needed,
+        seeds = [ORACLE_SEED, switchboard_function.key().as_ref(), b"ondo_traded_feed"],
+        bump,
+        payer = payer,
+        space = 8 + std::mem::size_of::<AggregatorAccountData>(),
+    )]
+    pub ondo_traded_feed: AccountLoader<'info, models::AggregatorAccountData>,
+}
+
 #[derive(Accounts)]
 #[instruction(params: RefreshOraclesParams)] // rpc parameters hint
 pub struct RefreshOracles<'info> {
@@ -195,26 +513,148 @@ pub struct RefreshOracles<'info> {
     // We use this to verify the functions enclave state was verified successfully
    #[account(
     constraint =
-                switchboard_function.load()?.validate(
-                &enclave_signer.to_account_info()
+                validate_enclave_signer(
+                &switchboard_function,
+                &enclave_signer.to_account_info(),
+                function_request.as_deref().map(|request| &**request)
             )? @ USDY_USDC_ORACLEError::FunctionValidationFailed     
     )]
     pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
     pub enclave_signer: Signer<'info>,
+    // The feeds of a new function only exist once sync_feeds was called for it.
     #[account(mut, 
         constraint = ondo_price_feed.load()?.authority == program.load()?.authority,
 
         seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(),  b"ondo_price_feed"],
         bump
     )]
-    pub ondo_price_feed: AccountLoader<'info, models::AggregatorAccountData>,
+    pub ondo_price_feed: Option<AccountLoader<'info, models::AggregatorAccountData>>,
     #[account(mut,        
+        constraint = ondo_traded_feed.load()?.authority == program.load()?.authority,
+
+        seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(), b"ondo_traded_feed"],
+        bump
+    )]
+    pub ondo_traded_feed: Option<AccountLoader<'info, models::AggregatorAccountData>>,
+
+    #[account(mut,
+        seeds = [SYMBOL_TABLE_SEED],
+        bump = symbol_table.load()?.bump
+    )]
+    pub symbol_table: Option<AccountLoader<'info, SymbolTable>>,
+
+    // The request a run started by trigger_function_request was for, whose enclave signer
+    // signs instead of the function's. Scheduled runs don't pass one.
+    #[account(
+        constraint = is_authorized_request(
+            &switchboard_function.load()?,
+            &function_request
+        ) @ USDY_USDC_ORACLEError::UnauthorizedFunctionRequest
+    )]
+    pub function_request: Option<Box<Account<'info, FunctionRequestAccountData>>>,
+}
+
+/// Same accounts as `RefreshOracles`.
+#[derive(Accounts)]
+#[instruction(params: RefreshOraclesBatchParams)] // rpc parameters hint
+pub struct RefreshOraclesBatch<'info> {
+    // We need this to validate that the Switchboard Function passed to our program
+    // is the expected one.
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+       //has_one = switchboard_function
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+
+    #[account(
+        mut,
+        seeds = [ORACLE_SEED],
+        bump = oracle.load()?.bump
+    )]
+    pub oracle: AccountLoader<'info, MyOracleState>,
+
+    // We use this to verify the functions enclave state was verified successfully
+   #[account(
+    constraint =
+                validate_enclave_signer(
+                &switchboard_function,
+                &enclave_signer.to_account_info(),
+                function_request.as_deref().map(|request| &**request)
+            )? @ USDY_USDC_ORACLEError::FunctionValidationFailed     
+    )]
+    pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
+    pub enclave_signer: Signer<'info>,
+    // The feeds of a new function only exist once sync_feeds was called for it.
+    #[account(mut, 
         constraint = ondo_price_feed.load()?.authority == program.load()?.authority,
 
+        seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(),  b"ondo_price_feed"],
+        bump
+    )]
+    pub ondo_price_feed: Option<AccountLoader<'info, models::AggregatorAccountData>>,
+    #[account(mut,        
+        constraint = ondo_traded_feed.load()?.authority == program.load()?.authority,
+
         seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(), b"ondo_traded_feed"],
         bump
     )]
-    pub ondo_traded_feed: AccountLoader<'info, models::AggregatorAccountData>
+    pub ondo_traded_feed: Option<AccountLoader<'info, models::AggregatorAccountData>>,
+
+    #[account(mut,
+        seeds = [SYMBOL_TABLE_SEED],
+        bump = symbol_table.load()?.bump
+    )]
+    pub symbol_table: Option<AccountLoader<'info, SymbolTable>>,
+
+    #[account(
+        constraint = is_authorized_request(
+            &switchboard_function.load()?,
+            &function_request
+        ) @ USDY_USDC_ORACLEError::UnauthorizedFunctionRequest
+    )]
+    pub function_request: Option<Box<Account<'info, FunctionRequestAccountData>>>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: RefreshOraclesParams)] // rpc parameters hint
+pub struct RefreshOraclesSigned<'info> {
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+
+    #[account(
+        mut,
+        seeds = [ORACLE_SEED],
+        bump = oracle.load()?.bump
+    )]
+    pub oracle: AccountLoader<'info, MyOracleState>,
+
+    #[account(mut,
+        constraint = ondo_price_feed.load()?.authority == program.load()?.authority,
+        seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(), b"ondo_price_feed"],
+        bump
+    )]
+    pub ondo_price_feed: Option<AccountLoader<'info, models::AggregatorAccountData>>,
+
+    #[account(mut,
+        constraint = ondo_traded_feed.load()?.authority == program.load()?.authority,
+        seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(), b"ondo_traded_feed"],
+        bump
+    )]
+    pub ondo_traded_feed: Option<AccountLoader<'info, models::AggregatorAccountData>>,
+
+    #[account(mut,
+        seeds = [SYMBOL_TABLE_SEED],
+        bump = symbol_table.load()?.bump
+    )]
+    pub symbol_table: Option<AccountLoader<'info, SymbolTable>>,
+
+    /// CHECK: address is explicit
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
 }
 
 #[derive(Clone, AnchorSerialize, AnchorDeserialize)]
@@ -228,7 +668,8 @@ pub struct SetFunction<'info> {
         mut,
         seeds = [PROGRAM_SEED],
         bump = program.load()?.bump,
-        has_one = authority
+        has_one = authority,
+        constraint = program.load()?.multisig == Pubkey::default() @ USDY_USDC_ORACLEError::MultisigRequired
     )]
     pub program: AccountLoader<'info, MyProgramState>,
     pub authority: Signer<'info>,
@@ -236,6 +677,273 @@ pub struct SetFunction<'info> {
     pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
 }
 
+#[derive(Accounts)]
+#[instruction(params: SetConfigParams)] // rpc parameters hint
+pub struct SetConfig<'info> {
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+        has_one = authority,
+        constraint = program.load()?.multisig == Pubkey::default() @ USDY_USDC_ORACLEError::MultisigRequired
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Clone, Debug, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub struct SetConfigParams {
+    pub max_reference_divergence_bps: u64,
+    pub max_reference_staleness: i64,
+    pub reject_on_divergence: bool,
+    /// At most `MAX_REFERENCE_FEEDS` Switchboard V2 aggregators and the price each is checked
+    /// against, see `MyProgramState.reference_feeds`.
+    pub reference_feeds: Vec<ReferenceFeed>,
+}
+
+#[derive(Accounts)]
+#[instruction(trusted_signer: Pubkey)] // rpc parameters hint
+pub struct SetTrustedSigner<'info> {
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+        has_one = authority,
+        constraint = program.load()?.multisig == Pubkey::default() @ USDY_USDC_ORACLEError::MultisigRequired
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+    pub authority: Signer<'info>,
+}
+
+/// Anyone can migrate, the accounts only grow and their new fields are zeroed, which leaves the
+/// features they configure disabled.
+#[derive(Accounts)]
+pub struct Migrate<'info> {
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump,
+        realloc = 8 + std::mem::size_of::<MyProgramState>(),
+        realloc::payer = payer,
+        realloc::zero = true
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+
+    #[account(
+        mut,
+        seeds = [ORACLE_SEED],
+        bump,
+        realloc = 8 + std::mem::size_of::<MyOracleState>(),
+        realloc::payer = payer,
+        realloc::zero = true
+    )]
+    pub oracle: AccountLoader<'info, MyOracleState>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(capacity: u32)] // rpc parameters hint
+pub struct InitSymbolTable<'info> {
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+        has_one = authority,
+        constraint = program.load()?.multisig == Pubkey::default() @ USDY_USDC_ORACLEError::MultisigRequired
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        space = SymbolTable::space(capacity),
+        payer = payer,
+        seeds = [SYMBOL_TABLE_SEED],
+        bump
+    )]
+    pub symbol_table: AccountLoader<'info, SymbolTable>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(capacity: u32)] // rpc parameters hint
+pub struct GrowSymbolTable<'info> {
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+        has_one = authority,
+        constraint = program.load()?.multisig == Pubkey::default() @ USDY_USDC_ORACLEError::MultisigRequired
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [SYMBOL_TABLE_SEED],
+        bump = symbol_table.load()?.bump,
+        constraint = capacity >= symbol_table.load()?.capacity @ USDY_USDC_ORACLEError::ArrayOverflow,
+        realloc = SymbolTable::space(capacity),
+        realloc::payer = payer,
+        realloc::zero = false
+    )]
+    pub symbol_table: AccountLoader<'info, SymbolTable>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitMultisig<'info> {
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+        has_one = authority,
+        constraint = program.load()?.multisig == Pubkey::default() @ USDY_USDC_ORACLEError::MultisigRequired
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        space = 8 + std::mem::size_of::<MultisigState>(),
+        payer = payer,
+        seeds = [MULTISIG_SEED, program.key().as_ref()],
+        bump
+    )]
+    pub multisig: AccountLoader<'info, MultisigState>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAction<'info> {
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+        has_one = multisig
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+
+    #[account(mut)]
+    pub multisig: AccountLoader<'info, MultisigState>,
+
+    #[account(
+        init,
+        space = Proposal::SPACE,
+        payer = payer,
+        seeds = [PROPOSAL_SEED, multisig.key().as_ref(), &multisig.load()?.proposal_count.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    pub proposer: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveAction<'info> {
+    pub multisig: AccountLoader<'info, MultisigState>,
+
+    #[account(
+        mut,
+        seeds = [PROPOSAL_SEED, multisig.key().as_ref(), &proposal.index.to_le_bytes()],
+        bump = proposal.bump,
+        has_one = multisig
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteAction<'info> {
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+        has_one = multisig
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+
+    #[account(mut)]
+    pub multisig: AccountLoader<'info, MultisigState>,
+
+    #[account(
+        mut,
+        seeds = [PROPOSAL_SEED, multisig.key().as_ref(), &proposal.index.to_le_bytes()],
+        bump = proposal.bump,
+        has_one = multisig
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    pub executor: Signer<'info>,
+
+    /// Updated by `AdminAction::SetAuthority` when passed. Feeds of a function set by
+    /// `AdminAction::SetFunction` don't exist until `sync_feeds` creates them.
+    #[account(mut,
+        seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(), b"ondo_price_feed"],
+        bump
+    )]
+    pub ondo_price_feed: Option<AccountLoader<'info, models::AggregatorAccountData>>,
+
+    #[account(mut,
+        seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(), b"ondo_traded_feed"],
+        bump
+    )]
+    pub ondo_traded_feed: Option<AccountLoader<'info, models::AggregatorAccountData>>,
+
+    /// Only required when executing `AdminAction::SetFunction`.
+    pub switchboard_function: Option<AccountLoader<'info, FunctionAccountData>>,
+}
+
+/// Anyone can sync the feeds, they only take the program authority.
+#[derive(Accounts)]
+pub struct SyncFeeds<'info> {
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+
+    #[account(init_if_needed,
+        seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(), b"ondo_price_feed"],
+        bump,
+        payer = payer,
+        space = 8 + std::mem::size_of::<AggregatorAccountData>(),
+    )]
+    pub ondo_price_feed: AccountLoader<'info, models::AggregatorAccountData>,
+
+    #[account(init_if_needed,
+        seeds = [ORACLE_SEED, program.load()?.switchboard_function.as_ref(), b"ondo_traded_feed"],
+        bump,
+        payer = payer,
+        space = 8 + std::mem::size_of::<AggregatorAccountData>(),
+    )]
+    pub ondo_traded_feed: AccountLoader<'info, models::AggregatorAccountData>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct TriggerFunction<'info> {
     // We need this to validate that the Switchboard Function passed to our program
@@ -262,6 +970,58 @@ pub struct TriggerFunction<'info> {
     pub attestation_program: AccountInfo<'info>,
 }
 
+#[derive(Accounts)]
+#[instruction(params: FunctionRequestParams)] // rpc parameters hint
+pub struct TriggerFunctionRequest<'info> {
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program.load()?.bump,
+        has_one = switchboard_function
+    )]
+    pub program: AccountLoader<'info, MyProgramState>,
+
+    #[account(mut,
+        has_one = authority,
+        has_one = attestation_queue,
+        owner = attestation_program.key()
+    )]
+    pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
+    pub authority: Signer<'info>,
+
+    /// A new keypair, initialized as the function request by the attestation program.
+    #[account(mut)]
+    pub request: Signer<'info>,
+
+    /// CHECK: the request's wrapped SOL escrow, created and checked by the attestation program
+    #[account(mut)]
+    pub escrow: AccountInfo<'info>,
+
+    /// CHECK: address is explicit
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub mint: AccountInfo<'info>,
+
+    pub state: AccountLoader<'info, AttestationProgramState>,
+
+    pub attestation_queue: AccountLoader<'info, AttestationQueueAccountData>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: address is explicit
+    #[account(address = anchor_spl::token::ID)]
+    pub token_program: AccountInfo<'info>,
+
+    /// CHECK: address is explicit
+    #[account(address = anchor_spl::associated_token::ID)]
+    pub associated_token_program: AccountInfo<'info>,
+
+    /// CHECK: address is explicit
+    #[account(address = SWITCHBOARD_ATTESTATION_PROGRAM_ID)]
+    pub attestation_program: AccountInfo<'info>,
+}
+
 #[error_code]
 #[derive(Eq, PartialEq)]
 pub enum USDY_USDC_ORACLEError {
@@ -283,4 +1043,32 @@ pub enum USDY_USDC_ORACLEError {
     InvalidSwitchboardFunction,
     #[msg("FunctionAccount was not validated successfully")]
     FunctionValidationFailed,
+    #[msg("Reference feed is not a valid Switchboard V2 aggregator")]
+    InvalidReferenceFeed,
+    #[msg("Price diverges from the reference feed by more than the configured max")]
+    ReferenceDivergenceExceeded,
+    #[msg("Previous instruction is not a valid Ed25519 signature over the params")]
+    InvalidEd25519Instruction,
+    #[msg("Batch prices do not match the symbol bitmap")]
+    InvalidBatch,
+    #[msg("Admin changes must go through the multisig")]
+    MultisigRequired,
+    #[msg("Signer is not a member of the multisig")]
+    InvalidMultisigSigner,
+    #[msg("Multisig signers must be unique and at most MAX_SIGNERS")]
+    InvalidMultisigSigners,
+    #[msg("Multisig threshold must be between 1 and the number of signers")]
+    InvalidMultisigThreshold,
+    #[msg("Proposal does not have enough approvals")]
+    InsufficientApprovals,
+    #[msg("Proposal was already executed")]
+    ProposalAlreadyExecuted,
+    #[msg("Proposal was created for a previous signer set")]
+    StaleProposal,
+    #[msg("Function request params are invalid or too long")]
+    InvalidFunctionRequestParams,
+    #[msg("Signed update is timestamped ahead of the cluster clock")]
+    FutureTimestamp,
+    #[msg("Function request was not opened by the function authority")]
+    UnauthorizedFunctionRequest,
 }
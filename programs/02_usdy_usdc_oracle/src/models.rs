@@ -225,6 +225,55 @@ pub struct MyProgramState {
     pub bump: u8,
     pub authority: Pubkey,
     pub switchboard_function: Pubkey,
+    /// Max divergence, in basis points, allowed between our traded price and any
+    /// reference feed passed to `refresh_oracles`. Zero disables the check.
+    pub max_reference_divergence_bps: u64,
+    /// Reference feeds older than this many seconds are ignored. Zero disables the check.
+    pub max_reference_staleness: i64,
+    /// Reject divergent updates instead of only flagging them with an event.
+    pub reject_on_divergence: bool,
+    /// Optional `MultisigState` that must approve admin changes. When set, the single-key
+    /// admin instructions are disabled in favor of propose/approve/execute.
+    pub multisig: Pubkey,
+    /// Ed25519 key of the off-chain publisher allowed to call `refresh_oracles_signed`, set with
+    /// `set_trusted_signer`. The default pubkey disables the signed update path.
+    pub trusted_signer: Pubkey,
+    /// Switchboard V2 aggregators every refresh must pass, in order, as remaining accounts
+    /// to be checked against. Only the first `num_reference_feeds` are set.
+    pub reference_feeds: [Pubkey; MAX_REFERENCE_FEEDS],
+    pub num_reference_feeds: u8,
+    /// The `ReferencePrice` each of `reference_feeds` is checked against.
+    pub reference_prices: [u8; MAX_REFERENCE_FEEDS],
+}
+
+impl MyProgramState {
+    pub fn apply_config(&mut self, params: &SetConfigParams) -> anchor_lang::Result<()> {
+        if params.reference_feeds.len() > MAX_REFERENCE_FEEDS {
+            return Err(error!(USDY_USDC_ORACLEError::InvalidReferenceFeed));
+        }
+        self.max_reference_divergence_bps = params.max_reference_divergence_bps;
+        self.max_reference_staleness = params.max_reference_staleness;
+        self.reject_on_divergence = params.reject_on_divergence;
+        self.reference_feeds = [Pubkey::default(); MAX_REFERENCE_FEEDS];
+        self.reference_prices = [0; MAX_REFERENCE_FEEDS];
+        for (i, reference) in params.reference_feeds.iter().enumerate() {
+            self.reference_feeds[i] = reference.feed;
+            self.reference_prices[i] = reference.price as u8;
+        }
+        self.num_reference_feeds = params.reference_feeds.len() as u8;
+
+        Ok(())
+    }
+
+    pub fn reference_feeds(&self) -> &[Pubkey] {
+        &self.reference_feeds[..self.num_reference_feeds as usize]
+    }
+
+    /// The price the `index`th reference feed is checked against.
+    pub fn reference_price(&self, index: usize) -> anchor_lang::Result<ReferencePrice> {
+        ReferencePrice::from_u8(self.reference_prices[index])
+            .ok_or(error!(USDY_USDC_ORACLEError::InvalidReferenceFeed))
+    }
 }
 
 #[repr(packed)]
@@ -233,13 +282,21 @@ pub struct OracleData {
     pub oracle_timestamp: i64,
     pub ondo_price: u64,
     pub traded_price: u64,
+    pub ondo_block: u64,
+    pub traded_block: u64,
 }
 
 #[derive(Copy, Clone, Default, AnchorSerialize, AnchorDeserialize)]
 pub struct OracleDataBorsh {
+    /// Timestamp of the oldest block the prices were read at.
     pub oracle_timestamp: i64,
     pub ondo_price: u64,
     pub traded_price: u64,
+    /// Block number `ondo_price` was read at, on the chain of the nav sources (a slot on Solana).
+    pub ondo_block: u64,
+    /// Block number `traded_price` was read at, on the chain of the market sources (a slot on
+    /// Solana).
+    pub traded_block: u64,
 }
 impl From<OracleDataBorsh> for OracleData {
     fn from(value: OracleDataBorsh) -> Self {
@@ -247,6 +304,8 @@ impl From<OracleDataBorsh> for OracleData {
             oracle_timestamp: value.oracle_timestamp,
             ondo_price: value.ondo_price,
             traded_price: value.traded_price,
+            ondo_block: value.ondo_block,
+            traded_block: value.traded_block,
         }
     }
 }
@@ -285,21 +344,25 @@ pub struct MyOracleState {
 impl MyOracleState {
     pub fn save_rows(&mut self, rows: &[OracleDataWithTradingSymbol]) -> anchor_lang::Result<()> {
         for row in rows.iter() {
-            match row.symbol {
-                TradingSymbol::Usdy_usdc => {
-                    self.usdy_usd = row.data.into();
-                    msg!("ondo_price: ${}", {self.usdy_usd.ondo_price});
-                    msg!("traded_price: ${}", {self.usdy_usd.traded_price});
-                }
-                _ => {
-                    msg!("no trading symbol found for {:?}", row.symbol);
-                    // TODO: emit an event so we can detect and fix
-                }
-            }
+            self.save_row(row);
         }
 
         Ok(())
     }
+
+    pub fn save_row(&mut self, row: &OracleDataWithTradingSymbol) {
+        match row.symbol {
+            TradingSymbol::Usdy_usdc => {
+                self.usdy_usd = row.data.into();
+                msg!("ondo_price: ${}", {self.usdy_usd.ondo_price});
+                msg!("traded_price: ${}", {self.usdy_usd.traded_price});
+            }
+            _ => {
+                msg!("no trading symbol found for {:?}", row.symbol);
+                // TODO: emit an event so we can detect and fix
+            }
+        }
+    }
 }
 
 #[repr(u8)]
@@ -616,6 +679,36 @@ impl AggregatorAccountData {
     }
 }
 
+impl AggregatorAccountData {
+    /// Records `price`, scaled by 9 decimals, as the latest confirmed round.
+    pub fn save_price(&mut self, price: u64, clock: &Clock) {
+        let mut result = AggregatorRound::default();
+        result.num_success = 1;
+        result.num_error = 0;
+        result.result = SwitchboardDecimal::from_f64(price as f64);
+        result.result.scale = 9;
+        result.round_open_timestamp = clock.unix_timestamp;
+        result.round_open_slot = clock.slot;
+        self.latest_confirmed_round = result;
+    }
+}
+
+/// Records the prices of `oracle` in whichever Ondo feed accounts were passed to a refresh.
+pub fn save_to_feeds(
+    ondo_price_feed: &Option<AccountLoader<AggregatorAccountData>>,
+    ondo_traded_feed: &Option<AccountLoader<AggregatorAccountData>>,
+    oracle: &OracleData,
+    clock: &Clock,
+) -> anchor_lang::Result<()> {
+    if let Some(feed) = ondo_price_feed {
+        feed.load_mut()?.save_price(oracle.ondo_price, clock);
+    }
+    if let Some(feed) = ondo_traded_feed {
+        feed.load_mut()?.save_price(oracle.traded_price, clock);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
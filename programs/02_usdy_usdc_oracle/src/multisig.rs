@@ -0,0 +1,250 @@
+use crate::*;
+
+pub const MULTISIG_SEED: &[u8] = b"USDY_MULTISIG_SEED";
+
+pub const PROPOSAL_SEED: &[u8] = b"USDY_PROPOSAL_SEED";
+
+pub const MAX_SIGNERS: usize = 10;
+
+#[account(zero_copy(unsafe))]
+pub struct MultisigState {
+    pub bump: u8,
+    pub threshold: u8,
+    pub num_signers: u8,
+    pub signers: [Pubkey; MAX_SIGNERS],
+    /// Incremented whenever the signer set changes so outstanding proposals can't
+    /// be executed against a different set of approvers.
+    pub nonce: u64,
+    pub proposal_count: u64,
+}
+
+impl MultisigState {
+    pub fn signer_index(&self, key: &Pubkey) -> Option<usize> {
+        self.signers[..self.num_signers as usize]
+            .iter()
+            .position(|signer| signer == key)
+    }
+
+    pub fn set_signers(&mut self, signers: &[Pubkey], threshold: u8) -> anchor_lang::Result<()> {
+        if signers.is_empty() || signers.len() > MAX_SIGNERS {
+            return Err(error!(USDY_USDC_ORACLEError::InvalidMultisigSigners));
+        }
+        for (i, signer) in signers.iter().enumerate() {
+            if signers[..i].contains(signer) {
+                return Err(error!(USDY_USDC_ORACLEError::InvalidMultisigSigners));
+            }
+        }
+        if threshold == 0 || threshold as usize > signers.len() {
+            return Err(error!(USDY_USDC_ORACLEError::InvalidMultisigThreshold));
+        }
+
+        self.signers = [Pubkey::default(); MAX_SIGNERS];
+        self.signers[..signers.len()].copy_from_slice(signers);
+        self.num_signers = signers.len() as u8;
+        self.threshold = threshold;
+        self.nonce += 1;
+
+        Ok(())
+    }
+}
+
+/// An admin change that requires `MultisigState.threshold` approvals before it is applied.
+#[derive(Clone, Debug, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub enum AdminAction {
+    SetFunction { switchboard_function: Pubkey },
+    SetAuthority { authority: Pubkey },
+    SetConfig { params: SetConfigParams },
+    SetSigners { signers: Vec<Pubkey>, threshold: u8 },
+    SetTrustedSigner { trusted_signer: Pubkey },
+}
+
+impl AdminAction {
+    /// Borsh size of the largest variant.
+    pub const MAX_SIZE: usize = 1 + 4 + 32 * MAX_SIGNERS + 1;
+}
+
+#[account]
+pub struct Proposal {
+    pub multisig: Pubkey,
+    pub index: u64,
+    pub nonce: u64,
+    pub proposer: Pubkey,
+    pub action: AdminAction,
+    /// Bitmap of approvals, indexed by the approver's position in `MultisigState.signers`.
+    pub approvals: u16,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl Proposal {
+    pub const SPACE: usize = 8 + 32 + 8 + 8 + 32 + AdminAction::MAX_SIZE + 2 + 1 + 1;
+
+    pub fn approve(&mut self, signer_index: usize) {
+        self.approvals |= 1 << signer_index;
+    }
+
+    pub fn num_approvals(&self) -> u8 {
+        self.approvals.count_ones() as u8
+    }
+
+    /// Applies the action to `program` and `multisig` once `executor` may execute it.
+    ///
+    /// `switchboard_function` is the function account passed with the instruction, required
+    /// for `SetFunction`. The Ondo feed accounts are left to the caller.
+    pub fn execute(
+        &mut self,
+        multisig: &mut MultisigState,
+        program: &mut MyProgramState,
+        executor: &Pubkey,
+        switchboard_function: Option<Pubkey>,
+    ) -> anchor_lang::Result<()> {
+        multisig
+            .signer_index(executor)
+            .ok_or(error!(USDY_USDC_ORACLEError::InvalidMultisigSigner))?;
+        if self.executed {
+            return Err(error!(USDY_USDC_ORACLEError::ProposalAlreadyExecuted));
+        }
+        if self.nonce != multisig.nonce {
+            return Err(error!(USDY_USDC_ORACLEError::StaleProposal));
+        }
+        if self.num_approvals() < multisig.threshold {
+            return Err(error!(USDY_USDC_ORACLEError::InsufficientApprovals));
+        }
+
+        match &self.action {
+            AdminAction::SetFunction {
+                switchboard_function: function,
+            } => {
+                if switchboard_function != Some(*function) {
+                    return Err(error!(USDY_USDC_ORACLEError::InvalidSwitchboardFunction));
+                }
+                program.switchboard_function = *function;
+            }
+            AdminAction::SetAuthority { authority } => {
+                program.authority = *authority;
+            }
+            AdminAction::SetConfig { params } => {
+                program.apply_config(params)?;
+            }
+            AdminAction::SetSigners { signers, threshold } => {
+                multisig.set_signers(signers, *threshold)?;
+            }
+            AdminAction::SetTrustedSigner { trusted_signer } => {
+                program.trusted_signer = *trusted_signer;
+            }
+        }
+        self.executed = true;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn multisig() -> MultisigState {
+        unsafe { std::mem::zeroed() }
+    }
+
+    fn propose(multisig: &mut MultisigState, proposer: &Pubkey, action: AdminAction) -> Proposal {
+        let mut proposal = Proposal {
+            multisig: Pubkey::default(),
+            index: multisig.proposal_count,
+            nonce: multisig.nonce,
+            proposer: *proposer,
+            action,
+            approvals: 0,
+            executed: false,
+            bump: 0,
+        };
+        proposal.approve(multisig.signer_index(proposer).unwrap());
+        multisig.proposal_count += 1;
+        proposal
+    }
+
+    #[test]
+    fn set_signers_validates_threshold() {
+        let mut state = multisig();
+        let signers = [Pubkey::new_unique(), Pubkey::new_unique()];
+        assert!(state.set_signers(&signers, 0).is_err());
+        assert!(state.set_signers(&signers, 3).is_err());
+        assert!(state.set_signers(&signers, 2).is_ok());
+        assert_eq!(state.signer_index(&signers[1]), Some(1));
+        assert_eq!(state.nonce, 1);
+    }
+
+    #[test]
+    fn set_signers_rejects_duplicates() {
+        let mut state = multisig();
+        let signer = Pubkey::new_unique();
+        assert!(state.set_signers(&[signer, signer], 1).is_err());
+    }
+
+    #[test]
+    fn actions_execute_after_the_function_changes() {
+        let signers = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut multisig = multisig();
+        multisig.set_signers(&signers, 2).unwrap();
+        let mut program: MyProgramState = bytemuck::Zeroable::zeroed();
+        program.switchboard_function = Pubkey::new_unique();
+
+        let function = Pubkey::new_unique();
+        let mut set_function = propose(
+            &mut multisig,
+            &signers[0],
+            AdminAction::SetFunction {
+                switchboard_function: function,
+            },
+        );
+        assert!(set_function
+            .execute(&mut multisig, &mut program, &signers[0], Some(function))
+            .is_err());
+        set_function.approve(multisig.signer_index(&signers[1]).unwrap());
+        // the function account passed must be the proposed one
+        assert!(set_function
+            .execute(&mut multisig, &mut program, &signers[2], Some(Pubkey::new_unique()))
+            .is_err());
+        set_function
+            .execute(&mut multisig, &mut program, &signers[2], Some(function))
+            .unwrap();
+        assert_eq!({ program.switchboard_function }, function);
+        assert!(set_function
+            .execute(&mut multisig, &mut program, &signers[2], Some(function))
+            .is_err());
+
+        // later actions don't depend on accounts derived from the previous function
+        let authority = Pubkey::new_unique();
+        let mut set_authority =
+            propose(&mut multisig, &signers[1], AdminAction::SetAuthority { authority });
+        set_authority.approve(multisig.signer_index(&signers[2]).unwrap());
+        set_authority
+            .execute(&mut multisig, &mut program, &signers[1], None)
+            .unwrap();
+        assert_eq!({ program.authority }, authority);
+
+        // changing the signers invalidates outstanding proposals
+        let mut set_signers = propose(
+            &mut multisig,
+            &signers[0],
+            AdminAction::SetSigners {
+                signers: signers[..2].to_vec(),
+                threshold: 1,
+            },
+        );
+        let mut outstanding = propose(&mut multisig, &signers[0], AdminAction::SetAuthority { authority });
+        set_signers.approve(multisig.signer_index(&signers[1]).unwrap());
+        outstanding.approve(multisig.signer_index(&signers[1]).unwrap());
+        set_signers
+            .execute(&mut multisig, &mut program, &signers[0], None)
+            .unwrap();
+        assert!(outstanding
+            .execute(&mut multisig, &mut program, &signers[0], None)
+            .is_err());
+        // and removed signers can't execute
+        let mut removed = propose(&mut multisig, &signers[0], AdminAction::SetAuthority { authority });
+        assert!(removed
+            .execute(&mut multisig, &mut program, &signers[2], None)
+            .is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::*;
+
+/// Upper bound on the encoded `FunctionRequestParams`, reserved as the request's
+/// `max_container_params_len`.
+pub const MAX_FUNCTION_REQUEST_PARAMS_LEN: usize = 512;
+
+/// Parameters passed to the Switchboard Function by `trigger_function_request`, so an
+/// operator can ask for a targeted refresh instead of the full scheduled job.
+///
+/// The Borsh enum tag is the schema version: new fields go in a new variant and the
+/// function keeps decoding the old ones. Empty container params (scheduled runs and
+/// `trigger_function`) decode as `FunctionRequestParams::default()`, the full job.
+#[derive(Clone, Debug, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub enum FunctionRequestParams {
+    V1(FunctionRequestParamsV1),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub struct FunctionRequestParamsV1 {
+    /// Symbols to refresh, every symbol when empty.
+    pub symbols: Vec<TradingSymbol>,
+    /// Push prices even when the oracle already holds data at least as new as the run read.
+    pub force: bool,
+    /// Changes to the function's configured sources for this run only.
+    pub source_overrides: Vec<SourceOverride>,
+    /// Fail the run if the oldest block it read is older than this many seconds. Zero
+    /// disables the check.
+    pub max_age_seconds: u64,
+}
+
+/// A change to one of the function's configured sources, by source name.
+#[derive(Clone, Debug, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub enum SourceOverride {
+    /// Leaves the source out of the run. Runs that can't meet quorum without it fail.
+    Disable { name: String },
+    /// Replaces the source's address, the same value as its `<NAME>_<FIELD>` override,
+    /// e.g. a pool pubkey or a ticker base url. The function only accepts addresses listed
+    /// for the source in its `allowed_addresses`.
+    Address { name: String, value: String },
+}
+
+impl Default for FunctionRequestParams {
+    fn default() -> Self {
+        FunctionRequestParams::V1(FunctionRequestParamsV1::default())
+    }
+}
+
+impl FunctionRequestParams {
+    /// Decodes the container params of a run, empty params being the full job.
+    pub fn decode(bytes: &[u8]) -> anchor_lang::Result<Self> {
+        if bytes.is_empty() {
+            return Ok(Self::default());
+        }
+        Self::try_from_slice(bytes)
+            .map_err(|_| error!(USDY_USDC_ORACLEError::InvalidFunctionRequestParams))
+    }
+
+    /// Encodes the params, checking they name known symbols and fit in a request.
+    pub fn encode(&self) -> anchor_lang::Result<Vec<u8>> {
+        let FunctionRequestParams::V1(params) = self;
+        if params.symbols.contains(&TradingSymbol::Unknown) {
+            return Err(error!(USDY_USDC_ORACLEError::InvalidSymbol));
+        }
+        let bytes = self.try_to_vec()?;
+        if bytes.len() > MAX_FUNCTION_REQUEST_PARAMS_LEN {
+            return Err(error!(USDY_USDC_ORACLEError::InvalidFunctionRequestParams));
+        }
+        Ok(bytes)
+    }
+
+    /// The latest schema, older versions are upgraded with their new fields defaulted.
+    pub fn latest(self) -> FunctionRequestParamsV1 {
+        match self {
+            FunctionRequestParams::V1(params) => params,
+        }
+    }
+}
+
+impl FunctionRequestParamsV1 {
+    pub fn includes(&self, symbol: TradingSymbol) -> bool {
+        self.symbols.is_empty() || self.symbols.contains(&symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targeted() -> FunctionRequestParams {
+        FunctionRequestParams::V1(FunctionRequestParamsV1 {
+            symbols: vec![TradingSymbol::Usdy_usdc],
+            force: true,
+            source_overrides: vec![
+                SourceOverride::Disable {
+                    name: "fusion".to_string(),
+                },
+                SourceOverride::Address {
+                    name: "agni".to_string(),
+                    value: "0x25780dc8Fc3cfBD75F33bFDAB65e969b603b2035".to_string(),
+                },
+            ],
+            max_age_seconds: 120,
+        })
+    }
+
+    #[test]
+    fn empty_params_are_the_full_job() {
+        let params = FunctionRequestParams::decode(&[]).unwrap().latest();
+        assert_eq!(params, FunctionRequestParamsV1::default());
+        assert!(params.includes(TradingSymbol::Usdy_usdc));
+    }
+
+    #[test]
+    fn params_round_trip() {
+        let bytes = targeted().encode().unwrap();
+        // the enum tag is the version byte
+        assert_eq!(bytes[0], 0);
+        assert_eq!(FunctionRequestParams::decode(&bytes).unwrap(), targeted());
+    }
+
+    #[test]
+    fn invalid_params_are_rejected() {
+        // unknown version
+        let mut bytes = targeted().encode().unwrap();
+        bytes[0] = 1;
+        assert!(FunctionRequestParams::decode(&bytes).is_err());
+
+        // trailing bytes
+        let mut bytes = targeted().encode().unwrap();
+        bytes.push(0);
+        assert!(FunctionRequestParams::decode(&bytes).is_err());
+
+        let unknown = FunctionRequestParams::V1(FunctionRequestParamsV1 {
+            symbols: vec![TradingSymbol::Unknown],
+            ..Default::default()
+        });
+        assert!(unknown.encode().is_err());
+
+        let oversized = FunctionRequestParams::V1(FunctionRequestParamsV1 {
+            source_overrides: vec![SourceOverride::Disable {
+                name: "x".repeat(MAX_FUNCTION_REQUEST_PARAMS_LEN),
+            }],
+            ..Default::default()
+        });
+        assert!(oversized.encode().is_err());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::*;
+
+/// Number of decimals used for the prices stored in `OracleData`.
+pub const PRICE_SCALE: u32 = 9;
+
+/// Max number of reference feeds `MyProgramState` can hold.
+pub const MAX_REFERENCE_FEEDS: usize = 4;
+
+/// Which price of an update a reference feed is checked against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+#[repr(u8)]
+pub enum ReferencePrice {
+    /// `traded_price`, e.g. a USDY/USD market feed.
+    Traded = 0,
+    /// `ondo_price`, the accruing NAV.
+    Nav = 1,
+    /// The USDC/USD price implied by pricing USDY at its NAV, `ondo_price / traded_price`,
+    /// e.g. a USDC/USD feed.
+    Usdc = 2,
+}
+
+impl ReferencePrice {
+    pub fn from_u8(value: u8) -> Option<ReferencePrice> {
+        match value {
+            0 => Some(ReferencePrice::Traded),
+            1 => Some(ReferencePrice::Nav),
+            2 => Some(ReferencePrice::Usdc),
+            _ => None,
+        }
+    }
+
+    /// The price of `oracle` this references, with `PRICE_SCALE` decimals.
+    pub fn price(&self, oracle: &OracleData) -> Option<u64> {
+        let (ondo_price, traded_price) = (oracle.ondo_price, oracle.traded_price);
+        match self {
+            ReferencePrice::Traded => Some(traded_price),
+            ReferencePrice::Nav => Some(ondo_price),
+            ReferencePrice::Usdc => {
+                if traded_price == 0 {
+                    return None;
+                }
+                let price = ondo_price as u128 * 10_u128.pow(PRICE_SCALE) / traded_price as u128;
+                u64::try_from(price).ok()
+            }
+        }
+    }
+}
+
+/// A reference feed and the price it's checked against.
+#[derive(Clone, Debug, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub struct ReferenceFeed {
+    pub feed: Pubkey,
+    pub price: ReferencePrice,
+}
+
+/// Emitted when an update disagrees with a reference feed by more than
+/// `MyProgramState.max_reference_divergence_bps`.
+#[event]
+pub struct ReferenceDivergenceEvent {
+    pub reference_feed: Pubkey,
+    /// Which price of the update `price` is.
+    pub reference: ReferencePrice,
+    pub price: u64,
+    pub reference_price: u64,
+    pub divergence_bps: u64,
+    pub timestamp: i64,
+}
+
+/// Returns the absolute difference between `price` and `reference`, in basis points of `reference`.
+pub fn divergence_bps(price: u64, reference: u64) -> Option<u64> {
+    if reference == 0 {
+        return None;
+    }
+    let diff = (price as i128 - reference as i128).unsigned_abs();
+    let bps = diff.checked_mul(10_000)? / reference as u128;
+    u64::try_from(bps).ok()
+}
+
+/// Checks the remaining accounts of a refresh are exactly the reference feeds configured in
+/// `state`, in order, so callers can't pick which feeds they are checked against.
+pub fn validate_reference_accounts(
+    state: &MyProgramState,
+    reference_feeds: &[Pubkey],
+) -> anchor_lang::Result<()> {
+    if reference_feeds != state.reference_feeds() {
+        return Err(error!(USDY_USDC_ORACLEError::InvalidReferenceFeed));
+    }
+    Ok(())
+}
+
+/// Cross-checks `oracle` against every reference feed configured in `state`, which must be
+/// passed as `reference_feeds`. Each feed is only checked against its `ReferencePrice`.
+///
+/// Only Switchboard V2 aggregators are supported as reference feeds, Pyth price accounts are
+/// rejected. Stale feeds are skipped. Divergent feeds either fail the instruction or emit a
+/// `ReferenceDivergenceEvent`, depending on `MyProgramState.reject_on_divergence`; when only
+/// flagging, feeds that can't be read are skipped too.
+pub fn check_reference_feeds(
+    state: &MyProgramState,
+    oracle: &OracleData,
+    reference_feeds: &[AccountInfo],
+    unix_timestamp: i64,
+) -> anchor_lang::Result<()> {
+    let keys: Vec<Pubkey> = reference_feeds.iter().map(|feed| feed.key()).collect();
+    validate_reference_accounts(state, &keys)?;
+    if state.max_reference_divergence_bps == 0 {
+        return Ok(());
+    }
+
+    for (index, feed) in reference_feeds.iter().enumerate() {
+        let reference = state.reference_price(index)?;
+        let price = reference
+            .price(oracle)
+            .ok_or(error!(USDY_USDC_ORACLEError::InvalidReferenceFeed))?;
+        let reference_price = match reference_price(state, feed, unix_timestamp) {
+            Ok(Some(reference_price)) => reference_price,
+            Ok(None) => {
+                msg!("skipping stale reference feed {}", feed.key());
+                continue;
+            }
+            Err(e) if !state.reject_on_divergence => {
+                msg!("skipping unreadable reference feed {}: {}", feed.key(), e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        check_divergence(
+            state,
+            feed.key(),
+            reference,
+            price,
+            reference_price,
+            unix_timestamp,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The latest result of a reference feed with `PRICE_SCALE` decimals, or `None` when it's stale.
+fn reference_price(
+    state: &MyProgramState,
+    feed: &AccountInfo,
+    unix_timestamp: i64,
+) -> anchor_lang::Result<Option<u64>> {
+    if *feed.owner != SWITCHBOARD_PROGRAM_ID {
+        return Err(error!(USDY_USDC_ORACLEError::InvalidReferenceFeed));
+    }
+    let data = feed.try_borrow_data()?;
+    let aggregator = models::AggregatorAccountData::new_from_bytes(&data)?;
+
+    if state.max_reference_staleness > 0
+        && aggregator
+            .check_staleness(unix_timestamp, state.max_reference_staleness)
+            .is_err()
+    {
+        return Ok(None);
+    }
+
+    let reference_price = aggregator.get_result()?.scale_to(PRICE_SCALE);
+    u64::try_from(reference_price)
+        .map(Some)
+        .map_err(|_| error!(USDY_USDC_ORACLEError::InvalidReferenceFeed))
+}
+
+/// Fails or flags `price` when it diverges from `reference_price` by more than the configured max.
+pub fn check_divergence(
+    state: &MyProgramState,
+    reference_feed: Pubkey,
+    reference: ReferencePrice,
+    price: u64,
+    reference_price: u64,
+    unix_timestamp: i64,
+) -> anchor_lang::Result<()> {
+    let divergence = divergence_bps(price, reference_price)
+        .ok_or(error!(USDY_USDC_ORACLEError::InvalidReferenceFeed))?;
+    if divergence <= state.max_reference_divergence_bps {
+        return Ok(());
+    }
+
+    msg!(
+        "reference feed {} diverges by {} bps (${} vs ${})",
+        reference_feed,
+        divergence,
+        price,
+        reference_price
+    );
+    if state.reject_on_divergence {
+        return Err(error!(USDY_USDC_ORACLEError::ReferenceDivergenceExceeded));
+    }
+    emit!(ReferenceDivergenceEvent {
+        reference_feed,
+        reference,
+        price,
+        reference_price,
+        divergence_bps: divergence,
+        timestamp: unix_timestamp,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traded(feeds: &[Pubkey]) -> Vec<ReferenceFeed> {
+        feeds
+            .iter()
+            .map(|feed| ReferenceFeed {
+                feed: *feed,
+                price: ReferencePrice::Traded,
+            })
+            .collect()
+    }
+
+    fn configured(reference_feeds: &[Pubkey], reject_on_divergence: bool) -> MyProgramState {
+        let mut state: MyProgramState = bytemuck::Zeroable::zeroed();
+        state
+            .apply_config(&SetConfigParams {
+                max_reference_divergence_bps: 100,
+                max_reference_staleness: 0,
+                reject_on_divergence,
+                reference_feeds: traded(reference_feeds),
+            })
+            .unwrap();
+        state
+    }
+
+    #[test]
+    fn divergence_is_symmetric_in_sign() {
+        assert_eq!(divergence_bps(1_010_000_000, 1_000_000_000), Some(100));
+        assert_eq!(divergence_bps(990_000_000, 1_000_000_000), Some(100));
+        assert_eq!(divergence_bps(1_000_000_000, 1_000_000_000), Some(0));
+    }
+
+    #[test]
+    fn divergence_against_zero_reference_is_none() {
+        assert_eq!(divergence_bps(1_000_000_000, 0), None);
+    }
+
+    #[test]
+    fn configured_reference_feeds_must_be_passed_exactly() {
+        let feeds = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let state = configured(&feeds, true);
+        assert!(validate_reference_accounts(&state, &feeds).is_ok());
+        // omitted, partial, reordered or substituted feeds are all rejected
+        assert!(validate_reference_accounts(&state, &[]).is_err());
+        assert!(validate_reference_accounts(&state, &feeds[..1]).is_err());
+        assert!(validate_reference_accounts(&state, &[feeds[1], feeds[0]]).is_err());
+        assert!(validate_reference_accounts(&state, &[feeds[0], Pubkey::new_unique()]).is_err());
+
+        // without configured feeds, none may be passed
+        let state = configured(&[], true);
+        assert!(validate_reference_accounts(&state, &[]).is_ok());
+        assert!(validate_reference_accounts(&state, &feeds[..1]).is_err());
+    }
+
+    #[test]
+    fn divergent_prices_are_rejected_or_flagged() {
+        let feed = Pubkey::new_unique();
+        let rejecting = configured(&[feed], true);
+        let nav = ReferencePrice::Nav;
+        assert!(check_divergence(&rejecting, feed, nav, 1_010_000_000, 1_000_000_000, 0).is_ok());
+        assert!(check_divergence(&rejecting, feed, nav, 1_020_000_000, 1_000_000_000, 0).is_err());
+
+        let flagging = configured(&[feed], false);
+        let traded = ReferencePrice::Traded;
+        assert!(check_divergence(&flagging, feed, traded, 1_020_000_000, 1_000_000_000, 0).is_ok());
+    }
+
+    #[test]
+    fn feeds_are_checked_against_their_own_price() {
+        let (usdc_feed, usdy_feed) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut state: MyProgramState = bytemuck::Zeroable::zeroed();
+        state
+            .apply_config(&SetConfigParams {
+                max_reference_divergence_bps: 100,
+                max_reference_staleness: 0,
+                reject_on_divergence: true,
+                reference_feeds: vec![
+                    ReferenceFeed {
+                        feed: usdc_feed,
+                        price: ReferencePrice::Usdc,
+                    },
+                    ReferenceFeed {
+                        feed: usdy_feed,
+                        price: ReferencePrice::Traded,
+                    },
+                ],
+            })
+            .unwrap();
+        assert_eq!(state.reference_feeds(), &[usdc_feed, usdy_feed]);
+        assert_eq!(state.reference_price(0).unwrap(), ReferencePrice::Usdc);
+        assert_eq!(state.reference_price(1).unwrap(), ReferencePrice::Traded);
+
+        // USDY at a NAV of $1.05 trading at 1.0496 USDC implies USDC at $1.0004
+        let oracle = OracleData {
+            oracle_timestamp: 0,
+            ondo_price: 1_050_000_000,
+            traded_price: 1_049_600_000,
+            ondo_block: 0,
+            traded_block: 0,
+        };
+        let usdc_price = ReferencePrice::Usdc.price(&oracle).unwrap();
+        assert_eq!(usdc_price, 1_000_381_097);
+        // a USDC/USD feed at $1 agrees with the implied USDC price, not with the NAV
+        let usdc_reference = 1_000_000_000;
+        let usdc = ReferencePrice::Usdc;
+        assert!(check_divergence(&state, usdc_feed, usdc, usdc_price, usdc_reference, 0).is_ok());
+        let nav = ReferencePrice::Nav;
+        let nav_price = nav.price(&oracle).unwrap();
+        assert!(check_divergence(&state, usdc_feed, nav, nav_price, usdc_reference, 0).is_err());
+
+        assert_eq!(ReferencePrice::from_u8(3), None);
+        let untraded = OracleData {
+            traded_price: 0,
+            ..oracle
+        };
+        assert_eq!(ReferencePrice::Usdc.price(&untraded), None);
+    }
+
+    #[test]
+    fn too_many_reference_feeds_are_rejected() {
+        let mut state: MyProgramState = bytemuck::Zeroable::zeroed();
+        let feeds: Vec<Pubkey> = (0..=MAX_REFERENCE_FEEDS).map(|_| Pubkey::new_unique()).collect();
+        let params = SetConfigParams {
+            max_reference_divergence_bps: 100,
+            max_reference_staleness: 0,
+            reject_on_divergence: true,
+            reference_feeds: traded(&feeds),
+        };
+        assert!(state.apply_config(&params).is_err());
+
+        let params = SetConfigParams {
+            reference_feeds: traded(&feeds[..MAX_REFERENCE_FEEDS]),
+            ..params
+        };
+        state.apply_config(&params).unwrap();
+        assert_eq!(state.reference_feeds(), &feeds[..MAX_REFERENCE_FEEDS]);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::*;
+
+/// Checks `enclave_signer` signed for a run of `switchboard_function`. Scheduled runs sign
+/// with the enclave signer stored on the function. Runs started by `trigger_function_request`
+/// sign with the one the attestation program stored on their `function_request`.
+pub fn validate_enclave_signer<'info>(
+    switchboard_function: &AccountLoader<'info, FunctionAccountData>,
+    enclave_signer: &AccountInfo<'info>,
+    function_request: Option<&FunctionRequestAccountData>,
+) -> anchor_lang::Result<bool> {
+    match function_request {
+        Some(request) => request.validate_signer(switchboard_function, enclave_signer),
+        None => switchboard_function.load()?.validate(enclave_signer),
+    }
+}
+
+/// Request params can narrow down and override the sources of a run, so only requests opened
+/// by the function authority, as `trigger_function_request` does, may refresh the oracle.
+/// Anyone can open a request against the function with the attestation program directly.
+pub fn is_authorized_request(
+    switchboard_function: &FunctionAccountData,
+    function_request: &FunctionRequestAccountData,
+) -> bool {
+    function_request.authority == switchboard_function.authority
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_runs_are_validated_against_the_request() {
+        let (authority, queue, signer) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut function = FunctionAccountData::default();
+        function.authority = authority;
+        function.attestation_queue = queue;
+        let function_key = Pubkey::new_unique();
+        let owner = FunctionAccountData::owner();
+        let mut lamports = 0;
+        let mut data = [&FunctionAccountData::DISCRIMINATOR[..], bytemuck::bytes_of(&function)].concat();
+        let function_info = AccountInfo::new(
+            &function_key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        let function_loader = AccountLoader::<FunctionAccountData>::try_from(&function_info).unwrap();
+
+        let mut request = FunctionRequestAccountData {
+            authority,
+            function: function_key,
+            attestation_queue: queue,
+            ..Default::default()
+        };
+        request.active_request.enclave_signer = signer;
+
+        let system = Pubkey::default();
+        let (mut signer_lamports, mut signer_data) = (0, vec![]);
+        let signer_info = AccountInfo::new(
+            &signer,
+            true,
+            false,
+            &mut signer_lamports,
+            &mut signer_data,
+            &system,
+            false,
+            0,
+        );
+        assert!(validate_enclave_signer(&function_loader, &signer_info, Some(&request)).unwrap());
+        assert!(is_authorized_request(&function, &request));
+        // the function holds no enclave signer for request runs
+        assert!(!validate_enclave_signer(&function_loader, &signer_info, None).unwrap());
+
+        // requests opened by anyone else are refused
+        let mut foreign = request.clone();
+        foreign.authority = Pubkey::new_unique();
+        assert!(!is_authorized_request(&function, &foreign));
+
+        // as are requests for another function or signed by another enclave
+        let mut other_function = request.clone();
+        other_function.function = Pubkey::new_unique();
+        assert!(!validate_enclave_signer(&function_loader, &signer_info, Some(&other_function)).unwrap());
+        let mut other_signer = request.clone();
+        other_signer.active_request.enclave_signer = Pubkey::new_unique();
+        assert!(!validate_enclave_signer(&function_loader, &signer_info, Some(&other_signer)).unwrap());
+
+        let (mut unsigned_lamports, mut unsigned_data) = (0, vec![]);
+        let unsigned_info = AccountInfo::new(
+            &signer,
+            false,
+            false,
+            &mut unsigned_lamports,
+            &mut unsigned_data,
+            &system,
+            false,
+            0,
+        );
+        assert!(!validate_enclave_signer(&function_loader, &unsigned_info, Some(&request)).unwrap());
+    }
+}
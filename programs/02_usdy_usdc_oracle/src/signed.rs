@@ -0,0 +1,185 @@
+use crate::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+
+/// Signed updates older than this many seconds are rejected.
+pub const MAX_SIGNED_UPDATE_AGE: i64 = 120;
+
+/// Signed updates may be ahead of the cluster clock by this many seconds, for clock drift
+/// between the publisher and the validators.
+pub const MAX_SIGNED_UPDATE_SKEW: i64 = 5;
+
+/// Prefixes every signed message, so a signature over our params can't be used for anything else.
+pub const SIGNED_UPDATE_DOMAIN: &[u8] = b"USDY_USDC_ORACLE_SIGNED_UPDATE_V1";
+
+/// The message the trusted signer signs: the domain, this program's id and the oracle account
+/// the update is for, followed by the borsh encoded `RefreshOraclesParams`.
+pub fn signed_update_message(oracle: &Pubkey, params: &[u8]) -> Vec<u8> {
+    [SIGNED_UPDATE_DOMAIN, crate::ID.as_ref(), oracle.as_ref(), params].concat()
+}
+
+/// Checks a signed row is newer than the row the oracle holds, at most `MAX_SIGNED_UPDATE_AGE`
+/// old and at most `MAX_SIGNED_UPDATE_SKEW` ahead of the cluster clock.
+pub fn check_signed_timestamp(
+    oracle_timestamp: i64,
+    latest_timestamp: i64,
+    unix_timestamp: i64,
+) -> anchor_lang::Result<()> {
+    // reject replays of older signed payloads and anything too old to be useful
+    if oracle_timestamp <= latest_timestamp
+        || unix_timestamp - oracle_timestamp > MAX_SIGNED_UPDATE_AGE
+    {
+        return Err(error!(USDY_USDC_ORACLEError::StaleData));
+    }
+    // a future timestamp would block every honest update until the clock catches up
+    if oracle_timestamp > unix_timestamp + MAX_SIGNED_UPDATE_SKEW {
+        return Err(error!(USDY_USDC_ORACLEError::FutureTimestamp));
+    }
+    Ok(())
+}
+
+// Layout of the Ed25519 precompile instruction data. See
+// https://docs.solana.com/developing/runtime-facilities/programs#ed25519-program
+const SIGNATURE_OFFSETS_START: usize = 2;
+const SIGNATURE_OFFSETS_SIZE: usize = 14;
+const PUBKEY_SIZE: usize = 32;
+const SIGNATURE_SIZE: usize = 64;
+
+/// Verifies that the instruction preceding the current one is an Ed25519 precompile
+/// instruction carrying a single signature by `signer` over `message`.
+///
+/// The precompile has already checked the signature by the time our program runs, so
+/// we only need to confirm it was over the expected key and bytes.
+pub fn verify_ed25519_instruction(
+    instructions_sysvar: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> anchor_lang::Result<()> {
+    let current_index = load_current_index_checked(instructions_sysvar)? as usize;
+    if current_index == 0 {
+        return Err(error!(USDY_USDC_ORACLEError::InvalidEd25519Instruction));
+    }
+    let ix = load_instruction_at_checked(current_index - 1, instructions_sysvar)?;
+    if ix.program_id != ed25519_program::ID || !ix.accounts.is_empty() {
+        return Err(error!(USDY_USDC_ORACLEError::InvalidEd25519Instruction));
+    }
+
+    verify_ed25519_data(&ix.data, signer, message)
+}
+
+/// Checks the raw Ed25519 precompile instruction data references `signer` and `message`.
+pub fn verify_ed25519_data(
+    data: &[u8],
+    signer: &Pubkey,
+    message: &[u8],
+) -> anchor_lang::Result<()> {
+    if data.len() < SIGNATURE_OFFSETS_START + SIGNATURE_OFFSETS_SIZE || data[0] != 1 {
+        return Err(error!(USDY_USDC_ORACLEError::InvalidEd25519Instruction));
+    }
+    let offsets = &data[SIGNATURE_OFFSETS_START..SIGNATURE_OFFSETS_START + SIGNATURE_OFFSETS_SIZE];
+    let read_u16 = |i: usize| u16::from_le_bytes([offsets[i], offsets[i + 1]]);
+
+    let signature_offset = read_u16(0) as usize;
+    let signature_ix_index = read_u16(2);
+    let pubkey_offset = read_u16(4) as usize;
+    let pubkey_ix_index = read_u16(6);
+    let message_offset = read_u16(8) as usize;
+    let message_size = read_u16(10) as usize;
+    let message_ix_index = read_u16(12);
+
+    // everything must live inside the precompile instruction itself
+    if signature_ix_index != u16::MAX || pubkey_ix_index != u16::MAX || message_ix_index != u16::MAX
+    {
+        return Err(error!(USDY_USDC_ORACLEError::InvalidEd25519Instruction));
+    }
+    if data.len() < signature_offset + SIGNATURE_SIZE {
+        return Err(error!(USDY_USDC_ORACLEError::InvalidEd25519Instruction));
+    }
+
+    let pubkey = data
+        .get(pubkey_offset..pubkey_offset + PUBKEY_SIZE)
+        .ok_or(error!(USDY_USDC_ORACLEError::InvalidEd25519Instruction))?;
+    if pubkey != signer.as_ref() {
+        return Err(error!(USDY_USDC_ORACLEError::InvalidTrustedSigner));
+    }
+
+    let signed_message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(error!(USDY_USDC_ORACLEError::InvalidEd25519Instruction))?;
+    if signed_message != message {
+        return Err(error!(USDY_USDC_ORACLEError::InvalidEd25519Instruction));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mirrors the layout produced by `new_ed25519_instruction` in solana-sdk.
+    fn ed25519_data(signer: &Pubkey, message: &[u8]) -> Vec<u8> {
+        let data_start = SIGNATURE_OFFSETS_START + SIGNATURE_OFFSETS_SIZE;
+        let pubkey_offset = data_start;
+        let signature_offset = pubkey_offset + PUBKEY_SIZE;
+        let message_offset = signature_offset + SIGNATURE_SIZE;
+
+        let mut data = vec![1u8, 0u8];
+        for value in [
+            signature_offset as u16,
+            u16::MAX,
+            pubkey_offset as u16,
+            u16::MAX,
+            message_offset as u16,
+            message.len() as u16,
+            u16::MAX,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(&[0u8; SIGNATURE_SIZE]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    #[test]
+    fn accepts_matching_signer_and_message() {
+        let signer = Pubkey::new_unique();
+        let data = ed25519_data(&signer, b"rows");
+        assert!(verify_ed25519_data(&data, &signer, b"rows").is_ok());
+    }
+
+    #[test]
+    fn rejects_other_signer_or_message() {
+        let signer = Pubkey::new_unique();
+        let data = ed25519_data(&signer, b"rows");
+        assert!(verify_ed25519_data(&data, &Pubkey::new_unique(), b"rows").is_err());
+        assert!(verify_ed25519_data(&data, &signer, b"other").is_err());
+    }
+
+    #[test]
+    fn messages_are_bound_to_the_program_and_oracle() {
+        let oracle = Pubkey::new_unique();
+        let message = signed_update_message(&oracle, b"rows");
+        assert!(message.starts_with(SIGNED_UPDATE_DOMAIN));
+        assert!(message.ends_with(b"rows"));
+        assert_ne!(message, signed_update_message(&Pubkey::new_unique(), b"rows"));
+
+        let signer = Pubkey::new_unique();
+        let data = ed25519_data(&signer, b"rows");
+        assert!(verify_ed25519_data(&data, &signer, &message).is_err());
+    }
+
+    #[test]
+    fn signed_timestamps_must_be_recent() {
+        let now = 1_700_000_000;
+        assert!(check_signed_timestamp(now, now - 60, now).is_ok());
+        assert!(check_signed_timestamp(now + MAX_SIGNED_UPDATE_SKEW, now, now).is_ok());
+        // replays, stale and future rows are rejected
+        assert!(check_signed_timestamp(now - 60, now - 60, now).is_err());
+        assert!(check_signed_timestamp(now - MAX_SIGNED_UPDATE_AGE - 1, 0, now).is_err());
+        assert!(check_signed_timestamp(now + MAX_SIGNED_UPDATE_SKEW + 1, now, now).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::*;
+
+pub const SYMBOL_TABLE_SEED: &[u8] = b"USDY_SYMBOL_TABLE_SEED";
+
+/// Header of the symbol table account. The header is followed by `capacity` `OracleData`
+/// entries, where entry `n` holds the latest data for the `TradingSymbol` with id `n`.
+///
+/// The authority grows the table with `grow_symbol_table` as symbols are added.
+#[account(zero_copy(unsafe))]
+#[repr(packed)]
+pub struct SymbolTable {
+    pub bump: u8,
+    pub capacity: u32,
+}
+
+impl SymbolTable {
+    /// Size of the discriminator and header preceding the entries.
+    pub const HEADER_SIZE: usize = 8 + std::mem::size_of::<SymbolTable>();
+
+    /// Account size required to hold `capacity` entries.
+    pub fn space(capacity: u32) -> usize {
+        Self::HEADER_SIZE + capacity as usize * std::mem::size_of::<OracleData>()
+    }
+
+    fn capacity_of(data: &[u8]) -> anchor_lang::Result<u32> {
+        if data.len() < Self::HEADER_SIZE {
+            return Err(ErrorCode::AccountDidNotDeserialize.into());
+        }
+        let header: &SymbolTable = bytemuck::from_bytes(&data[8..Self::HEADER_SIZE]);
+        Ok(header.capacity)
+    }
+
+    /// Returns the entries stored after the header of a symbol table account's data.
+    pub fn entries(data: &[u8]) -> anchor_lang::Result<&[OracleData]> {
+        let end = Self::space(Self::capacity_of(data)?);
+        let entries = data
+            .get(Self::HEADER_SIZE..end)
+            .ok_or(error!(USDY_USDC_ORACLEError::ArrayOverflow))?;
+        Ok(bytemuck::cast_slice(entries))
+    }
+
+    pub fn entries_mut(data: &mut [u8]) -> anchor_lang::Result<&mut [OracleData]> {
+        let end = Self::space(Self::capacity_of(data)?);
+        let entries = data
+            .get_mut(Self::HEADER_SIZE..end)
+            .ok_or(error!(USDY_USDC_ORACLEError::ArrayOverflow))?;
+        Ok(bytemuck::cast_slice_mut(entries))
+    }
+
+    /// Returns the latest data for `symbol`, if the table is large enough to hold it.
+    pub fn get(data: &[u8], symbol: TradingSymbol) -> anchor_lang::Result<Option<OracleData>> {
+        let entries = Self::entries(data)?;
+        Ok(entries.get(u8::from(symbol) as usize).copied())
+    }
+
+    /// Writes `row` into the entry for its symbol, failing if the table needs to grow first.
+    pub fn save_row(data: &mut [u8], row: &OracleDataWithTradingSymbol) -> anchor_lang::Result<()> {
+        let entries = Self::entries_mut(data)?;
+        let entry = entries
+            .get_mut(u8::from(row.symbol) as usize)
+            .ok_or(error!(USDY_USDC_ORACLEError::ArrayOverflow))?;
+        *entry = row.data.into();
+        Ok(())
+    }
+}
+
+/// Writes `rows` into the optional symbol table passed to a refresh instruction.
+pub fn save_to_symbol_table(
+    symbol_table: &Option<AccountLoader<SymbolTable>>,
+    rows: impl Iterator<Item = OracleDataWithTradingSymbol>,
+) -> anchor_lang::Result<()> {
+    if let Some(symbol_table) = symbol_table {
+        let info: &AccountInfo = symbol_table.as_ref();
+        let mut data = info.try_borrow_mut_data()?;
+        for row in rows {
+            SymbolTable::save_row(&mut data, &row)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(capacity: u32) -> Vec<u8> {
+        let mut data = vec![0u8; SymbolTable::space(capacity)];
+        data[9..13].copy_from_slice(&capacity.to_le_bytes());
+        data
+    }
+
+    fn row(symbol: TradingSymbol, price: u64) -> OracleDataWithTradingSymbol {
+        OracleDataWithTradingSymbol {
+            symbol,
+            data: OracleDataBorsh {
+                oracle_timestamp: 1,
+                ondo_price: price,
+                traded_price: price,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn symbol_table_saves_rows_by_symbol_id() {
+        let mut data = table(2);
+        SymbolTable::save_row(&mut data, &row(TradingSymbol::Usdy_usdc, 42)).unwrap();
+
+        let entry = SymbolTable::get(&data, TradingSymbol::Usdy_usdc).unwrap().unwrap();
+        assert_eq!({ entry.ondo_price }, 42);
+        let empty = SymbolTable::get(&data, TradingSymbol::Unknown).unwrap().unwrap();
+        assert_eq!({ empty.ondo_price }, 0);
+    }
+
+    #[test]
+    fn symbol_table_rejects_symbols_past_capacity() {
+        let mut data = table(1);
+        assert!(SymbolTable::save_row(&mut data, &row(TradingSymbol::Usdy_usdc, 42)).is_err());
+        assert!(SymbolTable::get(&data, TradingSymbol::Usdy_usdc).unwrap().is_none());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::*;
+use serde::Deserialize;
+
+/// How the quotes of several sources are combined into a single price.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum AggregationStrategy {
+    /// Mean of all quotes, weighted by `Quote.weight`.
+    WeightedMean,
+    /// Median of all quotes.
+    Median,
+    /// Price at which half of the total `Quote.weight` lies on either side.
+    WeightedMedian,
+    /// Mean after dropping `trim_percent` of the quotes from each end.
+    TrimmedMean { trim_percent: u32 },
+    /// Rejects quotes further than `max_deviations` median absolute deviations from the
+    /// median, then takes the weighted mean of the rest.
+    ///
+    /// The deviation is floored at one basis point of the median so a few identical
+    /// quotes don't cause every other quote to be rejected.
+    MadFilter { max_deviations: f64 },
+}
+
+impl Default for AggregationStrategy {
+    fn default() -> Self {
+        AggregationStrategy::WeightedMedian
+    }
+}
+
+impl AggregationStrategy {
+    pub fn validate(&self) -> Result<(), SbError> {
+        match self {
+            AggregationStrategy::TrimmedMean { trim_percent } if *trim_percent >= 50 => Err(
+                SbError::CustomMessage("trim_percent must be less than 50".to_string()),
+            ),
+            AggregationStrategy::MadFilter { max_deviations }
+                if !max_deviations.is_finite() || *max_deviations <= 0.0 =>
+            {
+                Err(SbError::CustomMessage(
+                    "max_deviations must be a positive number".to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Combines the `(source name, quote)` pairs into a single price.
+    pub fn aggregate(&self, quotes: &[(String, Quote)]) -> Result<Aggregate, SbError> {
+        if quotes.is_empty() {
+            return Err(SbError::CustomMessage("nothing to aggregate".to_string()));
+        }
+        let prices: Vec<Decimal> = quotes.iter().map(|(_, quote)| quote.price).collect();
+
+        let (price, rejected) = match self {
+            AggregationStrategy::WeightedMean => (weighted_mean(&quote_weights(quotes)?)?, vec![]),
+            AggregationStrategy::Median => (median(&prices), vec![]),
+            AggregationStrategy::WeightedMedian => (weighted_median(&quote_weights(quotes)?)?, vec![]),
+            AggregationStrategy::TrimmedMean { trim_percent } => {
+                let mut order: Vec<usize> = (0..prices.len()).collect();
+                order.sort_by_key(|i| prices[*i]);
+                let trim = prices.len() * *trim_percent as usize / 100;
+                let kept: Vec<(Decimal, Decimal)> = order[trim..order.len() - trim]
+                    .iter()
+                    .map(|i| (prices[*i], Decimal::ONE))
+                    .collect();
+                let mut rejected: Vec<usize> = order[..trim].to_vec();
+                rejected.extend_from_slice(&order[order.len() - trim..]);
+                rejected.sort();
+                (weighted_mean(&kept)?, rejected)
+            }
+            AggregationStrategy::MadFilter { max_deviations } => {
+                let max_deviations = Decimal::from_f64(*max_deviations).ok_or(
+                    SbError::CustomMessage("max_deviations overflows Decimal".to_string()),
+                )?;
+                let values = quote_weights(quotes)?;
+                let center = median(&prices);
+                let deviations: Vec<Decimal> =
+                    prices.iter().map(|price| (price - center).abs()).collect();
+                let spread = median(&deviations).max(center.abs() / Decimal::from(10_000));
+
+                let mut kept = vec![];
+                let mut rejected = vec![];
+                for (i, deviation) in deviations.iter().enumerate() {
+                    if *deviation <= spread * max_deviations {
+                        kept.push(values[i]);
+                    } else {
+                        rejected.push(i);
+                    }
+                }
+                (weighted_mean(&kept)?, rejected)
+            }
+        };
+
+        Ok(Aggregate {
+            price,
+            strategy: self.clone(),
+            rejected: rejected.into_iter().map(|i| quotes[i].0.clone()).collect(),
+        })
+    }
+}
+
+/// The result of aggregating a set of quotes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregate {
+    pub price: Decimal,
+    pub strategy: AggregationStrategy,
+    /// Names of the sources whose quotes were left out of `price`.
+    pub rejected: Vec<String>,
+}
+
+/// Mean of `(value, weight)` pairs, weighted by `weight`.
+pub fn weighted_mean(values: &[(Decimal, Decimal)]) -> Result<Decimal, SbError> {
+    let total_weight: Decimal = values.iter().map(|(_, weight)| *weight).sum();
+    if values.is_empty() || total_weight <= Decimal::ZERO {
+        return Err(SbError::CustomMessage("nothing to aggregate".to_string()));
+    }
+    let weighted_sum: Decimal = values.iter().map(|(value, weight)| value * weight).sum();
+    Ok(weighted_sum / total_weight)
+}
+
+/// Median of `values`, averaging the middle two for an even count. Zero when empty.
+pub fn median(values: &[Decimal]) -> Decimal {
+    if values.is_empty() {
+        return Decimal::ZERO;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort();
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / Decimal::TWO
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Weighted median of `(value, weight)` pairs. When the weight splits exactly in half
+/// between two values, their midpoint is returned.
+pub fn weighted_median(values: &[(Decimal, Decimal)]) -> Result<Decimal, SbError> {
+    let total_weight: Decimal = values.iter().map(|(_, weight)| *weight).sum();
+    if values.is_empty() || total_weight <= Decimal::ZERO {
+        return Err(SbError::CustomMessage("nothing to aggregate".to_string()));
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by_key(|(value, _)| *value);
+
+    let mut cumulative = Decimal::ZERO;
+    for (i, (value, weight)) in sorted.iter().enumerate() {
+        cumulative += weight;
+        if cumulative * Decimal::TWO == total_weight && i + 1 < sorted.len() {
+            return Ok((value + sorted[i + 1].0) / Decimal::TWO);
+        }
+        if cumulative * Decimal::TWO >= total_weight {
+            return Ok(*value);
+        }
+    }
+    Ok(sorted[sorted.len() - 1].0)
+}
+
+/// Weights each quote by `Quote.weight`, or equally if no quote has a weight.
+///
+/// A set where only some quotes have a weight is rejected: a pool's depth in USDC and an
+/// equal share can't be compared, and weighting everything equally would let the
+/// shallowest pool count as much as the deepest. Unweighted sources can be given a `weight`
+/// in the config.
+pub fn quote_weights(quotes: &[(String, Quote)]) -> Result<Vec<(Decimal, Decimal)>, SbError> {
+    let unweighted: Vec<&str> = quotes
+        .iter()
+        .filter(|(_, quote)| quote.weight.is_none())
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if !unweighted.is_empty() && unweighted.len() < quotes.len() {
+        return Err(SbError::CustomMessage(format!(
+            "{:?} have no weight while other quotes do, set their weight or use an unweighted strategy",
+            unweighted
+        )));
+    }
+    Ok(quotes
+        .iter()
+        .map(|(_, quote)| (quote.price, quote.weight.unwrap_or(Decimal::ONE)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn named(prices: &[(&str, &str)]) -> Vec<(String, Quote)> {
+        prices
+            .iter()
+            .map(|(name, price)| (name.to_string(), Quote::new(dec(price))))
+            .collect()
+    }
+
+    #[test]
+    fn weighted_mean_favors_heavier_values() {
+        let values = [(dec("1.00"), dec("3")), (dec("1.04"), dec("1"))];
+        assert_eq!(weighted_mean(&values).unwrap(), dec("1.01"));
+    }
+
+    #[test]
+    fn weighted_mean_of_nothing_fails() {
+        assert!(weighted_mean(&[]).is_err());
+        assert!(weighted_mean(&[(dec("1"), Decimal::ZERO)]).is_err());
+    }
+
+    #[test]
+    fn unweighted_quotes_are_weighted_equally() {
+        let quotes = named(&[("a", "1.00"), ("b", "1.04")]);
+        assert_eq!(weighted_mean(&quote_weights(&quotes).unwrap()).unwrap(), dec("1.02"));
+    }
+
+    #[test]
+    fn mixed_weights_are_rejected() {
+        let mut weighted = Quote::new(dec("1.00"));
+        weighted.weight = Some(dec("100"));
+        let quotes = [("a".to_string(), weighted), ("b".to_string(), Quote::new(dec("1.04")))];
+        assert!(quote_weights(&quotes).is_err());
+        assert!(AggregationStrategy::WeightedMedian.aggregate(&quotes).is_err());
+        // strategies that ignore weights still work
+        assert_eq!(AggregationStrategy::Median.aggregate(&quotes).unwrap().price, dec("1.02"));
+    }
+
+    #[test]
+    fn medians() {
+        assert_eq!(median(&[dec("3"), dec("1"), dec("2")]), dec("2"));
+        assert_eq!(median(&[dec("4"), dec("1"), dec("2"), dec("3")]), dec("2.5"));
+
+        let values = [(dec("1.00"), dec("1")), (dec("1.01"), dec("1")), (dec("1.50"), dec("3"))];
+        assert_eq!(weighted_median(&values).unwrap(), dec("1.50"));
+        let values = [(dec("1.00"), dec("1")), (dec("1.02"), dec("1"))];
+        assert_eq!(weighted_median(&values).unwrap(), dec("1.01"));
+    }
+
+    #[test]
+    fn trimmed_mean_rejects_the_extremes() {
+        let quotes = named(&[("a", "1.00"), ("b", "1.10"), ("c", "1.02"), ("d", "0.50"), ("e", "1.04")]);
+        let aggregate = AggregationStrategy::TrimmedMean { trim_percent: 20 }
+            .aggregate(&quotes)
+            .unwrap();
+        assert_eq!(aggregate.price, dec("1.02"));
+        assert_eq!(aggregate.rejected, vec!["b".to_string(), "d".to_string()]);
+    }
+
+    #[test]
+    fn mad_filter_rejects_a_manipulated_pool() {
+        let quotes = named(&[("agni", "1.050"), ("fusion", "1.052"), ("ondo", "1.051"), ("evil", "1.300")]);
+        let aggregate = AggregationStrategy::MadFilter { max_deviations: 3.0 }
+            .aggregate(&quotes)
+            .unwrap();
+        assert_eq!(aggregate.price, dec("1.051"));
+        assert_eq!(aggregate.rejected, vec!["evil".to_string()]);
+
+        // identical quotes keep anything within a basis point
+        let quotes = named(&[("a", "1.0000"), ("b", "1.0000"), ("c", "1.0001")]);
+        let aggregate = AggregationStrategy::MadFilter { max_deviations: 3.0 }
+            .aggregate(&quotes)
+            .unwrap();
+        assert!(aggregate.rejected.is_empty());
+    }
+
+    #[test]
+    fn invalid_strategies_are_rejected() {
+        assert!(AggregationStrategy::TrimmedMean { trim_percent: 50 }.validate().is_err());
+        assert!(AggregationStrategy::MadFilter { max_deviations: 0.0 }.validate().is_err());
+        assert!(AggregationStrategy::MadFilter { max_deviations: f64::NAN }.validate().is_err());
+        assert!(AggregationStrategy::Median.aggregate(&[]).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::*;
+use ethers::types::{BlockId, BlockNumber, U256, U64};
+use serde::{Deserialize, Serialize};
+
+/// Which block a run reads a chain at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockTag {
+    /// The chain head. Newest prices, but the block can still be reorged out.
+    #[default]
+    Latest,
+    /// The latest block that is unlikely to be reorged.
+    Safe,
+    /// The latest block that can no longer be reorged.
+    Finalized,
+}
+
+impl BlockTag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockTag::Latest => "latest",
+            BlockTag::Safe => "safe",
+            BlockTag::Finalized => "finalized",
+        }
+    }
+
+    /// The Solana commitment level closest to this tag. The head of the chain is read at
+    /// `confirmed`, since `processed` slots can still be skipped and have no block time.
+    pub fn commitment(&self) -> solana_sdk::commitment_config::CommitmentConfig {
+        use solana_sdk::commitment_config::CommitmentConfig;
+        match self {
+            BlockTag::Latest | BlockTag::Safe => CommitmentConfig::confirmed(),
+            BlockTag::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+/// The fields of an `eth_getBlockByNumber` result needed to pin a block.
+#[derive(Debug, Serialize, Deserialize)]
+struct BlockHeader {
+    number: U64,
+    timestamp: U256,
+}
+
+/// The block every read on a chain is issued at, so all prices of a run come from the
+/// same chain state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinnedBlock {
+    /// Block number, or the slot on Solana.
+    pub number: u64,
+    /// Unix timestamp of the block.
+    pub timestamp: i64,
+}
+
+impl PinnedBlock {
+    /// Resolves `tag` to a concrete block.
+    pub async fn resolve(provider: &EvmProvider, tag: BlockTag) -> Result<PinnedBlock, SourceError> {
+        let block: Option<BlockHeader> = provider
+            .request("eth_getBlockByNumber", (tag.as_str(), false))
+            .await
+            .map_err(SourceError::rpc("eth_getBlockByNumber"))?;
+        let block = block.ok_or(SourceError::InvalidData(format!(
+            "no {} block",
+            tag.as_str()
+        )))?;
+
+        if block.timestamp > U256::from(i64::MAX) {
+            return Err(SourceError::InvalidData(format!(
+                "block timestamp {} out of range",
+                block.timestamp
+            )));
+        }
+        Ok(PinnedBlock {
+            number: block.number.as_u64(),
+            timestamp: block.timestamp.as_u64() as i64,
+        })
+    }
+
+    /// Resolves `tag` to a concrete Solana slot.
+    pub async fn resolve_slot(client: &SolanaClient, tag: BlockTag) -> Result<PinnedBlock, SourceError> {
+        let client = client.clone();
+        // the runner's client is blocking
+        tokio::task::spawn_blocking(move || {
+            let slot = client
+                .get_slot_with_commitment(tag.commitment())
+                .map_err(SourceError::rpc("getSlot"))?;
+            let timestamp = client.get_block_time(slot).map_err(SourceError::rpc("getBlockTime"))?;
+            Ok(PinnedBlock {
+                number: slot,
+                timestamp,
+            })
+        })
+        .await
+        .map_err(SourceError::rpc("getSlot"))?
+    }
+
+    /// The current time, for sources that aren't read from a chain and have no block.
+    pub fn now() -> PinnedBlock {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        PinnedBlock {
+            number: 0,
+            timestamp: timestamp as i64,
+        }
+    }
+
+    pub fn id(&self) -> BlockId {
+        BlockId::Number(BlockNumber::Number(self.number.into()))
+    }
+}
+
+/// Resolves the block to read at on every chain that has a source configured. Off-chain
+/// sources read at the run's clock.
+pub async fn pin_blocks(
+    config: &FunctionConfig,
+    providers: &Providers,
+    solana: &SolanaClient,
+    tape: &RpcTape,
+) -> std::collections::HashMap<Chain, Result<PinnedBlock, SourceError>> {
+    let mut chains: Vec<Chain> = config.sources.iter().map(|source| source.chain()).collect();
+    chains.sort_by_key(|chain| *chain as u8);
+    chains.dedup();
+
+    let blocks = join_all(chains.iter().map(|chain| async move {
+        match (chain, config.chain(*chain)) {
+            (_, Some(chain_config)) => PinnedBlock::resolve(&providers[chain], chain_config.block_tag).await,
+            (Chain::Solana, None) => {
+                let tag = config.solana.as_ref().map(|solana| solana.block_tag).unwrap_or_default();
+                PinnedBlock::resolve_slot(solana, tag).await
+            }
+            (_, None) => Ok(tape.now()),
+        }
+    }))
+    .await;
+    chains.into_iter().zip(blocks).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, Reply};
+    use serde_json::json;
+
+    fn provider(server: &MockServer) -> EvmProvider {
+        EvmProvider::new(FailoverClient::new(&[server.url.clone()], RpcConfig::default()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn resolves_tagged_block() {
+        let server = MockServer::start(|method, params| {
+            assert_eq!(method, "eth_getBlockByNumber");
+            assert_eq!(params, &json!(["finalized", false]));
+            Reply::Result(json!({
+                "hash": format!("0x{}", "11".repeat(32)),
+                "number": "0x112a880",
+                "timestamp": "0x6553f100",
+                "gasLimit": "0x1c9c380",
+                "gasUsed": "0x0",
+                "difficulty": "0x0",
+                "transactions": [],
+            }))
+        })
+        .await;
+
+        let block = PinnedBlock::resolve(&provider(&server), BlockTag::Finalized).await.unwrap();
+        assert_eq!(
+            block,
+            PinnedBlock {
+                number: 18_000_000,
+                timestamp: 1_700_000_000,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_block_is_an_error() {
+        let server = MockServer::start(|_, _| Reply::Result(serde_json::Value::Null)).await;
+        assert!(PinnedBlock::resolve(&provider(&server), BlockTag::Safe).await.is_err());
+    }
+}
@@ -0,0 +1,1066 @@
+use crate::*;
+use ethers::types::H160;
+use serde::{Deserialize, Deserializer};
+use usdy_usd_oracle::SourceOverride;
+
+/// Defaults bundled into the enclave image, see `function.toml`.
+const BUNDLED_CONFIG: &str = include_str!("./function.toml");
+
+/// Symbols the function reports, as keyed in `[symbols]`.
+pub const SYMBOLS: [&str; 1] = [USDY_USDC];
+pub const USDY_USDC: &str = "usdy_usdc";
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct FunctionConfig {
+    pub mantle: ChainConfig,
+    pub ethereum: ChainConfig,
+    /// Required when a Solana source is configured.
+    #[serde(default)]
+    pub solana: Option<SolanaConfig>,
+    pub sources: Vec<SourceConfig>,
+    /// Per-symbol settings. Symbols that aren't listed use the defaults.
+    #[serde(default)]
+    pub symbols: std::collections::HashMap<String, SymbolConfig>,
+    #[serde(default)]
+    pub quorum: QuorumConfig,
+    #[serde(default)]
+    pub rpc: RpcConfig,
+    /// Addresses each source may be pointed at besides its bundled one, by its
+    /// `<NAME>_<FIELD>` variable or a function request.
+    #[serde(default)]
+    pub allowed_addresses: std::collections::HashMap<String, Vec<String>>,
+}
+
+/// Minimum number of sources of each kind that must return a quote for the run to
+/// report prices. Failed sources beyond that are logged and left out.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct QuorumConfig {
+    pub nav: usize,
+    pub market: usize,
+}
+
+impl Default for QuorumConfig {
+    fn default() -> Self {
+        QuorumConfig { nav: 1, market: 1 }
+    }
+}
+
+impl QuorumConfig {
+    pub fn min_sources(&self, kind: SourceKind) -> usize {
+        match kind {
+            SourceKind::Nav => self.nav,
+            SourceKind::Market => self.market,
+        }
+    }
+
+    /// Checks that enough sources of each kind succeeded, given the kinds of the
+    /// sources that returned a quote.
+    pub fn check(&self, succeeded: &[SourceKind]) -> Result<(), SbError> {
+        for kind in [SourceKind::Nav, SourceKind::Market] {
+            let count = succeeded.iter().filter(|k| **k == kind).count();
+            if count < self.min_sources(kind) {
+                return Err(SbError::CustomMessage(format!(
+                    "only {} {:?} sources succeeded, {} required",
+                    count,
+                    kind,
+                    self.min_sources(kind)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the source quotes for a symbol are aggregated.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SymbolConfig {
+    /// Strategy for the `SourceKind::Nav` quotes.
+    #[serde(default)]
+    pub nav: AggregationStrategy,
+    /// Strategy for the `SourceKind::Market` quotes.
+    #[serde(default)]
+    pub market: AggregationStrategy,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ChainConfig {
+    /// Endpoints in order of preference, later ones are only used when earlier ones fail.
+    pub rpc_urls: Vec<String>,
+    /// Block all reads on this chain are pinned to, `latest` unless configured.
+    #[serde(default)]
+    pub block_tag: BlockTag,
+    pub usdy: H160,
+    pub usdc: H160,
+}
+
+/// Solana is read through the `FunctionRunner`'s client, so only the tokens are configured.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SolanaConfig {
+    /// Slot all reads on Solana are pinned to, `latest` unless configured.
+    #[serde(default)]
+    pub block_tag: BlockTag,
+    #[serde(deserialize_with = "deserialize_pubkey")]
+    pub usdy: Pubkey,
+    #[serde(deserialize_with = "deserialize_pubkey")]
+    pub usdc: Pubkey,
+}
+
+/// The account or endpoint a source reads and is configured by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceAddress {
+    Evm(H160),
+    Solana(Pubkey),
+    Url(String),
+}
+
+impl SourceAddress {
+    pub fn is_zero(&self) -> bool {
+        match self {
+            SourceAddress::Evm(address) => address.is_zero(),
+            SourceAddress::Solana(pubkey) => *pubkey == Pubkey::default(),
+            SourceAddress::Url(url) => url.is_empty(),
+        }
+    }
+}
+
+enum SourceAddressMut<'a> {
+    Evm(&'a mut H160),
+    Solana(&'a mut Pubkey),
+    Url(&'a mut String),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SourceConfig {
+    UniswapV3 {
+        name: String,
+        chain: Chain,
+        factory: H160,
+        /// TWAP window in seconds, zero for the slot0 spot price.
+        #[serde(default = "default_twap_seconds")]
+        twap_seconds: u32,
+        /// Fee tiers to look for pools in, defaults to every standard tier.
+        #[serde(default = "default_fee_tiers")]
+        fee_tiers: Vec<u32>,
+        /// Pools with less in-range liquidity than this are ignored.
+        #[serde(default)]
+        min_liquidity: u128,
+    },
+    /// A Uniswap V2-style pair, including Solidly volatile and stable pairs.
+    UniswapV2 {
+        name: String,
+        chain: Chain,
+        pair: H160,
+        /// Solidly stable pair, priced with the `x^3 * y + x * y^3 = k` invariant.
+        #[serde(default)]
+        stable: bool,
+        /// The pair is ignored if `sqrt(reserve0 * reserve1)` is less than this.
+        #[serde(default)]
+        min_liquidity: u128,
+    },
+    /// A Curve StableSwap pool holding USDY and USDC.
+    Curve {
+        name: String,
+        chain: Chain,
+        pool: H160,
+        /// Amount of USDY, in whole tokens, quoted with `get_dy`.
+        #[serde(default = "default_curve_notional")]
+        notional: u64,
+        /// The pool's EMA oracle, used instead of `get_dy` when set.
+        #[serde(default)]
+        price_oracle: CurveOracle,
+    },
+    /// A Chainlink `AggregatorV3Interface` feed pricing USDY in USD.
+    Chainlink {
+        name: String,
+        chain: Chain,
+        feed: H160,
+        /// Rounds older than this at the pinned block are rejected.
+        #[serde(default = "default_chainlink_max_age_seconds")]
+        max_age_seconds: u64,
+        /// Whether the feed reports a traded price or a NAV, market unless configured.
+        #[serde(default = "default_chainlink_kind")]
+        kind: SourceKind,
+        /// Depth in USD the feed counts as when aggregated with pool quotes, which are
+        /// weighted by their USDC depth. Unweighted unless configured.
+        #[serde(default)]
+        weight: Option<u64>,
+    },
+    /// Ondo's `RWADynamicOracle`, whose price is checked against its accrual ranges.
+    Ondo {
+        name: String,
+        chain: Chain,
+        oracle: H160,
+        /// The NAV is projected to the run's time plus this many seconds, when the report
+        /// is expected to land on Solana.
+        #[serde(default)]
+        submission_delay_seconds: u64,
+    },
+    /// An Orca Whirlpool or Raydium CLMM pool holding USDY and USDC on Solana.
+    SolanaClmm {
+        name: String,
+        program: ClmmProgram,
+        #[serde(deserialize_with = "deserialize_pubkey")]
+        pool: Pubkey,
+        /// The pool is ignored if its in-range liquidity is less than this.
+        #[serde(default)]
+        min_liquidity: u128,
+    },
+    /// A centralized exchange's public REST ticker.
+    CexTicker {
+        name: String,
+        exchange: Exchange,
+        /// API root, e.g. `https://api.bybit.com`.
+        base_url: String,
+        /// The exchange's symbol for the market, which must be quoted in USDC, e.g.
+        /// `USDYUSDC`, or `USDY-USDC` on OKX.
+        symbol: String,
+        /// The ticker field reported, the bid/ask midpoint unless configured.
+        #[serde(default)]
+        price: TickerPrice,
+        /// Markets whose last trade is older than this are rejected.
+        #[serde(default = "default_cex_max_age_seconds")]
+        max_age_seconds: u64,
+        /// Tickers with a wider bid/ask spread than this are rejected.
+        #[serde(default = "default_cex_max_spread_bps")]
+        max_spread_bps: u32,
+        /// Tickers with less 24 hour volume than this, in USDY, are rejected.
+        #[serde(default)]
+        min_volume: u64,
+        /// Depth in USD the market counts as when aggregated with pool quotes, which are
+        /// weighted by their USDC depth. Unweighted unless configured.
+        #[serde(default)]
+        weight: Option<u64>,
+    },
+}
+
+impl SourceConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            SourceConfig::UniswapV3 { name, .. }
+            | SourceConfig::UniswapV2 { name, .. }
+            | SourceConfig::Curve { name, .. }
+            | SourceConfig::Chainlink { name, .. }
+            | SourceConfig::Ondo { name, .. }
+            | SourceConfig::SolanaClmm { name, .. }
+            | SourceConfig::CexTicker { name, .. } => name,
+        }
+    }
+
+    pub fn chain(&self) -> Chain {
+        match self {
+            SourceConfig::UniswapV3 { chain, .. }
+            | SourceConfig::UniswapV2 { chain, .. }
+            | SourceConfig::Curve { chain, .. }
+            | SourceConfig::Chainlink { chain, .. }
+            | SourceConfig::Ondo { chain, .. } => *chain,
+            SourceConfig::SolanaClmm { .. } => Chain::Solana,
+            SourceConfig::CexTicker { .. } => Chain::Offchain,
+        }
+    }
+
+    pub fn kind(&self) -> SourceKind {
+        match self {
+            SourceConfig::UniswapV3 { .. }
+            | SourceConfig::UniswapV2 { .. }
+            | SourceConfig::Curve { .. }
+            | SourceConfig::SolanaClmm { .. }
+            | SourceConfig::CexTicker { .. } => SourceKind::Market,
+            SourceConfig::Chainlink { kind, .. } => *kind,
+            SourceConfig::Ondo { .. } => SourceKind::Nav,
+        }
+    }
+
+    /// The contract or account address of this source and the field name it is configured under.
+    pub fn address(&self) -> (&'static str, SourceAddress) {
+        match self {
+            SourceConfig::UniswapV3 { factory, .. } => ("FACTORY", SourceAddress::Evm(*factory)),
+            SourceConfig::UniswapV2 { pair, .. } => ("PAIR", SourceAddress::Evm(*pair)),
+            SourceConfig::Curve { pool, .. } => ("POOL", SourceAddress::Evm(*pool)),
+            SourceConfig::Chainlink { feed, .. } => ("FEED", SourceAddress::Evm(*feed)),
+            SourceConfig::Ondo { oracle, .. } => ("ORACLE", SourceAddress::Evm(*oracle)),
+            SourceConfig::SolanaClmm { pool, .. } => ("POOL", SourceAddress::Solana(*pool)),
+            SourceConfig::CexTicker { base_url, .. } => ("URL", SourceAddress::Url(base_url.clone())),
+        }
+    }
+
+    fn address_mut(&mut self) -> (&'static str, SourceAddressMut<'_>) {
+        match self {
+            SourceConfig::UniswapV3 { factory, .. } => ("FACTORY", SourceAddressMut::Evm(factory)),
+            SourceConfig::UniswapV2 { pair, .. } => ("PAIR", SourceAddressMut::Evm(pair)),
+            SourceConfig::Curve { pool, .. } => ("POOL", SourceAddressMut::Evm(pool)),
+            SourceConfig::Chainlink { feed, .. } => ("FEED", SourceAddressMut::Evm(feed)),
+            SourceConfig::Ondo { oracle, .. } => ("ORACLE", SourceAddressMut::Evm(oracle)),
+            SourceConfig::SolanaClmm { pool, .. } => ("POOL", SourceAddressMut::Solana(pool)),
+            SourceConfig::CexTicker { base_url, .. } => ("URL", SourceAddressMut::Url(base_url)),
+        }
+    }
+
+    /// Replaces the address with `value`, parsed for the source's chain. `key` names the
+    /// override in errors.
+    fn set_address(&mut self, key: &str, value: &str) -> Result<(), SbError> {
+        match self.address_mut().1 {
+            SourceAddressMut::Evm(address) => *address = parse_address(key, value)?,
+            SourceAddressMut::Solana(pubkey) => *pubkey = parse_pubkey(key, value)?,
+            SourceAddressMut::Url(url) => *url = value.trim().to_string(),
+        }
+        Ok(())
+    }
+}
+
+impl FunctionConfig {
+    /// Loads the bundled config, applies any environment overrides and validates the result.
+    pub fn load() -> Result<FunctionConfig, SbError> {
+        dotenv::dotenv().ok();
+        let mut config = Self::from_toml(BUNDLED_CONFIG)?;
+        config.apply_overrides(|key| std::env::var(key).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_toml(s: &str) -> Result<FunctionConfig, SbError> {
+        toml::from_str(s).map_err(|e| SbError::CustomMessage(format!("invalid function config: {}", e)))
+    }
+
+    pub fn symbol(&self, symbol: &str) -> SymbolConfig {
+        self.symbols.get(symbol).cloned().unwrap_or_default()
+    }
+
+    /// The config of an EVM chain, `None` for Solana, which is configured by `solana`, and
+    /// off-chain sources.
+    pub fn chain(&self, chain: Chain) -> Option<&ChainConfig> {
+        match chain {
+            Chain::Ethereum => Some(&self.ethereum),
+            Chain::Mantle => Some(&self.mantle),
+            Chain::Solana | Chain::Offchain => None,
+        }
+    }
+
+    /// Overrides config values with the variables returned by `lookup`, the host environment
+    /// of the enclave.
+    ///
+    /// Chains read `<CHAIN>_RPC_URL`, a comma separated list of urls. Sources read
+    /// `<NAME>_<FIELD>`, e.g. `BYBIT_URL`, `AGNI_FACTORY` or `ONDO_ORACLE`. Endpoints can be
+    /// moved freely, but the host can only point a source at an address `allowed_addresses`
+    /// lists, as the addresses read decide what the enclave signs. Token addresses only come
+    /// from the bundled config.
+    pub fn apply_overrides(
+        &mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<(), SbError> {
+        for (prefix, chain) in [("MANTLE", &mut self.mantle), ("ETHEREUM", &mut self.ethereum)] {
+            if let Some(urls) = lookup(&format!("{}_RPC_URL", prefix)) {
+                chain.rpc_urls = urls
+                    .split(',')
+                    .map(|url| url.trim().to_string())
+                    .filter(|url| !url.is_empty())
+                    .collect();
+            }
+        }
+
+        for index in 0..self.sources.len() {
+            let source = &mut self.sources[index];
+            let key = format!("{}_{}", source.name().to_uppercase(), source.address().0);
+            if let Some(value) = lookup(&key) {
+                if let SourceAddress::Url(_) = source.address().1 {
+                    source.set_address(&key, &value)?;
+                } else {
+                    self.set_allowed_address(index, &key, &value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Points `sources[index]` at `value`, which `allowed_addresses` must list for it.
+    fn set_allowed_address(&mut self, index: usize, key: &str, value: &str) -> Result<(), SbError> {
+        let source = &mut self.sources[index];
+        let allowed = self.allowed_addresses.get(source.name()).cloned().unwrap_or_default();
+        // compared parsed, so EVM addresses match in any case
+        let allowed = allowed
+            .iter()
+            .map(|address| {
+                let mut allowed = source.clone();
+                allowed.set_address(key, address)?;
+                Ok(allowed.address().1)
+            })
+            .collect::<Result<Vec<_>, SbError>>()?;
+        source.set_address(key, value)?;
+        if !allowed.contains(&source.address().1) {
+            return Err(SbError::CustomMessage(format!(
+                "{}={} isn't in allowed_addresses.{}",
+                key,
+                value,
+                source.name()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Applies the source overrides of a function request for this run, then validates the
+    /// result so a request can't take the config below quorum. Addresses must be listed for
+    /// the source in `allowed_addresses`.
+    pub fn apply_request(&mut self, overrides: &[SourceOverride]) -> Result<(), SbError> {
+        for source_override in overrides {
+            let (SourceOverride::Disable { name } | SourceOverride::Address { name, .. }) = source_override;
+            let index = self
+                .sources
+                .iter()
+                .position(|source| source.name() == name)
+                .ok_or(SbError::CustomMessage(format!("request overrides unknown source {:?}", name)))?;
+            match source_override {
+                SourceOverride::Disable { .. } => {
+                    self.sources.remove(index);
+                    self.allowed_addresses.remove(name);
+                }
+                SourceOverride::Address { value, .. } => {
+                    let key = format!("{}_{}", name.to_uppercase(), self.sources[index].address().0);
+                    self.set_allowed_address(index, &key, value)?;
+                }
+            }
+        }
+        self.validate()
+    }
+
+    pub fn validate(&self) -> Result<(), SbError> {
+        self.rpc.validate()?;
+        for (name, chain) in [("mantle", &self.mantle), ("ethereum", &self.ethereum)] {
+            if chain.rpc_urls.is_empty() {
+                return Err(SbError::CustomMessage(format!("{}.rpc_urls must not be empty", name)));
+            }
+            for rpc_url in chain.rpc_urls.iter() {
+                validate_http_url(&format!("{}.rpc_urls", name), rpc_url)?;
+            }
+            if chain.usdy.is_zero() || chain.usdc.is_zero() {
+                return Err(SbError::CustomMessage(format!(
+                    "{}.usdy and {}.usdc must not be the zero address",
+                    name, name
+                )));
+            }
+            if chain.usdy == chain.usdc {
+                return Err(SbError::CustomMessage(format!(
+                    "{}.usdy and {}.usdc must be different tokens",
+                    name, name
+                )));
+            }
+        }
+
+        if let Some(solana) = self.solana.as_ref() {
+            if solana.usdy == Pubkey::default() || solana.usdc == Pubkey::default() {
+                return Err(SbError::CustomMessage(
+                    "solana.usdy and solana.usdc must be set".to_string(),
+                ));
+            }
+            if solana.usdy == solana.usdc {
+                return Err(SbError::CustomMessage(
+                    "solana.usdy and solana.usdc must be different mints".to_string(),
+                ));
+            }
+        }
+
+        let mut names = std::collections::HashSet::new();
+        for source in self.sources.iter() {
+            if !names.insert(source.name()) {
+                return Err(SbError::CustomMessage(format!(
+                    "source {:?} is configured more than once",
+                    source.name()
+                )));
+            }
+            if let SourceConfig::UniswapV3 { fee_tiers, .. } = source {
+                if fee_tiers.is_empty() {
+                    return Err(SbError::CustomMessage(format!(
+                        "source {:?}: fee_tiers must not be empty",
+                        source.name()
+                    )));
+                }
+            }
+            // build_sources reads EVM sources with the provider of their chain
+            let evm = !matches!(source, SourceConfig::SolanaClmm { .. } | SourceConfig::CexTicker { .. });
+            if evm && self.chain(source.chain()).is_none() {
+                return Err(SbError::CustomMessage(format!(
+                    "source {:?}: {:?} is not an EVM chain",
+                    source.name(),
+                    source.chain()
+                )));
+            }
+            if source.chain() == Chain::Solana && self.solana.is_none() {
+                return Err(SbError::CustomMessage(format!(
+                    "source {:?}: a [solana] section is required for Solana sources",
+                    source.name()
+                )));
+            }
+            if let SourceConfig::CexTicker {
+                exchange,
+                base_url,
+                symbol,
+                ..
+            } = source
+            {
+                validate_http_url(&format!("source {:?}: base_url", source.name()), base_url)?;
+                if symbol.is_empty() {
+                    return Err(SbError::CustomMessage(format!(
+                        "source {:?}: symbol must not be empty",
+                        source.name()
+                    )));
+                }
+                // prices are reported in USDC, other quote currencies aren't converted
+                if !exchange.quotes_usdc(symbol) {
+                    return Err(SbError::CustomMessage(format!(
+                        "source {:?}: {} is not a USDC market",
+                        source.name(),
+                        symbol
+                    )));
+                }
+            }
+            if let SourceConfig::Curve { notional: 0, .. } = source {
+                return Err(SbError::CustomMessage(format!(
+                    "source {:?}: notional must not be zero",
+                    source.name()
+                )));
+            }
+            let (field, address) = source.address();
+            if address.is_zero() {
+                return Err(SbError::CustomMessage(format!(
+                    "source {:?}: {} must not be the zero address",
+                    source.name(),
+                    field.to_lowercase()
+                )));
+            }
+        }
+        for (name, addresses) in self.allowed_addresses.iter() {
+            let mut source = self
+                .sources
+                .iter()
+                .find(|source| source.name() == name)
+                .cloned()
+                .ok_or(SbError::CustomMessage(format!(
+                    "allowed_addresses lists unknown source {:?}",
+                    name
+                )))?;
+            let key = format!("allowed_addresses.{}", name);
+            for address in addresses.iter() {
+                source.set_address(&key, address)?;
+            }
+        }
+        for kind in [SourceKind::Nav, SourceKind::Market] {
+            if !self.sources.iter().any(|source| source.kind() == kind) {
+                return Err(SbError::CustomMessage(format!(
+                    "at least one {:?} source must be configured",
+                    kind
+                )));
+            }
+            let configured = self.sources.iter().filter(|source| source.kind() == kind).count();
+            let quorum = self.quorum.min_sources(kind);
+            if quorum == 0 || quorum > configured {
+                return Err(SbError::CustomMessage(format!(
+                    "quorum for {:?} sources must be between 1 and {}, got {}",
+                    kind, configured, quorum
+                )));
+            }
+        }
+        for (symbol, symbol_config) in self.symbols.iter() {
+            if !SYMBOLS.contains(&symbol.as_str()) {
+                return Err(SbError::CustomMessage(format!("unknown symbol {:?}", symbol)));
+            }
+            for strategy in [&symbol_config.nav, &symbol_config.market] {
+                strategy.validate().map_err(|e| {
+                    SbError::CustomMessage(format!("symbols.{}: {:?}", symbol, e))
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn default_twap_seconds() -> u32 {
+    1800
+}
+
+fn default_chainlink_max_age_seconds() -> u64 {
+    // the 24h heartbeat of most stablecoin feeds, plus an hour of slack
+    90_000
+}
+
+fn default_chainlink_kind() -> SourceKind {
+    SourceKind::Market
+}
+
+fn default_cex_max_age_seconds() -> u64 {
+    60
+}
+
+fn default_cex_max_spread_bps() -> u32 {
+    100
+}
+
+fn default_curve_notional() -> u64 {
+    1_000
+}
+
+fn default_fee_tiers() -> Vec<u32> {
+    DEFAULT_FEE_TIERS.to_vec()
+}
+
+fn parse_address(key: &str, value: &str) -> Result<H160, SbError> {
+    H160::from_str(value.trim())
+        .map_err(|e| SbError::CustomMessage(format!("{}={:?} is not a valid address: {}", key, value, e)))
+}
+
+/// Plain http is only accepted for local stand-ins in dry runs and tests.
+const ALLOW_HTTP: bool = cfg!(any(test, feature = "dry-run"));
+
+fn validate_http_url(field: &str, value: &str) -> Result<(), SbError> {
+    validate_url(field, value, ALLOW_HTTP)
+}
+
+fn validate_url(field: &str, value: &str, allow_http: bool) -> Result<(), SbError> {
+    let url = url::Url::parse(value)
+        .map_err(|e| SbError::CustomMessage(format!("{} {:?} is invalid: {}", field, value, e)))?;
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if allow_http => Ok(()),
+        _ => Err(SbError::CustomMessage(format!("{} {:?} must be https", field, value))),
+    }
+}
+
+fn parse_pubkey(key: &str, value: &str) -> Result<Pubkey, SbError> {
+    Pubkey::from_str(value.trim())
+        .map_err(|e| SbError::CustomMessage(format!("{}={:?} is not a valid pubkey: {}", key, value, e)))
+}
+
+/// Reads a base58 pubkey, `Pubkey` itself deserializes from a byte array.
+fn deserialize_pubkey<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    Pubkey::from_str(&value).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundled() -> FunctionConfig {
+        FunctionConfig::from_toml(BUNDLED_CONFIG).unwrap()
+    }
+
+    #[test]
+    fn bundled_overrides_are_passed_into_the_enclave() {
+        let manifest = include_str!("../config/app.manifest.template");
+        let keys = std::cell::RefCell::new(vec![]);
+        bundled()
+            .apply_overrides(|key| {
+                keys.borrow_mut().push(key.to_string());
+                None
+            })
+            .unwrap();
+        assert!(keys.borrow().contains(&"ONDO_ORACLE".to_string()));
+        for key in keys.into_inner() {
+            let passthrough = format!("loader.env.{} = {{ passthrough = true }}", key);
+            assert!(manifest.contains(&passthrough), "{} is not passed through", key);
+        }
+    }
+
+    #[test]
+    fn bundled_config_is_valid() {
+        let config = bundled();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.sources.len(), 3);
+    }
+
+    #[test]
+    fn overrides_replace_bundled_values() {
+        let mut config = bundled();
+        config.allowed_addresses.insert(
+            "agni".to_string(),
+            vec!["0x0000000000000000000000000000000000000002".to_string()],
+        );
+        config
+            .apply_overrides(|key| match key {
+                "MANTLE_RPC_URL" => Some("http://127.0.0.1:8545".to_string()),
+                "ETHEREUM_RPC_URL" => Some("https://a.example, https://b.example".to_string()),
+                "MANTLE_USDC_ADDRESS" => Some("0x0000000000000000000000000000000000000001".to_string()),
+                "AGNI_FACTORY" => Some("0x0000000000000000000000000000000000000002".to_string()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(config.mantle.rpc_urls, vec!["http://127.0.0.1:8545".to_string()]);
+        assert_eq!(
+            config.ethereum.rpc_urls,
+            vec!["https://a.example".to_string(), "https://b.example".to_string()]
+        );
+        // token addresses aren't read from the environment
+        assert_eq!(config.mantle.usdc, bundled().mantle.usdc);
+        match &config.sources[0] {
+            SourceConfig::UniswapV3 { factory, .. } => assert_eq!(*factory, H160::from_low_u64_be(2)),
+            other => panic!("unexpected source {:?}", other),
+        }
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_overrides_are_rejected() {
+        let mut config = bundled();
+        assert!(config
+            .apply_overrides(|key| (key == "ONDO_ORACLE").then(|| "not-an-address".to_string()))
+            .is_err());
+        // the host can't point a source at an address the bundled config doesn't allow
+        let mut config = bundled();
+        assert!(config
+            .apply_overrides(|key| {
+                (key == "ONDO_ORACLE").then(|| "0x0000000000000000000000000000000000000001".to_string())
+            })
+            .is_err());
+
+        assert!(validate_url("rpc_urls", "https://rpc.example", false).is_ok());
+        assert!(validate_url("rpc_urls", "http://rpc.example", false).is_err());
+        assert!(validate_url("rpc_urls", "http://127.0.0.1:8545", true).is_ok());
+
+        let mut config = bundled();
+        config.ethereum.rpc_urls.push("ws://localhost".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn requests_override_sources() {
+        let mut config = bundled();
+        config.allowed_addresses.insert(
+            "agni".to_string(),
+            vec!["0x0000000000000000000000000000000000000002".to_string()],
+        );
+        config
+            .apply_request(&[
+                SourceOverride::Disable {
+                    name: "fusion".to_string(),
+                },
+                SourceOverride::Address {
+                    name: "agni".to_string(),
+                    value: "0x0000000000000000000000000000000000000002".to_string(),
+                },
+            ])
+            .unwrap();
+        assert_eq!(config.sources.len(), 2);
+        assert!(config.sources.iter().all(|source| source.name() != "fusion"));
+        assert_eq!(
+            config.sources[0].address(),
+            ("FACTORY", SourceAddress::Evm(H160::from_low_u64_be(2)))
+        );
+
+        let unknown = [SourceOverride::Disable {
+            name: "missing".to_string(),
+        }];
+        assert!(bundled().apply_request(&unknown).is_err());
+
+        // ondo is the only nav source, the request can't go below quorum
+        let below_quorum = [SourceOverride::Disable {
+            name: "ondo".to_string(),
+        }];
+        assert!(bundled().apply_request(&below_quorum).is_err());
+
+        let invalid = [SourceOverride::Address {
+            name: "ondo".to_string(),
+            value: "not-an-address".to_string(),
+        }];
+        assert!(bundled().apply_request(&invalid).is_err());
+    }
+
+    #[test]
+    fn request_addresses_must_be_allowed() {
+        let ondo = |value: &str| {
+            [SourceOverride::Address {
+                name: "ondo".to_string(),
+                value: value.to_string(),
+            }]
+        };
+        let allowed = "0x00000000000000000000000000000000000000aB";
+        // nothing is allowed by default
+        assert!(bundled().apply_request(&ondo(allowed)).is_err());
+
+        let mut config = bundled();
+        config.allowed_addresses.insert("ondo".to_string(), vec![allowed.to_string()]);
+        config.validate().unwrap();
+        let mut other = config.clone();
+        assert!(other
+            .apply_request(&ondo("0x00000000000000000000000000000000000000ac"))
+            .is_err());
+        config.apply_request(&ondo(&allowed.to_lowercase())).unwrap();
+        assert_eq!(
+            config.sources.iter().find(|source| source.name() == "ondo").unwrap().address().1,
+            SourceAddress::Evm(H160::from_low_u64_be(0xab))
+        );
+
+        // the allowlist must name configured sources and parse as their addresses
+        let mut unknown = bundled();
+        unknown.allowed_addresses.insert("missing".to_string(), vec![allowed.to_string()]);
+        assert!(unknown.validate().is_err());
+        let mut invalid = bundled();
+        invalid.allowed_addresses.insert("ondo".to_string(), vec!["ondo".to_string()]);
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn evm_sources_must_be_on_evm_chains() {
+        for chain in ["solana", "offchain"] {
+            let config = FunctionConfig::from_toml(&format!(
+                "{}\n[[sources]]\ntype = \"chainlink\"\nname = \"feed\"\nchain = \"{}\"\nfeed = \"0x0000000000000000000000000000000000000001\"\n",
+                BUNDLED_CONFIG, chain
+            ))
+            .unwrap();
+            assert!(config.validate().is_err());
+        }
+
+        let mut config = bundled();
+        if let SourceConfig::Ondo { chain, .. } = config.sources.last_mut().unwrap() {
+            *chain = Chain::Offchain;
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn duplicate_sources_are_rejected() {
+        let mut config = bundled();
+        config.sources.push(config.sources[0].clone());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn symbols_configure_aggregation() {
+        let config = bundled();
+        assert_eq!(
+            config.symbol(USDY_USDC).market,
+            AggregationStrategy::MadFilter { max_deviations: 3.0 }
+        );
+
+        let mut config = config;
+        config.symbols.clear();
+        assert_eq!(config.symbol(USDY_USDC), SymbolConfig::default());
+
+        config.symbols.insert("btc_usd".to_string(), SymbolConfig::default());
+        assert!(config.validate().is_err());
+
+        let mut config = bundled();
+        config.symbols.get_mut(USDY_USDC).unwrap().nav =
+            AggregationStrategy::TrimmedMean { trim_percent: 60 };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn quorum_tolerates_failed_sources() {
+        let config = bundled();
+        assert_eq!(config.quorum, QuorumConfig { nav: 1, market: 1 });
+        assert!(config.quorum.check(&[SourceKind::Nav, SourceKind::Market]).is_ok());
+        assert!(config.quorum.check(&[SourceKind::Market, SourceKind::Market]).is_err());
+        assert!(config.quorum.check(&[SourceKind::Nav]).is_err());
+
+        let mut config = config;
+        config.quorum.market = 3;
+        assert!(config.validate().is_err());
+        config.quorum.market = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn uniswap_v2_pairs_are_configurable() {
+        let config = FunctionConfig::from_toml(&format!(
+            "{}\n{}",
+            BUNDLED_CONFIG,
+            r#"
+[[sources]]
+type = "uniswap_v2"
+name = "stable_pair"
+chain = "mantle"
+pair = "0x0000000000000000000000000000000000000003"
+stable = true
+"#
+        ))
+        .unwrap();
+        match config.sources.last().unwrap() {
+            SourceConfig::UniswapV2 { stable, min_liquidity, .. } => {
+                assert!(*stable);
+                assert_eq!(*min_liquidity, 0);
+            }
+            other => panic!("unexpected source {:?}", other),
+        }
+        assert_eq!(config.sources.last().unwrap().kind(), SourceKind::Market);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn curve_pools_are_configurable() {
+        let curve = |extra: &str| {
+            FunctionConfig::from_toml(&format!(
+                "{}\n[[sources]]\ntype = \"curve\"\nname = \"curve\"\nchain = \"ethereum\"\npool = \"0x0000000000000000000000000000000000000004\"\n{}",
+                BUNDLED_CONFIG, extra
+            ))
+            .unwrap()
+        };
+
+        let config = curve("");
+        match config.sources.last().unwrap() {
+            SourceConfig::Curve { notional, price_oracle, .. } => {
+                assert_eq!(*notional, 1_000);
+                assert_eq!(*price_oracle, CurveOracle::None);
+            }
+            other => panic!("unexpected source {:?}", other),
+        }
+        assert!(config.validate().is_ok());
+
+        let config = curve("price_oracle = \"indexed\"\nnotional = 0");
+        assert!(matches!(
+            config.sources.last().unwrap(),
+            SourceConfig::Curve { price_oracle: CurveOracle::Indexed, .. }
+        ));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn chainlink_feeds_can_be_either_kind() {
+        let config = FunctionConfig::from_toml(&format!(
+            "{}\n{}",
+            BUNDLED_CONFIG,
+            r#"
+[[sources]]
+type = "chainlink"
+name = "chainlink_nav"
+chain = "ethereum"
+feed = "0x0000000000000000000000000000000000000005"
+kind = "nav"
+"#
+        ))
+        .unwrap();
+        let source = config.sources.last().unwrap();
+        assert_eq!(source.kind(), SourceKind::Nav);
+        assert_eq!(source.address(), ("FEED", SourceAddress::Evm(H160::from_low_u64_be(5))));
+        match source {
+            SourceConfig::Chainlink { max_age_seconds, .. } => assert_eq!(*max_age_seconds, 90_000),
+            other => panic!("unexpected source {:?}", other),
+        }
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn solana_pools_are_configurable() {
+        let config = FunctionConfig::from_toml(&format!(
+            "{}\n{}",
+            BUNDLED_CONFIG,
+            r#"
+[[sources]]
+type = "solana_clmm"
+name = "orca"
+program = "whirlpool"
+pool = "11111111111111111111111111111112"
+"#
+        ))
+        .unwrap();
+        let source = config.sources.last().unwrap();
+        assert_eq!(source.chain(), Chain::Solana);
+        assert_eq!(source.kind(), SourceKind::Market);
+        assert!(matches!(source.address(), ("POOL", SourceAddress::Solana(_))));
+        assert!(config.chain(Chain::Solana).is_none());
+        assert!(config.validate().is_ok());
+
+        let pool = Pubkey::new_unique();
+        let mut overridden = config.clone();
+        overridden.allowed_addresses.insert("orca".to_string(), vec![pool.to_string()]);
+        overridden
+            .apply_overrides(|key| (key == "ORCA_POOL").then(|| pool.to_string()))
+            .unwrap();
+        assert_eq!(overridden.sources.last().unwrap().address().1, SourceAddress::Solana(pool));
+        assert!(config
+            .clone()
+            .apply_overrides(|key| (key == "ORCA_POOL").then(|| "0x0000000000000000000000000000000000000001".to_string()))
+            .is_err());
+
+        let mut config = config;
+        config.solana = None;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn cex_tickers_are_configurable() {
+        let cex = |extra: &str| {
+            FunctionConfig::from_toml(&format!(
+                "{}\n[[sources]]\ntype = \"cex_ticker\"\nname = \"bybit\"\nexchange = \"bybit\"\nbase_url = \"https://api.bybit.com\"\nsymbol = \"USDYUSDC\"\n{}",
+                BUNDLED_CONFIG, extra
+            ))
+            .unwrap()
+        };
+
+        let config = cex("");
+        let source = config.sources.last().unwrap();
+        assert_eq!(source.chain(), Chain::Offchain);
+        assert_eq!(source.kind(), SourceKind::Market);
+        match source {
+            SourceConfig::CexTicker {
+                price,
+                max_age_seconds,
+                max_spread_bps,
+                ..
+            } => {
+                assert_eq!(*price, TickerPrice::Mid);
+                assert_eq!(*max_age_seconds, 60);
+                assert_eq!(*max_spread_bps, 100);
+            }
+            other => panic!("unexpected source {:?}", other),
+        }
+        assert!(config.validate().is_ok());
+
+        let mut config = cex("price = \"last\"");
+        config
+            .apply_overrides(|key| (key == "BYBIT_URL").then(|| "http://127.0.0.1:8080".to_string()))
+            .unwrap();
+        assert_eq!(
+            config.sources.last().unwrap().address().1,
+            SourceAddress::Url("http://127.0.0.1:8080".to_string())
+        );
+        assert!(config.validate().is_ok());
+
+        let mut config = cex("");
+        config.apply_overrides(|key| (key == "BYBIT_URL").then(|| "ftp://x".to_string())).unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config = cex("");
+        if let Some(SourceConfig::CexTicker { symbol, .. }) = config.sources.last_mut() {
+            symbol.clear();
+        }
+        assert!(config.validate().is_err());
+
+        let mut config = cex("");
+        if let Some(SourceConfig::CexTicker { symbol, .. }) = config.sources.last_mut() {
+            *symbol = "USDYUSDT".to_string();
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn ondo_projects_to_the_submission_delay() {
+        let config = bundled();
+        match config.sources.last().unwrap() {
+            SourceConfig::Ondo { submission_delay_seconds, .. } => assert_eq!(*submission_delay_seconds, 30),
+            other => panic!("unexpected source {:?}", other),
+        }
+
+        let config = FunctionConfig::from_toml(&BUNDLED_CONFIG.replace("submission_delay_seconds = 30\n", "")).unwrap();
+        match config.sources.last().unwrap() {
+            SourceConfig::Ondo { submission_delay_seconds, .. } => assert_eq!(*submission_delay_seconds, 0),
+            other => panic!("unexpected source {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fee_tiers_default_to_every_tier() {
+        let config = FunctionConfig::from_toml(&BUNDLED_CONFIG.replace("fee_tiers = [100, 500, 3000, 10000]\n", ""))
+            .unwrap();
+        match &config.sources[0] {
+            SourceConfig::UniswapV3 { fee_tiers, .. } => assert_eq!(fee_tiers, &DEFAULT_FEE_TIERS.to_vec()),
+            other => panic!("unexpected source {:?}", other),
+        }
+
+        let mut config = config;
+        if let SourceConfig::UniswapV3 { fee_tiers, .. } = &mut config.sources[0] {
+            fee_tiers.clear();
+        }
+        assert!(config.validate().is_err());
+    }
+}
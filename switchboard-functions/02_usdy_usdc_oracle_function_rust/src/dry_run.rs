@@ -0,0 +1,232 @@
+//! Local dry runs of the function, outside an SGX runner.
+//!
+//! Built with `cargo run --features dry-run -- [params]`, where `params` are hex encoded
+//! `FunctionRequestParams` and the full job when omitted. The run builds a `FunctionRunner`
+//! from stub environment values, executes the same fetch and aggregation pipeline as the
+//! enclave and, instead of submitting, prints the instructions it would have emitted as a
+//! single JSON line, the last line on stdout.
+//!
+//! `CLUSTER` picks the Solana cluster as it does for the runner. `FUNCTION_KEY` defaults to
+//! the function set in the oracle program state, and the other runner variables to stubs.
+//! With `RPC_REPLAY_PATH` set to a recording of a production run, the dry run reproduces
+//! that run without network access, see `recording.rs`: the function and function request
+//! keys come from the recording and the function data is stubbed.
+
+use crate::*;
+use serde_json::{json, Value};
+use switchboard_solana::get_ixn_discriminator;
+use usdy_usd_oracle::{RefreshOraclesBatchParams, RefreshOraclesParams};
+
+pub async fn run() -> Result<(), SbError> {
+    dotenv::dotenv().ok();
+    let params = match std::env::args().nth(1) {
+        Some(hex) => decode_hex(&hex)?,
+        None => vec![],
+    };
+
+    let tape = RpcTape::from_env()?;
+    stub_environment(&tape)?;
+    let runner = FunctionRunner::from_env(None)?;
+    println!("dry run: {}", runner);
+
+    let ixs = etherprices_oracle_function(runner.clone(), params)
+        .await
+        .map_err(|e| SbError::CustomMessage(format!("function failed: {:?}", e)))?;
+    println!("{}", report(&runner.function, &runner.signer, &ixs)?);
+    Ok(())
+}
+
+/// Sets the runner's required variables that aren't already in the environment, from the
+/// recorded run when replaying one and from the cluster otherwise.
+fn stub_environment(tape: &RpcTape) -> Result<(), SbError> {
+    let cluster = std::env::var("CLUSTER").unwrap_or("mainnet".to_string());
+    let cluster = Cluster::from_str(&cluster)
+        .map_err(|e| SbError::CustomMessage(format!("CLUSTER={:?} is invalid: {}", cluster, e)))?;
+    let client = solana_client::rpc_client::RpcClient::new(cluster.url().to_string());
+
+    if let RpcTape::Replay { .. } = tape {
+        let (function, request) = tape.replayed_runner().ok_or_else(|| {
+            SbError::CustomMessage("the recording has no runner to replay as".to_string())
+        })?;
+        if std::env::var("FUNCTION_KEY").is_err() {
+            std::env::set_var("FUNCTION_KEY", function.to_string());
+        }
+        if let (Err(_), Some(request)) = (std::env::var("FUNCTION_REQUEST_KEY"), request) {
+            std::env::set_var("FUNCTION_REQUEST_KEY", request.to_string());
+        }
+        if std::env::var("FUNCTION_DATA").is_err() {
+            let data = FunctionAccountData::default();
+            std::env::set_var("FUNCTION_DATA", encode_hex(bytemuck::bytes_of(&data)));
+        }
+    }
+    if std::env::var("FUNCTION_KEY").is_err() {
+        let (program_state, _) =
+            Pubkey::find_program_address(&[usdy_usd_oracle::PROGRAM_SEED], &usdy_usd_oracle::ID);
+        let data = client
+            .get_account_data(&program_state)
+            .map_err(|e| SbError::CustomMessage(format!("failed to read the program state: {}", e)))?;
+        let len = 8 + std::mem::size_of::<usdy_usd_oracle::MyProgramState>();
+        if data.len() < len {
+            return Err(SbError::CustomMessage("program state is too short".to_string()));
+        }
+        let state: usdy_usd_oracle::MyProgramState = bytemuck::pod_read_unaligned(&data[8..len]);
+        std::env::set_var("FUNCTION_KEY", state.switchboard_function.to_string());
+    }
+    if std::env::var("FUNCTION_DATA").is_err() {
+        // the runner reads the function account without its discriminator
+        let function = Pubkey::from_str(&std::env::var("FUNCTION_KEY").unwrap_or_default())
+            .map_err(|e| SbError::CustomMessage(format!("FUNCTION_KEY is invalid: {}", e)))?;
+        let len = 8 + std::mem::size_of::<FunctionAccountData>();
+        let data = match client.get_account_data(&function) {
+            Ok(data) if data.len() >= len => data[8..len].to_vec(),
+            _ => {
+                println!("dry run: function {} not found, using empty function data", function);
+                bytemuck::bytes_of(&FunctionAccountData::default()).to_vec()
+            }
+        };
+        std::env::set_var("FUNCTION_DATA", encode_hex(&data));
+    }
+    for name in ["PAYER", "VERIFIER", "REWARD_RECEIVER"] {
+        if std::env::var(name).is_err() {
+            std::env::set_var(name, Pubkey::default().to_string());
+        }
+    }
+    Ok(())
+}
+
+/// What the run would have submitted: the PDAs it derived and each instruction with its
+/// account metas and decoded params.
+fn report(function: &Pubkey, signer: &Pubkey, ixs: &[Instruction]) -> Result<Value, SbError> {
+    let accounts = OracleAccounts::derive(function);
+    Ok(json!({
+        "function": function.to_string(),
+        "signer": signer.to_string(),
+        "pdas": {
+            "program_state": accounts.program_state.to_string(),
+            "oracle": accounts.oracle.to_string(),
+            "ondo_price_feed": accounts.ondo_price_feed.to_string(),
+            "ondo_traded_feed": accounts.ondo_traded_feed.to_string(),
+            "symbol_table": accounts.symbol_table.to_string(),
+        },
+        "instructions": ixs.iter().map(instruction_json).collect::<Result<Vec<_>, _>>()?,
+    }))
+}
+
+fn instruction_json(ix: &Instruction) -> Result<Value, SbError> {
+    let accounts: Vec<Value> = ix
+        .accounts
+        .iter()
+        .map(|meta| {
+            json!({
+                "pubkey": meta.pubkey.to_string(),
+                "is_signer": meta.is_signer,
+                "is_writable": meta.is_writable,
+            })
+        })
+        .collect();
+    let discriminator = get_ixn_discriminator("refresh_oracles_batch");
+    if ix.program_id != usdy_usd_oracle::ID || !ix.data.starts_with(&discriminator) {
+        return Ok(json!({
+            "program_id": ix.program_id.to_string(),
+            "accounts": accounts,
+            "data": encode_hex(&ix.data),
+        }));
+    }
+
+    let batch = RefreshOraclesBatchParams::try_from_slice(&ix.data[discriminator.len()..])
+        .map_err(|e| SbError::CustomMessage(format!("failed to decode the batch: {}", e)))?;
+    batch
+        .validate()
+        .map_err(|e| SbError::CustomMessage(format!("invalid batch: {:?}", e)))?;
+    let params = RefreshOraclesParams {
+        rows: batch.rows().collect(),
+    };
+    let rows: Vec<Value> = params
+        .rows
+        .iter()
+        .map(|row| {
+            json!({
+                "symbol": format!("{:?}", row.symbol),
+                "oracle_timestamp": row.data.oracle_timestamp,
+                "ondo_price": row.data.ondo_price,
+                "traded_price": row.data.traded_price,
+                "ondo_block": row.data.ondo_block,
+                "traded_block": row.data.traded_block,
+            })
+        })
+        .collect();
+    Ok(json!({
+        "program_id": ix.program_id.to_string(),
+        "instruction": "refresh_oracles_batch",
+        "accounts": accounts,
+        "params": { "rows": rows },
+    }))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, SbError> {
+    let hex = hex.trim().trim_start_matches("0x");
+    if !hex.is_ascii() || hex.len() % 2 != 0 {
+        return Err(SbError::CustomMessage(format!("params {:?} are not hex", hex)));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| SbError::CustomMessage(format!("params {:?} are not hex", hex)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use usdy_usd_oracle::{OracleDataBorsh, OracleDataWithTradingSymbol, TradingSymbol};
+
+    #[test]
+    fn hex_params_round_trip() {
+        let bytes = vec![0x00, 0x01, 0xab, 0xff];
+        assert_eq!(encode_hex(&bytes), "0001abff");
+        assert_eq!(decode_hex("0x0001abff").unwrap(), bytes);
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+    }
+
+    #[test]
+    fn refresh_instructions_are_decoded() {
+        let batch = RefreshOraclesBatchParams::from_rows(&[OracleDataWithTradingSymbol {
+            symbol: TradingSymbol::Usdy_usdc,
+            data: OracleDataBorsh {
+                oracle_timestamp: 1_700_000_000,
+                ondo_price: 1_050_000_000,
+                traded_price: 1_049_000_000,
+                ondo_block: 18_000_000,
+                traded_block: 50_000_000,
+            },
+        }])
+        .unwrap();
+        let signer = Pubkey::new_unique();
+        let ix = Instruction {
+            program_id: usdy_usd_oracle::ID,
+            accounts: vec![AccountMeta::new_readonly(signer, true)],
+            data: [
+                get_ixn_discriminator("refresh_oracles_batch").to_vec(),
+                batch.try_to_vec().unwrap(),
+            ]
+            .concat(),
+        };
+
+        let report = report(&Pubkey::new_unique(), &signer, &[ix]).unwrap();
+        let ix = &report["instructions"][0];
+        assert_eq!(ix["instruction"], "refresh_oracles_batch");
+        assert_eq!(ix["accounts"][0]["pubkey"], signer.to_string());
+        assert_eq!(ix["accounts"][0]["is_signer"], true);
+        assert_eq!(ix["params"]["rows"][0]["symbol"], "Usdy_usdc");
+        assert_eq!(ix["params"]["rows"][0]["ondo_price"], 1_050_000_000);
+        assert_eq!(ix["params"]["rows"][0]["traded_block"], 50_000_000);
+        assert!(report["pdas"]["oracle"].is_string());
+    }
+}
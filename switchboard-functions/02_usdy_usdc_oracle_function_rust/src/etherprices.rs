@@ -3,7 +3,10 @@
 use crate::*;
 
 use switchboard_solana::get_ixn_discriminator;
-use usdy_usd_oracle::{OracleDataBorsh, TradingSymbol, OracleDataWithTradingSymbol, RefreshOraclesParams};
+use usdy_usd_oracle::{
+    MyOracleState, MyProgramState, OracleDataBorsh, OracleDataWithTradingSymbol,
+    RefreshOraclesBatchParams, TradingSymbol,
+};
 use serde::Deserialize;
 
 #[allow(non_snake_case)]
@@ -11,7 +14,10 @@ use serde::Deserialize;
 pub struct Ticker {
     pub symbol: String, // BTCUSDT
     pub ondo_price: u128,
-    pub traded_price: u128
+    pub traded_price: u128,
+    pub oracle_timestamp: i64,
+    pub ondo_block: u64,
+    pub traded_block: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -24,26 +30,157 @@ impl TryInto<OracleDataBorsh> for IndexData {
     type Error = SbError;
 
     fn try_into(self) -> Result<OracleDataBorsh, Self::Error> {
-        let oracle_timestamp: i64 = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map_err(|_| {
-                SbError::CustomMessage("Invalid oracle_timestamp".to_string())
-            })?
-            .as_secs()
-            .try_into()
-            .map_err(|_| {
-                SbError::CustomMessage("Invalid oracle_timestamp".to_string())
-            })?;
+        if self.data.oracle_timestamp <= 0 {
+            return Err(SbError::CustomMessage("Invalid oracle_timestamp".to_string()));
+        }
 
             switchboard_solana::Result::Ok(OracleDataBorsh {
-                oracle_timestamp,
+                oracle_timestamp: self.data.oracle_timestamp,
                 ondo_price: self.data.ondo_price as u64,
                 traded_price: self.data.traded_price as u64,
-
+                ondo_block: self.data.ondo_block,
+                traded_block: self.data.traded_block,
             })
     }
 }
 
+/// The oracle program accounts `refresh_oracles_batch` writes, derived for a function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OracleAccounts {
+    pub program_state: Pubkey,
+    pub oracle: Pubkey,
+    pub ondo_price_feed: Pubkey,
+    pub ondo_traded_feed: Pubkey,
+    pub symbol_table: Pubkey,
+}
+
+impl OracleAccounts {
+    pub fn derive(function: &Pubkey) -> Self {
+        let pda = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &usdy_usd_oracle::ID).0;
+        OracleAccounts {
+            program_state: pda(&[b"USDY_USDC_ORACLE_V2"]),
+            oracle: pda(&[b"ORACLE_USDY_SEED_V2"]),
+            ondo_price_feed: pda(&[b"ORACLE_USDY_SEED_V2", function.as_ref(), b"ondo_price_feed"]),
+            ondo_traded_feed: pda(&[b"ORACLE_USDY_SEED_V2", function.as_ref(), b"ondo_traded_feed"]),
+            symbol_table: pda(&[usdy_usd_oracle::SYMBOL_TABLE_SEED]),
+        }
+    }
+}
+
+/// What `to_ixns` needs to know about the oracle program accounts on chain.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OnchainAccounts {
+    /// Reference feeds the program state requires as remaining accounts of a refresh.
+    pub reference_feeds: Vec<Pubkey>,
+    /// Whether the Ondo feeds of the function exist. They are optional, and only created by
+    /// `sync_feeds` after the multisig sets a new function.
+    pub feeds_exist: bool,
+    /// Whether `init_symbol_table` created the symbol table, which refreshes then also write.
+    pub symbol_table_exists: bool,
+}
+
+impl OnchainAccounts {
+    pub async fn load(client: &SolanaClient, accounts: &OracleAccounts) -> Result<Self, SbError> {
+        let client = client.clone();
+        let keys = vec![
+            accounts.program_state,
+            accounts.ondo_price_feed,
+            accounts.ondo_traded_feed,
+            accounts.symbol_table,
+        ];
+        // the runner's client is blocking
+        let mut found = tokio::task::spawn_blocking(move || client.get_multiple_accounts(&keys))
+            .await
+            .map_err(|e| SbError::CustomMessage(format!("getMultipleAccounts: {}", e)))?
+            .map_err(|e| SbError::CustomMessage(format!("getMultipleAccounts: {}", e)))?
+            .into_iter();
+        let program_state = found.next().flatten();
+        let (ondo_price_feed, ondo_traded_feed) = (found.next().flatten(), found.next().flatten());
+        let symbol_table = found.next().flatten();
+        Ok(OnchainAccounts {
+            reference_feeds: program_state
+                .and_then(|account| decode_program_state(&account.data))
+                .map(|state| state.reference_feeds().to_vec())
+                .unwrap_or_default(),
+            feeds_exist: ondo_price_feed.is_some() && ondo_traded_feed.is_some(),
+            symbol_table_exists: symbol_table.is_some(),
+        })
+    }
+}
+
+/// The program state, or `None` for a missing account or one created before the current layout,
+/// which can't have reference feeds configured.
+fn decode_program_state(data: &[u8]) -> Option<MyProgramState> {
+    let len = 8 + std::mem::size_of::<MyProgramState>();
+    if data.len() < len || data[..8] != MyProgramState::DISCRIMINATOR {
+        return None;
+    }
+    Some(bytemuck::pod_read_unaligned(&data[8..len]))
+}
+
+/// Account metas of `refresh_oracles_batch`, followed by the reference feeds it's checked against.
+/// Runs started by a function request pass it, the program checks `signer` against it.
+pub fn refresh_accounts(
+    function: &Pubkey,
+    signer: &Pubkey,
+    function_request: Option<&Pubkey>,
+    onchain: &OnchainAccounts,
+) -> Vec<AccountMeta> {
+    let OracleAccounts {
+        program_state,
+        oracle,
+        ondo_price_feed,
+        ondo_traded_feed,
+        symbol_table,
+    } = OracleAccounts::derive(function);
+    println!("program_state_pubkey: {:?}", program_state);
+    println!("oracle_pubkey: {:?}", oracle);
+    // optional accounts that aren't passed are replaced by the program id
+    let optional = |pubkey: Pubkey, exists: bool| AccountMeta {
+        pubkey: if exists { pubkey } else { usdy_usd_oracle::ID },
+        is_signer: false,
+        is_writable: exists,
+    };
+
+    let mut accounts = vec![
+        AccountMeta {
+            pubkey: program_state,
+            is_signer: false,
+            is_writable: true,
+        },
+        AccountMeta {
+            pubkey: oracle,
+            is_signer: false,
+            is_writable: true,
+        },
+        AccountMeta {
+            pubkey: *function,
+            is_signer: false,
+            is_writable: false,
+        },
+        AccountMeta {
+            pubkey: *signer,
+            is_signer: true,
+            is_writable: false,
+        },
+        optional(ondo_price_feed, onchain.feeds_exist),
+        optional(ondo_traded_feed, onchain.feeds_exist),
+        optional(symbol_table, onchain.symbol_table_exists),
+        AccountMeta::new_readonly(
+            function_request.copied().unwrap_or(usdy_usd_oracle::ID),
+            false,
+        ),
+    ];
+    // the program requires exactly the configured reference feeds, in order
+    accounts.extend(
+        onchain
+            .reference_feeds
+            .iter()
+            .map(|feed| AccountMeta::new_readonly(*feed, false)),
+    );
+    accounts
+}
+
 pub struct EtherPrices {
     pub usdy_usd: IndexData,
 }
@@ -51,7 +188,13 @@ pub struct EtherPrices {
 impl EtherPrices {
 
     // Fetch data from the EtherPrices API
-    pub async fn fetch(ondo_price:  ethers::types::U256, traded_price:  ethers::types::U256) -> std::result::Result<EtherPrices, SbError> {
+    pub async fn fetch(
+        ondo_price: ethers::types::U256,
+        traded_price: ethers::types::U256,
+        oracle_timestamp: i64,
+        ondo_block: u64,
+        traded_block: u64,
+    ) -> std::result::Result<EtherPrices, SbError> {
         let symbols = ["USDYUSD"];
         let ondo_price = ondo_price.as_u128();
         let traded_price = traded_price.as_u128();
@@ -68,21 +211,27 @@ impl EtherPrices {
                         symbol: symbol.to_string(),
                         ondo_price: ondo_price,
                         traded_price: traded_price,
-                    
+                        oracle_timestamp,
+                        ondo_block,
+                        traded_block,
                     }
                 }
             }
         })
     }
 
-    pub fn to_ixns(&self, runner: &FunctionRunner) -> Vec<Instruction> {
+    pub fn to_ixns(
+        &self,
+        runner: &FunctionRunner,
+        onchain: &OnchainAccounts,
+    ) -> Result<Vec<Instruction>, SbError> {
         println!("to_ixns");
         let rows: Vec<OracleDataWithTradingSymbol> = vec![
             OracleDataWithTradingSymbol {
                 symbol: TradingSymbol::Usdy_usdc,
                 data: self.usdy_usd.clone().try_into().map_err(|_| {
                     SbError::CustomMessage("Invalid oracle data".to_string())
-                }).unwrap(),
+                })?,
             }
             // OracleDataWithTradingSymbol {
             // symbol: TradingSymbol::Sol,
@@ -94,71 +243,126 @@ impl EtherPrices {
             // },
         ];
         println!("2");
-        let params = RefreshOraclesParams { rows };
-
-        let (program_state_pubkey, _state_bump) =
-            Pubkey::find_program_address(&[b"USDY_USDC_ORACLE_V2"], &usdy_usd_oracle::ID);
-        println!("program_state_pubkey: {:?}", program_state_pubkey);
-
-        let (oracle_pubkey, _oracle_bump) =
-            Pubkey::find_program_address(&[b"ORACLE_USDY_SEED_V2"], &usdy_usd_oracle::ID);
-        println!("oracle_pubkey: {:?}", oracle_pubkey);
-        let (ondo_price_feed, _) =
-            Pubkey::find_program_address(&[b"ORACLE_USDY_SEED_V2",
-            runner.function.as_ref(),
-            b"ondo_price_feed"],
-            &usdy_usd_oracle::ID);
-        let (ondo_traded_feed, _) =
-            Pubkey::find_program_address(&[b"ORACLE_USDY_SEED_V2",
-            runner.function.as_ref(),
-            b"ondo_traded_feed"],
-            &usdy_usd_oracle::ID);
-        println!("oracle_pubkey: {:?}", oracle_pubkey);
-        
+        // pack the rows into the compact batch encoding so we stay within compute limits
+        // as the number of tracked symbols grows
+        let params = RefreshOraclesBatchParams::from_rows(&rows)
+            .map_err(|e| SbError::CustomMessage(format!("Invalid batch: {:?}", e)))?;
+
         let ixn = Instruction {
             program_id: usdy_usd_oracle::ID,
-            accounts: vec![
-                AccountMeta {
-                    pubkey: program_state_pubkey,
-                    is_signer: false,
-                    is_writable: true,
-                },
-                AccountMeta {
-                    pubkey: oracle_pubkey,
-                    is_signer: false,
-                    is_writable: true,
-                },
-                AccountMeta {
-                    pubkey: runner.function,
-                    is_signer: false,
-                    is_writable: false,
-                },
-                AccountMeta {
-                    pubkey: runner.signer,
-                    is_signer: true,
-                    is_writable: false,
-                },
-                //ondo_price_feed
-                AccountMeta {
-                    pubkey: ondo_price_feed,
-                    is_signer: false,
-                    is_writable: true,
-                },
-                //ondo_traded_feed
-                AccountMeta {
-                    pubkey: ondo_traded_feed,
-                    is_signer: false,
-                    is_writable: true,
-                },
-
-            ],
+            accounts: refresh_accounts(
+                &runner.function,
+                &runner.signer,
+                runner.function_request_key.as_ref(),
+                onchain,
+            ),
             data: [
-                get_ixn_discriminator("refresh_oracles").to_vec(),
-                params.try_to_vec().unwrap(),
+                get_ixn_discriminator("refresh_oracles_batch").to_vec(),
+                params.try_to_vec().map_err(|_| {
+                    SbError::CustomMessage("Failed to serialize params".to_string())
+                })?,
             ]
             .concat(),
         };
-        vec![ixn]
+        Ok(vec![ixn])
     }
 }
 
+/// `oracle_timestamp` of the USDY/USDC row the oracle account currently holds.
+pub async fn onchain_oracle_timestamp(client: &SolanaClient) -> Result<i64, SbError> {
+    let (oracle_pubkey, _) = Pubkey::find_program_address(&[b"ORACLE_USDY_SEED_V2"], &usdy_usd_oracle::ID);
+    let client = client.clone();
+    // the runner's client is blocking
+    let data = tokio::task::spawn_blocking(move || client.get_account_data(&oracle_pubkey))
+        .await
+        .map_err(|e| SbError::CustomMessage(format!("getAccountInfo: {}", e)))?
+        .map_err(|e| SbError::CustomMessage(format!("getAccountInfo: {}", e)))?;
+    decode_oracle_timestamp(&data)
+}
+
+fn decode_oracle_timestamp(data: &[u8]) -> Result<i64, SbError> {
+    let len = 8 + std::mem::size_of::<MyOracleState>();
+    if data.len() < len || data[..8] != MyOracleState::DISCRIMINATOR {
+        return Err(SbError::CustomMessage("oracle account is not a MyOracleState".to_string()));
+    }
+    let state: &MyOracleState = bytemuck::from_bytes(&data[8..len]);
+    Ok({ state.usdy_usd.oracle_timestamp })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oracle_timestamps_are_decoded() {
+        let mut state: MyOracleState = bytemuck::Zeroable::zeroed();
+        state.usdy_usd.oracle_timestamp = 1_700_000_000;
+        let data = [&MyOracleState::DISCRIMINATOR[..], bytemuck::bytes_of(&state)].concat();
+        assert_eq!(decode_oracle_timestamp(&data).unwrap(), 1_700_000_000);
+
+        assert!(decode_oracle_timestamp(&data[..data.len() - 1]).is_err());
+        let mut wrong_account = data.clone();
+        wrong_account[0] ^= 1;
+        assert!(decode_oracle_timestamp(&wrong_account).is_err());
+    }
+
+    #[test]
+    fn configured_reference_feeds_are_passed_last() {
+        let feeds = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut state: MyProgramState = bytemuck::Zeroable::zeroed();
+        state.reference_feeds[..2].copy_from_slice(&feeds);
+        state.num_reference_feeds = 2;
+        let data = [&MyProgramState::DISCRIMINATOR[..], bytemuck::bytes_of(&state)].concat();
+        let decoded = decode_program_state(&data).unwrap();
+        assert_eq!(decoded.reference_feeds(), &feeds);
+        // accounts created before reference feeds were added have none
+        assert!(decode_program_state(&data[..data.len() - 1]).is_none());
+
+        let onchain = OnchainAccounts {
+            reference_feeds: feeds.to_vec(),
+            ..Default::default()
+        };
+        let accounts =
+            refresh_accounts(&Pubkey::new_unique(), &Pubkey::new_unique(), None, &onchain);
+        assert_eq!(accounts.len(), 8 + feeds.len());
+        assert_eq!(accounts[8], AccountMeta::new_readonly(feeds[0], false));
+        assert_eq!(accounts[9], AccountMeta::new_readonly(feeds[1], false));
+    }
+
+    #[test]
+    fn missing_optional_accounts_are_not_passed() {
+        let function = Pubkey::new_unique();
+        let derived = OracleAccounts::derive(&function);
+        let signer = Pubkey::new_unique();
+
+        let accounts = refresh_accounts(&function, &signer, None, &OnchainAccounts::default());
+        assert_eq!(accounts[4], AccountMeta::new_readonly(usdy_usd_oracle::ID, false));
+        assert_eq!(accounts[5], AccountMeta::new_readonly(usdy_usd_oracle::ID, false));
+        assert_eq!(accounts[6], AccountMeta::new_readonly(usdy_usd_oracle::ID, false));
+        assert_eq!(accounts[7], AccountMeta::new_readonly(usdy_usd_oracle::ID, false));
+
+        let onchain = OnchainAccounts {
+            feeds_exist: true,
+            symbol_table_exists: true,
+            ..Default::default()
+        };
+        let accounts = refresh_accounts(&function, &signer, None, &onchain);
+        assert_eq!(accounts[4], AccountMeta::new(derived.ondo_price_feed, false));
+        assert_eq!(accounts[5], AccountMeta::new(derived.ondo_traded_feed, false));
+        assert_eq!(accounts[6], AccountMeta::new(derived.symbol_table, false));
+    }
+
+    #[test]
+    fn request_runs_pass_their_request() {
+        let request = Pubkey::new_unique();
+        let onchain = OnchainAccounts {
+            reference_feeds: vec![Pubkey::new_unique()],
+            ..Default::default()
+        };
+        let accounts =
+            refresh_accounts(&Pubkey::new_unique(), &Pubkey::new_unique(), Some(&request), &onchain);
+        // after the symbol table and before the reference feeds
+        assert_eq!(accounts[7], AccountMeta::new_readonly(request, false));
+        assert_eq!(accounts.len(), 9);
+    }
+}
@@ -5,9 +5,39 @@ use std::future::Future;
 use std::pin::Pin;
 pub use switchboard_solana::prelude::*;
 pub mod etherprices;
-
 pub use etherprices::*;
+
+pub mod config;
+pub use config::*;
+
+pub mod sources;
+pub use sources::*;
+
+pub mod math;
+pub use math::*;
+
+pub mod aggregation;
+pub use aggregation::*;
+
+pub mod rpc;
+pub use rpc::*;
+
+pub mod recording;
+pub use recording::*;
+
+pub mod block;
+pub use block::*;
+
+#[cfg(feature = "dry-run")]
+pub mod dry_run;
+
+#[cfg(test)]
+mod mock_server;
+
+#[cfg(test)]
+mod mock_evm;
 use std::str::FromStr;
+#[cfg(not(feature = "dry-run"))]
 use switchboard_solana::switchboard_function;
 use switchboard_utils;
 use switchboard_utils::FromPrimitive;
@@ -19,140 +49,498 @@ use ethers::types::I256;
 
 use ethers_contract_derive::abigen;
 
+use usdy_usd_oracle::{FunctionRequestParams, TradingSymbol};
+
 declare_id!("2LuPhyrumCFRXjeDuYp1bLNYp7EbzUraZcvrzN9ZBUkN");
 
 pub const PROGRAM_SEED: &[u8] = b"USDY_USDC_ORACLE_V2";
 
 pub const ORACLE_SEED: &[u8] = b"ORACLE_USDY_SEED_V2";
-//
-#[account(zero_copy(unsafe))]
-pub struct MyProgramState {
-    pub bump: u8,
-    pub authority: Pubkey,
-    pub switchboard_function: Pubkey,
-    pub btc_price: f64,
-}
-async fn get_uniswap_price(
-    ether_transport: ethers::providers::Provider<ethers::providers::Http>,
-    factory_addr: ethers::types::H160,
-    usd_h160: ethers::types::H160,
-    usdy_h160: ethers::types::H160,
-) -> Result<Decimal, SbError> {
-    abigen!(Factory, "./src/factory.json");
-    let factory_contract = Factory::new(factory_addr, ether_transport.clone().into());
-
-    let pool = factory_contract
-        .get_pool(usd_h160, usdy_h160, 500)
-        .call()
-        .await;
-
-    println!("pool: {:?}", &pool);
-
-    abigen!(Pool, "./src/pool.json");
-    let pool_contract = Pool::new(pool.unwrap(), ether_transport.into());
-    let slot0 = pool_contract.slot_0().call().await.unwrap();
-    //    sqrtPriceX96 = sqrt(price) * 2 ** 96
 
-    let sqrt_price_x96: ethers::types::U256 = slot0.0;
-    let price: ethers::types::U256 = (sqrt_price_x96 * sqrt_price_x96) >> (96 * 2);
-
-    let inverse_price: f64 = 0.000001 / (price.as_u128() as f64);
-    let inverse_price = 1.0 / inverse_price;
-    let inverse_price = 1_000_000_000_000_000_000.0 / inverse_price * 1_000_000_000_000_000_000.0;
-    println!("Uniswap price: {:?}", &inverse_price);
-    Ok(Decimal::from_f64(inverse_price).unwrap())
-}
-// future
-async fn get_ondo_price(
-    ether_transport: ethers::providers::Provider<ethers::providers::Http>,
-) -> Result<Decimal, SbError> {
-    abigen!(Ondo, "./src/ondo.json");
-    let ondo = Ondo::new(
-        ethers::types::H160::from_str("0xa0219aa5b31e65bc920b5b6dfb8edf0988121de0").unwrap(),
-        ether_transport.clone().into(),
-    );
-
-    let price = ondo.get_price().call().await.unwrap();
-    let price: u128 = price.as_u128();
-    println!("Ondo price: {:?}", price);
-    // return Pin<Box<dyn Future<Output = Result<Decimal, SbError>>>> {
-    Ok(Decimal::from_u128(price).unwrap())
-}
-#[switchboard_function]
+// dry-run builds replace the enclave entrypoint with a local run, see dry_run.rs
+#[cfg_attr(not(feature = "dry-run"), switchboard_function)]
 pub async fn etherprices_oracle_function(
     runner: FunctionRunner,
-    _params: Vec<u8>,
+    params: Vec<u8>,
 ) -> Result<Vec<Instruction>, SbFunctionError> {
     msg!("etherprices_oracle_function");
 
-    let mantle_transport =
-        ethers::providers::Provider::try_from("https://mantle.publicnode.com").unwrap();
-    let ether_transport =
-        ethers::providers::Provider::try_from("https://ethereum.publicnode.com").unwrap();
-    let agni_factory =
-        ethers::types::H160::from_str("0x25780dc8Fc3cfBD75F33bFDAB65e969b603b2035").unwrap();
-
-    let fusion_factory =
-        ethers::types::H160::from_str("0x530d2766D1988CC1c000C8b7d00334c14B69AD71").unwrap();
-    let usdy_h160 =
-        ethers::types::H160::from_str("0x5bE26527e817998A7206475496fDE1E68957c5A6").unwrap();
-    let usd_h160 =
-        ethers::types::H160::from_str("0x09Bc4E0D864854c6aFB6eB9A9cdF58aC190D0dF9").unwrap();
-
-    let v: Vec<Pin<Box<dyn Future<Output = Result<Decimal, SbError>> + Send>>> = vec![
-        Box::pin(get_uniswap_price(
-            mantle_transport.clone(),
-            agni_factory,
-            usd_h160,
-            usdy_h160,
-        )),
-        Box::pin(get_uniswap_price(
-            mantle_transport.clone(),
-            fusion_factory,
-            usd_h160,
-            usdy_h160,
-        )),
-        Box::pin(get_ondo_price(ether_transport.clone())),
-    ];
-    let usdy_decimals: Vec<Decimal> = join_all(v).await.into_iter().map(|x| x.unwrap()).collect();
-    let first_two = usdy_decimals[0..2].to_vec();
-    let ondo_price = usdy_decimals.last().unwrap() / Decimal::from(1_000_000_000 as u64);
-    let usdy_decimals_divided_by_e18 = first_two
-        .into_iter()
-        .map(|x| x / Decimal::from(1_000_000_000_000_000_000 as u64))
-        .collect::<Vec<Decimal>>();
-    let usdy_e18s_f64s = usdy_decimals_divided_by_e18
-        .into_iter()
-        .map(|x| f64::from_str(&x.to_string()).unwrap())
-        .collect::<Vec<f64>>();
-    let usdy_mean = statistical::mean(&usdy_e18s_f64s);
-
-    
-    let usdy_mean = Decimal::from_f64(usdy_mean).unwrap()
-        * Decimal::from(1_000_000_000 as u64 );
-
-    
-    println!("usdy_mean: {:?}", usdy_mean);
+    let config = FunctionConfig::load().map_err(|e| {
+        println!("invalid config: {:?}", e);
+        Error::InvalidConfig
+    })?;
+    // RPC_RECORD_PATH records every upstream request of the run, RPC_REPLAY_PATH replays them
+    let tape = RpcTape::from_env().map_err(|e| {
+        println!("invalid rpc recording: {:?}", e);
+        Error::InvalidConfig
+    })?;
+    // lets the dry run replay the recording as the same function, see dry_run.rs
+    tape.record_runner(&runner.function, runner.function_request_key.as_ref());
+    let result = refresh_instructions(&runner, config, &params, &tape).await;
+    // failed runs are the ones worth replaying, so they're saved too
+    if let Err(e) = tape.save() {
+        println!("failed to save the rpc recording: {:?}", e);
+    }
+    result
+}
+
+/// Reads every source of `config`, aggregates the prices and builds the instructions that
+/// report them, narrowed down by the request `params`. Upstream requests go through `tape`.
+pub async fn refresh_instructions(
+    runner: &FunctionRunner,
+    mut config: FunctionConfig,
+    params: &[u8],
+    tape: &RpcTape,
+) -> Result<Vec<Instruction>, SbFunctionError> {
+    // scheduled runs have no params and do the full job, requests can narrow it down
+    let request = FunctionRequestParams::decode(params)
+        .map_err(|e| {
+            println!("invalid request params: {:?}", e);
+            Error::InvalidParams
+        })?
+        .latest();
+    println!("request: {:?}", request);
+    if !request.includes(TradingSymbol::Usdy_usdc) {
+        println!("{} was not requested, nothing to refresh", USDY_USDC);
+        return Ok(vec![]);
+    }
+
+    config.apply_request(&request.source_overrides).map_err(|e| {
+        println!("invalid request source overrides: {:?}", e);
+        Error::InvalidParams
+    })?;
+
+    let providers = build_providers(&config, tape).map_err(|e| {
+        println!("failed to build providers: {:?}", e);
+        Error::InvalidConfig
+    })?;
+    let solana: SolanaClient = tape.solana_client(&runner.client);
+    let sources = build_sources(&config, &providers, &solana, tape);
+
+    // every read on a chain is issued at the same block so prices can't mix chain states
+    let blocks = pin_blocks(&config, &providers, &solana, tape).await;
+    for (chain, block) in blocks.iter() {
+        println!("{:?} pinned to {:?}", chain, block);
+    }
+
+    let source_timeout = config.rpc.source_timeout();
+    let results = join_all(sources.iter().map(|source| {
+        let block = blocks[&source.chain()].clone();
+        async move {
+            match block {
+                Ok(block) => tokio::time::timeout(source_timeout, source.fetch(block))
+                    .await
+                    .unwrap_or(Err(SourceError::Timeout(source_timeout))),
+                Err(e) => Err(e),
+            }
+        }
+    }))
+    .await;
+    let mut nav_quotes: Vec<(String, Quote)> = vec![];
+    let mut market_quotes: Vec<(String, Quote)> = vec![];
+    let mut succeeded: Vec<SourceKind> = vec![];
+    // the block each successful source read at, in config order
+    let mut read_blocks: Vec<(SourceKind, Chain, PinnedBlock)> = vec![];
+    for (source, result) in sources.iter().zip(results) {
+        let quote = match result {
+            Ok(quote) => quote,
+            Err(e) => {
+                println!(
+                    "{} ({:?} on {:?}) failed: {}",
+                    source.name(),
+                    source.kind(),
+                    source.chain(),
+                    e
+                );
+                continue;
+            }
+        };
+        succeeded.push(source.kind());
+        // off-chain sources read at the run's clock, block 0
+        if let Some(Ok(block)) = blocks.get(&source.chain()) {
+            read_blocks.push((source.kind(), source.chain(), *block));
+        }
+        println!(
+            "{} ({:?} on {:?}): {} {:?}",
+            source.name(),
+            source.kind(),
+            source.chain(),
+            quote.price,
+            quote.flags
+        );
+        match source.kind() {
+            SourceKind::Nav => nav_quotes.push((source.name().to_string(), quote)),
+            SourceKind::Market => market_quotes.push((source.name().to_string(), quote)),
+        }
+    }
+    config.quorum.check(&succeeded).map_err(|e| {
+        println!("quorum not met: {:?}", e);
+        Error::QuorumNotMet
+    })?;
+
+    let symbol_config = config.symbol(USDY_USDC);
+    let nav = symbol_config.nav.aggregate(&nav_quotes).map_err(|e| {
+        println!("failed to aggregate nav quotes: {:?}", e);
+        Error::InvalidResult
+    })?;
+    let market = symbol_config.market.aggregate(&market_quotes).map_err(|e| {
+        println!("failed to aggregate market quotes: {:?}", e);
+        Error::InvalidResult
+    })?;
+    for (kind, aggregate) in [("nav", &nav), ("market", &market)] {
+        println!(
+            "{} {}: {} using {:?}, rejected {:?}",
+            USDY_USDC, kind, aggregate.price, aggregate.strategy, aggregate.rejected
+        );
+    }
+
+    // on-chain prices are fixed point numbers with PRICE_SCALE decimals
+    let price_scale = Decimal::from(10_u64.pow(usdy_usd_oracle::PRICE_SCALE));
+    let ondo_price = nav.price * price_scale;
+    let usdy_mean = market.price * price_scale;
 
     println!("USDY Mean: {}", usdy_mean);
     msg!("sending transaction");
     println!("Ondo price: {:?}", ondo_price);
     // Finally, emit the signed quote and partially signed transaction to the functionRunner oracle
     // The functionRunner oracle will use the last outputted word to stdout as the serialized result. This is what gets executed on-chain.
+    // prices are as old as the oldest read, on-chain or not, and each price reports the
+    // block of the first on-chain source of its kind
+    let oracle_timestamp = oldest_read(&read_blocks).ok_or(Error::InvalidResult)?;
+    let age = tape.now().timestamp - oracle_timestamp;
+    if request.max_age_seconds > 0 && age > request.max_age_seconds as i64 {
+        println!(
+            "prices are {}s old, the request allows {}s",
+            age, request.max_age_seconds
+        );
+        return Err(Error::StaleResult.into());
+    }
+    if !request.force {
+        // nothing to push if the oracle already holds prices at least this new
+        match onchain_oracle_timestamp(&solana).await {
+            Ok(onchain) if onchain >= oracle_timestamp => {
+                println!(
+                    "oracle already holds prices from {}, read prices from {}, skipping",
+                    onchain, oracle_timestamp
+                );
+                return Ok(vec![]);
+            }
+            Ok(_) => {}
+            Err(e) => println!("failed to read the oracle, pushing anyway: {:?}", e),
+        }
+    }
+    // a price read only off-chain has no block to report, and block 0 would claim one
+    let block_of = |kind: SourceKind| {
+        first_onchain_block(&read_blocks, kind).ok_or_else(|| {
+            println!("no on-chain {:?} source succeeded, there is no block to report", kind);
+            Error::InvalidResult
+        })
+    };
+
+    let ondo_price = ToPrimitive::to_u128(&ondo_price).ok_or(Error::InvalidResult)?;
+    let usdy_mean = ToPrimitive::to_u128(&usdy_mean).ok_or(Error::InvalidResult)?;
     let etherprices = EtherPrices::fetch(
-        // implement error handling and map_err
-        ethers::types::U256::from(ToPrimitive::to_u128(&ondo_price).unwrap()),
-        ethers::types::U256::from(ToPrimitive::to_u128(&usdy_mean).unwrap()),
-        
+        ethers::types::U256::from(ondo_price),
+        ethers::types::U256::from(usdy_mean),
+        oracle_timestamp,
+        block_of(SourceKind::Nav)?,
+        block_of(SourceKind::Market)?,
     )
     .await
-    .unwrap();
-    println!("1");
-    let ixs: Vec<Instruction> = etherprices.to_ixns(&runner);
+    .map_err(|e| {
+        println!("failed to build prices: {:?}", e);
+        Error::InvalidResult
+    })?;
+    // the accounts the refresh must be passed depend on how the program is configured
+    let onchain = OnchainAccounts::load(&solana, &OracleAccounts::derive(&runner.function))
+        .await
+        .map_err(|e| {
+            println!("failed to read the oracle program accounts: {:?}", e);
+            Error::InvalidResult
+        })?;
+    let ixs: Vec<Instruction> = etherprices.to_ixns(runner, &onchain).map_err(|e| {
+        println!("failed to build instructions: {:?}", e);
+        Error::InvalidResult
+    })?;
     Ok(ixs)
 }
 
+/// Timestamp of the oldest of `read_blocks`, off-chain reads included.
+fn oldest_read(read_blocks: &[(SourceKind, Chain, PinnedBlock)]) -> Option<i64> {
+    read_blocks.iter().map(|(_, _, block)| block.timestamp).min()
+}
+
+/// Block of the first on-chain source of `kind` in `read_blocks`.
+fn first_onchain_block(read_blocks: &[(SourceKind, Chain, PinnedBlock)], kind: SourceKind) -> Option<u64> {
+    read_blocks
+        .iter()
+        .find(|(k, chain, _)| *k == kind && *chain != Chain::Offchain)
+        .map(|(_, _, block)| block.number)
+}
+
+#[cfg(feature = "dry-run")]
+#[tokio::main(worker_threads = 12)]
+async fn main() {
+    if let Err(e) = dry_run::run().await {
+        println!("dry run failed: {:?}", e);
+        std::process::exit(1);
+    }
+}
+
 #[sb_error]
 pub enum Error {
     InvalidResult,
-}
\ No newline at end of file
+    InvalidConfig,
+    QuorumNotMet,
+    InvalidParams,
+    StaleResult,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_evm::EvmFixture;
+    use crate::mock_server::{MockServer, Reply};
+    use serde_json::{json, Value};
+    use solana_client::rpc_client::RpcClient;
+    use usdy_usd_oracle::{
+        FunctionRequestParamsV1, MyOracleState, OracleDataWithTradingSymbol, RefreshOraclesBatchParams,
+    };
+
+    const MANTLE: &str = include_str!("./fixtures/mantle.json");
+    const ETHEREUM: &str = include_str!("./fixtures/ethereum.json");
+    const ONDO_ORACLE: &str = "0xa0219aa5b31e65bc920b5b6dfb8edf0988121de0";
+
+    /// A runner whose Solana client reads the oracle account from `oracle`, or finds no
+    /// account when `None`.
+    async fn runner(oracle: Option<Vec<u8>>) -> (FunctionRunner, MockServer) {
+        static ENV: std::sync::Once = std::sync::Once::new();
+        ENV.call_once(|| {
+            let function_data: String = bytemuck::bytes_of(&FunctionAccountData::default())
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            std::env::set_var("FUNCTION_KEY", Pubkey::new_unique().to_string());
+            std::env::set_var("FUNCTION_DATA", function_data);
+            for name in ["PAYER", "VERIFIER", "REWARD_RECEIVER"] {
+                std::env::set_var(name, Pubkey::default().to_string());
+            }
+        });
+
+        let account = oracle.map(|data| {
+            json!({
+                "lamports": 1_000_000,
+                "data": [anchor_lang::__private::base64::encode(&data), "base64"],
+                "owner": usdy_usd_oracle::ID.to_string(),
+                "executable": false,
+                "rentEpoch": 0,
+                "space": data.len(),
+            })
+        });
+        let solana = MockServer::start(move |method, params| match method {
+            "getVersion" => Reply::Result(json!({"solana-core": "1.16.14", "feature-set": 0})),
+            "getAccountInfo" => Reply::Result(json!({"context": {"slot": 1}, "value": account})),
+            // the program's other accounts don't exist
+            "getMultipleAccounts" => {
+                let keys = params[0].as_array().map(Vec::len).unwrap_or_default();
+                Reply::Result(json!({"context": {"slot": 1}, "value": vec![Value::Null; keys]}))
+            }
+            _ => Reply::Error {
+                code: -32601,
+                message: format!("method {} not found", method),
+            },
+        })
+        .await;
+        let runner = FunctionRunner::new_with_client(RpcClient::new(solana.url.clone())).unwrap();
+        (runner, solana)
+    }
+
+    fn config(mantle: &MockServer, ethereum: &MockServer) -> FunctionConfig {
+        let mut config = FunctionConfig::from_toml(include_str!("./function.toml")).unwrap();
+        config.mantle.rpc_urls = vec![mantle.url.clone()];
+        config.ethereum.rpc_urls = vec![ethereum.url.clone()];
+        config.rpc = RpcConfig {
+            timeout_ms: 2_000,
+            max_retries: 0,
+            backoff_ms: 0,
+            source_timeout_ms: 10_000,
+        };
+        config
+    }
+
+    fn request(params: FunctionRequestParamsV1) -> Vec<u8> {
+        FunctionRequestParams::V1(params).encode().unwrap()
+    }
+
+    fn rows(ixs: &[Instruction]) -> Vec<OracleDataWithTradingSymbol> {
+        assert_eq!(ixs.len(), 1);
+        RefreshOraclesBatchParams::try_from_slice(&ixs[0].data[8..])
+            .unwrap()
+            .rows()
+            .collect()
+    }
+
+    fn assert_error(result: Result<Vec<Instruction>, SbFunctionError>, expected: Error) {
+        let error = result.unwrap_err();
+        assert_eq!(error.as_u8(), SbFunctionError::from(expected).as_u8());
+    }
+
+    #[tokio::test]
+    async fn refreshes_the_oracle_from_fixtures() {
+        let mantle = EvmFixture::load(MANTLE).serve().await;
+        let ethereum = EvmFixture::load(ETHEREUM).serve().await;
+        let (runner, _solana) = runner(None).await;
+
+        let ixs = refresh_instructions(&runner, config(&mantle, &ethereum), &[], &RpcTape::Off)
+            .await
+            .unwrap();
+        let rows = rows(&ixs);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].symbol, TradingSymbol::Usdy_usdc);
+
+        // the last Ondo range has ended, so the NAV stays at its close
+        assert_eq!(rows[0].data.ondo_price, 1_056_027_460);
+        // both factories hold pools at the same 30 minute mean tick
+        let traded_price = sqrt_price_x96_to_price(sqrt_ratio_at_tick(275836).unwrap(), 6, 18, false)
+            .unwrap()
+            * Decimal::from(10_u64.pow(usdy_usd_oracle::PRICE_SCALE));
+        assert_eq!(rows[0].data.traded_price, traded_price.to_u64().unwrap());
+        // the oldest block read is Mantle's
+        assert_eq!(rows[0].data.oracle_timestamp, 1_720_000_000);
+        assert_eq!(rows[0].data.ondo_block, 20_000_000);
+        assert_eq!(rows[0].data.traded_block, 66_000_000);
+    }
+
+    #[tokio::test]
+    async fn fails_without_quorum() {
+        let (runner, _solana) = runner(None).await;
+
+        // getPrice() off the accrual ranges rejects the only nav source
+        let mantle = EvmFixture::load(MANTLE).serve().await;
+        let mut ethereum = EvmFixture::load(ETHEREUM);
+        ethereum.set(ONDO_ORACLE, "getPrice() returns (uint256)", &[], &["1056027470000000000"]);
+        let ethereum = ethereum.serve().await;
+        let result =
+            refresh_instructions(&runner, config(&mantle, &ethereum), &[], &RpcTape::Off).await;
+        assert_error(result, Error::QuorumNotMet);
+
+        // every market source is on an unavailable chain
+        let mantle = MockServer::start(|_, _| Reply::Status(503)).await;
+        let ethereum = EvmFixture::load(ETHEREUM).serve().await;
+        let result =
+            refresh_instructions(&runner, config(&mantle, &ethereum), &[], &RpcTape::Off).await;
+        assert_error(result, Error::QuorumNotMet);
+    }
+
+    #[tokio::test]
+    async fn rejects_prices_older_than_requested() {
+        let mantle = EvmFixture::load(MANTLE).serve().await;
+        let ethereum = EvmFixture::load(ETHEREUM).serve().await;
+        let (runner, _solana) = runner(None).await;
+
+        let params = request(FunctionRequestParamsV1 {
+            max_age_seconds: 1,
+            ..Default::default()
+        });
+        let result =
+            refresh_instructions(&runner, config(&mantle, &ethereum), &params, &RpcTape::Off).await;
+        assert_error(result, Error::StaleResult);
+    }
+
+    #[tokio::test]
+    async fn skips_prices_the_oracle_already_holds() {
+        let mantle = EvmFixture::load(MANTLE).serve().await;
+        let ethereum = EvmFixture::load(ETHEREUM).serve().await;
+        let mut state: MyOracleState = bytemuck::Zeroable::zeroed();
+        state.usdy_usd.oracle_timestamp = 1_720_000_001;
+        let oracle = [&MyOracleState::DISCRIMINATOR[..], bytemuck::bytes_of(&state)].concat();
+        let (runner, _solana) = runner(Some(oracle)).await;
+
+        let ixs = refresh_instructions(&runner, config(&mantle, &ethereum), &[], &RpcTape::Off)
+            .await
+            .unwrap();
+        assert!(ixs.is_empty());
+
+        let params = request(FunctionRequestParamsV1 {
+            force: true,
+            ..Default::default()
+        });
+        let ixs = refresh_instructions(&runner, config(&mantle, &ethereum), &params, &RpcTape::Off)
+            .await
+            .unwrap();
+        assert_eq!(rows(&ixs)[0].data.oracle_timestamp, 1_720_000_000);
+    }
+
+    #[tokio::test]
+    async fn request_runs_are_signed_for_their_request() {
+        let mantle = EvmFixture::load(MANTLE).serve().await;
+        let ethereum = EvmFixture::load(ETHEREUM).serve().await;
+        let (mut runner, _solana) = runner(None).await;
+        let function_request = Pubkey::new_unique();
+        runner.function_request_key = Some(function_request);
+
+        let params = request(FunctionRequestParamsV1 {
+            symbols: vec![TradingSymbol::Usdy_usdc],
+            ..Default::default()
+        });
+        let ixs = refresh_instructions(&runner, config(&mantle, &ethereum), &params, &RpcTape::Off)
+            .await
+            .unwrap();
+        assert_eq!(rows(&ixs)[0].symbol, TradingSymbol::Usdy_usdc);
+        // the program validates the enclave signer against the request, see request.rs there
+        assert_eq!(ixs[0].accounts[3], AccountMeta::new_readonly(runner.signer, true));
+        assert_eq!(
+            ixs[0].accounts[7],
+            AccountMeta::new_readonly(function_request, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn replays_a_recorded_run() {
+        let mantle = EvmFixture::load(MANTLE).serve().await;
+        let ethereum = EvmFixture::load(ETHEREUM).serve().await;
+        let (runner, solana) = runner(None).await;
+
+        let recorder = RpcTape::recorder(None);
+        let recorded = refresh_instructions(&runner, config(&mantle, &ethereum), &[], &recorder)
+            .await
+            .unwrap();
+        let recording = recorder.recording();
+        assert!(recording
+            .records
+            .iter()
+            .any(|record| record.method == "eth_call" && record.block == Some(66_000_000)));
+        assert!(recording.records.iter().any(|record| record.chain == Chain::Solana));
+
+        // nothing reaches the nodes while replaying
+        let requests = (mantle.requests(), ethereum.requests(), solana.requests());
+        let mut offline = config(&mantle, &ethereum);
+        offline.mantle.rpc_urls = vec!["http://127.0.0.1:1".to_string()];
+        offline.ethereum.rpc_urls = vec!["http://127.0.0.1:1".to_string()];
+        let replayed = refresh_instructions(&runner, offline, &[], &RpcTape::replayer(recording))
+            .await
+            .unwrap();
+        assert_eq!(replayed[0].data, recorded[0].data);
+        assert_eq!(
+            (mantle.requests(), ethereum.requests(), solana.requests()),
+            requests
+        );
+    }
+
+    #[test]
+    fn off_chain_reads_count_toward_the_timestamp_but_report_no_block() {
+        let block = |number, timestamp| PinnedBlock { number, timestamp };
+        let read_blocks = [
+            (SourceKind::Market, Chain::Offchain, block(0, 1_699_999_000)),
+            (SourceKind::Market, Chain::Mantle, block(66_000_000, 1_700_000_000)),
+            (SourceKind::Nav, Chain::Ethereum, block(20_000_000, 1_699_999_990)),
+        ];
+        assert_eq!(oldest_read(&read_blocks), Some(1_699_999_000));
+        assert_eq!(first_onchain_block(&read_blocks, SourceKind::Market), Some(66_000_000));
+        assert_eq!(first_onchain_block(&read_blocks, SourceKind::Nav), Some(20_000_000));
+
+        // a CEX quote alone has no block to report
+        assert_eq!(first_onchain_block(&read_blocks[..1], SourceKind::Market), None);
+        assert_eq!(oldest_read(&[]), None);
+    }
+}
@@ -0,0 +1,450 @@
+use crate::*;
+use ethers::types::{U256, U512};
+
+/// Number of decimal places in prices returned by `sqrt_price_x96_to_price`.
+///
+/// The conversion is exact up to this precision: the result is the true price
+/// truncated (rounded toward zero) to 18 decimals.
+pub const PRICE_PRECISION: u32 = 18;
+
+/// Converts a Uniswap V3 `sqrtPriceX96` into the human price of the base token
+/// quoted in the other token of the pool.
+///
+/// `sqrtPriceX96 = sqrt(token1 / token0) * 2^96` in raw token units, so the raw price
+/// of token0 in token1 is `sqrtPriceX96^2 / 2^192`. Scaling by the token decimals gives
+/// the human price, which is inverted when the base token is token1.
+///
+/// All intermediate values are computed in U512 so nothing is lost before the final
+/// division. Fails if the price is zero or too large for a `Decimal` at
+/// `PRICE_PRECISION` (around 7.9e10).
+pub fn sqrt_price_x96_to_price(
+    sqrt_price_x96: U256,
+    token0_decimals: u8,
+    token1_decimals: u8,
+    base_is_token0: bool,
+) -> Result<Decimal, SbError> {
+    sqrt_price_to_price(sqrt_price_x96, 96, token0_decimals, token1_decimals, base_is_token0)
+}
+
+/// Converts the Q64.64 `sqrt_price` of an Orca Whirlpool or Raydium CLMM pool into the
+/// human price of the base token, like `sqrt_price_x96_to_price`.
+///
+/// `sqrt_price = sqrt(token_b / token_a) * 2^64` in raw token units, with token a (mint 0
+/// on Raydium) in the role of Uniswap's token0.
+pub fn sqrt_price_x64_to_price(
+    sqrt_price_x64: u128,
+    token_a_decimals: u8,
+    token_b_decimals: u8,
+    base_is_token_a: bool,
+) -> Result<Decimal, SbError> {
+    sqrt_price_to_price(
+        U256::from(sqrt_price_x64),
+        64,
+        token_a_decimals,
+        token_b_decimals,
+        base_is_token_a,
+    )
+}
+
+/// Price of the base token from a square root price with `fraction_bits` fractional bits.
+fn sqrt_price_to_price(
+    sqrt_price: U256,
+    fraction_bits: usize,
+    token0_decimals: u8,
+    token1_decimals: u8,
+    base_is_token0: bool,
+) -> Result<Decimal, SbError> {
+    if sqrt_price.is_zero() {
+        return Err(SbError::CustomMessage("sqrt price is zero".to_string()));
+    }
+    if token0_decimals > 36 || token1_decimals > 36 {
+        return Err(SbError::CustomMessage("token decimals out of range".to_string()));
+    }
+
+    let sqrt_price = U512::from(sqrt_price);
+    let price_q = sqrt_price * sqrt_price;
+    let q = U512::one() << (2 * fraction_bits);
+    let precision = U512::exp10(PRICE_PRECISION as usize);
+    let scale0 = U512::exp10(token0_decimals as usize);
+    let scale1 = U512::exp10(token1_decimals as usize);
+
+    let mantissa = if base_is_token0 {
+        // token1 per token0 = price_q / q * 10^dec0 / 10^dec1
+        price_q * scale0 * precision / (q * scale1)
+    } else {
+        // token0 per token1 = q / price_q * 10^dec1 / 10^dec0
+        q * scale1 * precision / (price_q * scale0)
+    };
+
+    mantissa_to_price(mantissa)
+}
+
+/// Converts a price scaled by `10^PRICE_PRECISION` into a `Decimal`.
+fn mantissa_to_price(mantissa: U512) -> Result<Decimal, SbError> {
+    if mantissa > U512::from(i128::MAX as u128) {
+        return Err(SbError::CustomMessage("price overflows Decimal".to_string()));
+    }
+    Decimal::try_from_i128_with_scale(mantissa.as_u128() as i128, PRICE_PRECISION)
+        .map_err(|_| SbError::CustomMessage("price overflows Decimal".to_string()))
+}
+
+/// Reserves of a pool normalized to `PRICE_PRECISION` decimals, as `(base, quote)`.
+fn normalized_reserves(
+    reserve_base: U256,
+    reserve_quote: U256,
+    base_decimals: u8,
+    quote_decimals: u8,
+) -> Result<(U512, U512), SbError> {
+    if reserve_base.is_zero() || reserve_quote.is_zero() {
+        return Err(SbError::CustomMessage("pool has no reserves".to_string()));
+    }
+    if base_decimals > 36 || quote_decimals > 36 {
+        return Err(SbError::CustomMessage("token decimals out of range".to_string()));
+    }
+    let normalize = |reserve: U256, decimals: u8| {
+        let reserve = U512::from(reserve);
+        if decimals as u32 > PRICE_PRECISION {
+            reserve / U512::exp10(decimals as usize - PRICE_PRECISION as usize)
+        } else {
+            reserve * U512::exp10(PRICE_PRECISION as usize - decimals as usize)
+        }
+    };
+    Ok((
+        normalize(reserve_base, base_decimals),
+        normalize(reserve_quote, quote_decimals),
+    ))
+}
+
+/// Price of the base token in the quote token implied by swapping `amount_base` for
+/// `amount_quote`, truncated to `PRICE_PRECISION` decimals.
+pub fn exchange_rate(
+    amount_base: U256,
+    amount_quote: U256,
+    base_decimals: u8,
+    quote_decimals: u8,
+) -> Result<Decimal, SbError> {
+    let (x, y) = normalized_reserves(amount_base, amount_quote, base_decimals, quote_decimals)?;
+    mantissa_to_price(y * U512::exp10(PRICE_PRECISION as usize) / x)
+}
+
+/// Spot price of the base token in the quote token of a constant-product (`x * y = k`) pool,
+/// truncated to `PRICE_PRECISION` decimals.
+pub fn constant_product_price(
+    reserve_base: U256,
+    reserve_quote: U256,
+    base_decimals: u8,
+    quote_decimals: u8,
+) -> Result<Decimal, SbError> {
+    exchange_rate(reserve_base, reserve_quote, base_decimals, quote_decimals)
+}
+
+/// Spot price of the base token in the quote token of a Solidly stable pair, whose invariant
+/// is `x^3 * y + x * y^3 = k` over reserves normalized to 18 decimals.
+///
+/// The price is the marginal rate `-dy/dx = y * (3x^2 + y^2) / (x * (x^2 + 3y^2))`. Reserves
+/// above 2^128 are scaled down first so the cubes fit in U512, which only affects digits far
+/// past `PRICE_PRECISION`.
+pub fn stable_swap_price(
+    reserve_base: U256,
+    reserve_quote: U256,
+    base_decimals: u8,
+    quote_decimals: u8,
+) -> Result<Decimal, SbError> {
+    let (mut x, mut y) = normalized_reserves(reserve_base, reserve_quote, base_decimals, quote_decimals)?;
+    let bits = x.max(y).bits();
+    if bits > 128 {
+        x >>= bits - 128;
+        y >>= bits - 128;
+    }
+    if x.is_zero() || y.is_zero() {
+        return Err(SbError::CustomMessage("pool reserves are too imbalanced".to_string()));
+    }
+    let three = U512::from(3);
+    let numerator = y * (three * x * x + y * y);
+    let denominator = x * (x * x + three * y * y);
+    mantissa_to_price(numerator * U512::exp10(PRICE_PRECISION as usize) / denominator)
+}
+
+/// Converts a raw token amount into whole tokens, truncating past 28 decimals.
+pub fn token_amount(amount: U256, decimals: u8) -> Result<Decimal, SbError> {
+    raw_amount_to_decimal(U512::from(amount), decimals)
+}
+
+fn raw_amount_to_decimal(amount: U512, decimals: u8) -> Result<Decimal, SbError> {
+    if decimals > 36 {
+        return Err(SbError::CustomMessage("token decimals out of range".to_string()));
+    }
+    let (amount, scale) = if decimals > 28 {
+        (amount / U512::exp10(decimals as usize - 28), 28)
+    } else {
+        (amount, decimals as u32)
+    };
+    if amount > U512::from(i128::MAX as u128) {
+        return Err(SbError::CustomMessage(format!("amount {} overflows Decimal", amount)));
+    }
+    Decimal::try_from_i128_with_scale(amount.as_u128() as i128, scale)
+        .map_err(|_| SbError::CustomMessage(format!("amount {} overflows Decimal", amount)))
+}
+
+/// Depth of a concentrated liquidity pool in its quote token: twice the virtual reserve of
+/// the quote token at `sqrt_price`, in whole tokens.
+///
+/// With `L` the in-range liquidity and `sqrt_price` carrying `fraction_bits` fractional bits,
+/// the virtual reserves are `L * 2^bits / sqrt_price` of token0 and `L * sqrt_price / 2^bits`
+/// of token1. Doubling the quote side values both sides at the pool price, like the
+/// reserves of a constant-product pool, so depths compare across pools and sources.
+pub fn clmm_quote_depth(
+    liquidity: u128,
+    sqrt_price: U256,
+    fraction_bits: usize,
+    quote_is_token0: bool,
+    quote_decimals: u8,
+) -> Result<Decimal, SbError> {
+    if sqrt_price.is_zero() {
+        return Err(SbError::CustomMessage("sqrt price is zero".to_string()));
+    }
+    let liquidity = U512::from(liquidity);
+    let sqrt_price = U512::from(sqrt_price);
+    let reserve = if quote_is_token0 {
+        (liquidity << fraction_bits) / sqrt_price
+    } else {
+        (liquidity * sqrt_price) >> fraction_bits
+    };
+    Ok(raw_amount_to_decimal(reserve, quote_decimals)? * Decimal::TWO)
+}
+
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+
+/// Port of Uniswap V3's `TickMath.getSqrtRatioAtTick`, returning `sqrt(1.0001^tick) * 2^96`
+/// bit-for-bit identical to the on-chain library.
+pub fn sqrt_ratio_at_tick(tick: i32) -> Result<U256, SbError> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return Err(SbError::CustomMessage(format!("tick {} out of range", tick)));
+    }
+    let abs_tick = tick.unsigned_abs();
+
+    // each constant is 2^128 / sqrt(1.0001)^(2^i)
+    const RATIOS: [(u32, &str); 19] = [
+        (0x2, "fff97272373d413259a46990580e213a"),
+        (0x4, "fff2e50f5f656932ef12357cf3c7fdcc"),
+        (0x8, "ffe5caca7e10e4e61c3624eaa0941cd0"),
+        (0x10, "ffcb9843d60f6159c9db58835c926644"),
+        (0x20, "ff973b41fa98c081472e6896dfb254c0"),
+        (0x40, "ff2ea16466c96a3843ec78b326b52861"),
+        (0x80, "fe5dee046a99a2a811c461f1969c3053"),
+        (0x100, "fcbe86c7900a88aedcffc83b479aa3a4"),
+        (0x200, "f987a7253ac413176f2b074cf7815e54"),
+        (0x400, "f3392b0822b70005940c7a398e4b70f3"),
+        (0x800, "e7159475a2c29b7443b29c7fa6e889d9"),
+        (0x1000, "d097f3bdfd2022b8845ad8f792aa5825"),
+        (0x2000, "a9f746462d870fdf8a65dc1f90e061e5"),
+        (0x4000, "70d869a156d2a1b890bb3df62baf32f7"),
+        (0x8000, "31be135f97d08fd981231505542fcfa6"),
+        (0x10000, "9aa508b5b7a84e1c677de54f3e99bc9"),
+        (0x20000, "5d6af8dedb81196699c329225ee604"),
+        (0x40000, "2216e584f5fa1ea926041bedfe98"),
+        (0x80000, "48a170391f7dc42444e8fa2"),
+    ];
+
+    let mut ratio = if abs_tick & 0x1 != 0 {
+        U256::from_str_radix("fffcb933bd6fad37aa2d162d1a594001", 16).unwrap()
+    } else {
+        U256::one() << 128
+    };
+    for (bit, constant) in RATIOS.iter() {
+        if abs_tick & bit != 0 {
+            ratio = (ratio * U256::from_str_radix(constant, 16).unwrap()) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::max_value() / ratio;
+    }
+
+    // round up to 160 bits so the price never understates the tick
+    let remainder = ratio % (U256::one() << 32);
+    Ok((ratio >> 32) + if remainder.is_zero() { U256::zero() } else { U256::one() })
+}
+
+/// Arithmetic mean tick between two `tickCumulative` observations `seconds` apart,
+/// rounded toward negative infinity like Uniswap's `OracleLibrary.consult`.
+pub fn mean_tick(tick_cumulative_start: i64, tick_cumulative_end: i64, seconds: u32) -> i32 {
+    let delta = tick_cumulative_end - tick_cumulative_start;
+    let mut tick = delta / seconds as i64;
+    if delta < 0 && delta % seconds as i64 != 0 {
+        tick -= 1;
+    }
+    tick as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn unit_sqrt_price_is_one() {
+        let q96 = U256::one() << 96;
+        assert_eq!(sqrt_price_x96_to_price(q96, 18, 18, true).unwrap(), dec("1"));
+        assert_eq!(sqrt_price_x96_to_price(q96, 18, 18, false).unwrap(), dec("1"));
+    }
+
+    #[test]
+    fn usdy_as_token1_against_6_decimal_usdc() {
+        // token0 = USDC (6 decimals), token1 = USDY (18 decimals), USDY at 1.05 USDC
+        let sqrt_price = U256::from_dec_str("77318769577248808968632876404589386").unwrap();
+        assert_eq!(
+            sqrt_price_x96_to_price(sqrt_price, 6, 18, false).unwrap(),
+            dec("1.050000000000000000")
+        );
+
+        let sqrt_price = U256::from_dec_str("77318772577248808968632876404589386").unwrap();
+        assert_eq!(
+            sqrt_price_x96_to_price(sqrt_price, 6, 18, false).unwrap(),
+            dec("1.049999918519142663")
+        );
+    }
+
+    #[test]
+    fn usdy_as_token0_against_6_decimal_usdc() {
+        // token0 = USDY (18 decimals), token1 = USDC (6 decimals), USDY at 1.05 USDC
+        let sqrt_price = U256::from_dec_str("81184708056111249417065").unwrap();
+        assert_eq!(
+            sqrt_price_x96_to_price(sqrt_price, 18, 6, true).unwrap(),
+            dec("1.050000000000000000")
+        );
+    }
+
+    #[test]
+    fn results_are_truncated_to_precision() {
+        // sqrt(1.002001) * 2^96, a price just above 1.002
+        let sqrt_price = U256::from_dec_str("79307390676778601931137494286").unwrap();
+        assert_eq!(
+            sqrt_price_x96_to_price(sqrt_price, 18, 18, true).unwrap(),
+            dec("1.002000999999999999")
+        );
+        assert_eq!(
+            sqrt_price_x96_to_price(sqrt_price, 18, 18, false).unwrap(),
+            dec("0.998002996004994006")
+        );
+    }
+
+    #[test]
+    fn sqrt_ratio_matches_tick_math() {
+        assert_eq!(sqrt_ratio_at_tick(0).unwrap(), U256::one() << 96);
+        assert_eq!(sqrt_ratio_at_tick(MIN_TICK).unwrap(), U256::from(4295128739_u64));
+        assert_eq!(
+            sqrt_ratio_at_tick(MAX_TICK).unwrap(),
+            U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap()
+        );
+        assert_eq!(
+            sqrt_ratio_at_tick(1).unwrap(),
+            U256::from_dec_str("79232123823359799118286999568").unwrap()
+        );
+        assert_eq!(
+            sqrt_ratio_at_tick(-1).unwrap(),
+            U256::from_dec_str("79224201403219477170569942574").unwrap()
+        );
+        // around the USDC(6)/USDY(18) price range
+        assert_eq!(
+            sqrt_ratio_at_tick(276324).unwrap(),
+            U256::from_dec_str("79228057781537899283318961129827820").unwrap()
+        );
+        assert!(sqrt_ratio_at_tick(MAX_TICK + 1).is_err());
+    }
+
+    #[test]
+    fn mean_tick_rounds_toward_negative_infinity() {
+        assert_eq!(mean_tick(0, 1800 * 10, 1800), 10);
+        assert_eq!(mean_tick(0, 1800 * 10 + 1, 1800), 10);
+        assert_eq!(mean_tick(0, -1800 * 10, 1800), -10);
+        assert_eq!(mean_tick(0, -1800 * 10 - 1, 1800), -11);
+    }
+
+    #[test]
+    fn constant_product_prices() {
+        // 1,000,000 USDY (18 decimals) against 1,050,000 USDC (6 decimals)
+        let usdy = U256::exp10(24);
+        let usdc = U256::from(1_050_000_000_000_u64);
+        assert_eq!(constant_product_price(usdy, usdc, 18, 6).unwrap(), dec("1.05"));
+        assert_eq!(
+            constant_product_price(usdc, usdy, 6, 18).unwrap(),
+            dec("0.952380952380952380")
+        );
+        assert!(constant_product_price(U256::zero(), usdc, 18, 6).is_err());
+    }
+
+    #[test]
+    fn stable_swap_prices() {
+        let usdy = U256::exp10(24);
+        assert_eq!(
+            stable_swap_price(usdy, U256::from(1_000_000_000_000_u64), 18, 6).unwrap(),
+            dec("1")
+        );
+        // the stable curve is much flatter than x * y = k around the peg
+        assert_eq!(
+            stable_swap_price(usdy, U256::from(1_050_000_000_000_u64), 18, 6).unwrap(),
+            dec("1.000029019152640742")
+        );
+        assert_eq!(
+            stable_swap_price(usdy * 2, usdy, 18, 18).unwrap(),
+            dec("0.928571428571428571")
+        );
+        // reserves too large for the cubes to fit in U512 unscaled
+        let huge = U256::one() << 200;
+        assert_eq!(stable_swap_price(huge, huge, 18, 18).unwrap(), dec("1"));
+    }
+
+    #[test]
+    fn q64_sqrt_prices() {
+        let q64 = 1_u128 << 64;
+        assert_eq!(sqrt_price_x64_to_price(q64, 6, 6, true).unwrap(), dec("1"));
+
+        // floor(sqrt(1.05) * 2^64), USDY as token a against USDC, both 6 decimals
+        let sqrt_price = 18902287831555877210_u128;
+        assert_eq!(
+            sqrt_price_x64_to_price(sqrt_price, 6, 6, true).unwrap(),
+            dec("1.049999999999999999")
+        );
+        assert_eq!(
+            sqrt_price_x64_to_price(sqrt_price, 6, 6, false).unwrap(),
+            dec("0.952380952380952381")
+        );
+        assert!(sqrt_price_x64_to_price(0, 6, 6, true).is_err());
+    }
+
+    #[test]
+    fn clmm_depth_is_twice_the_virtual_quote_reserve() {
+        // at a raw price of 1 both virtual reserves equal the liquidity
+        let q96 = U256::one() << 96;
+        assert_eq!(clmm_quote_depth(5_000_000, q96, 96, true, 6).unwrap(), dec("10"));
+        assert_eq!(clmm_quote_depth(5_000_000, q96, 96, false, 6).unwrap(), dec("10"));
+
+        // token1 worth 4 token0: sqrt_price = 2^95, so reserve0 = 2L and reserve1 = L/2
+        let half = U256::one() << 95;
+        assert_eq!(clmm_quote_depth(1_000_000, half, 96, true, 6).unwrap(), dec("4"));
+        assert_eq!(clmm_quote_depth(1_000_000, half, 96, false, 6).unwrap(), dec("1"));
+
+        let q64 = U256::one() << 64;
+        assert_eq!(clmm_quote_depth(1_000_000, q64, 64, true, 6).unwrap(), dec("2"));
+        assert!(clmm_quote_depth(1, U256::zero(), 96, true, 6).is_err());
+    }
+
+    #[test]
+    fn token_amounts_are_scaled_by_decimals() {
+        assert_eq!(token_amount(U256::from(1_500_000), 6).unwrap(), dec("1.5"));
+        assert_eq!(token_amount(U256::exp10(36), 36).unwrap(), dec("1"));
+        assert!(token_amount(U256::max_value(), 6).is_err());
+    }
+
+    #[test]
+    fn invalid_prices_are_rejected() {
+        assert!(sqrt_price_x96_to_price(U256::zero(), 18, 18, true).is_err());
+        // MAX_SQRT_RATIO, far beyond what a Decimal can hold
+        let max = U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap();
+        assert!(sqrt_price_x96_to_price(max, 18, 18, true).is_err());
+    }
+}
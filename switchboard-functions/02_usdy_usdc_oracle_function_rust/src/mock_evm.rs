@@ -0,0 +1,330 @@
+//! A mock EVM node for tests, answering `eth_getBlockByNumber` with one fixed block and
+//! `eth_call` from a fixture of human readable calls, so the whole function can run
+//! without network access.
+//!
+//! Fixtures live in `src/fixtures/`:
+//!
+//! ```json
+//! {
+//!   "block": { "number": 66000000, "timestamp": 1720000000 },
+//!   "calls": [
+//!     {
+//!       "to": "0x25780dc8Fc3cfBD75F33bFDAB65e969b603b2035",
+//!       "function": "getPool(address,address,uint24) returns (address)",
+//!       "args": ["0x09Bc4E0D864854c6aFB6eB9A9cdF58aC190D0dF9", "0x5bE26527e817998A7206475496fDE1E68957c5A6", "500"],
+//!       "returns": ["0x1111111111111111111111111111111111111111"]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! A call with `"revert": "<reason>"` instead of `returns` reverts with that reason. Calls
+//! that aren't in the fixture revert, like `ranges(i)` past the last Ondo range does, and
+//! calls at any other block than the fixture's are rejected.
+
+use crate::mock_server::{MockServer, Reply};
+use ethabi::param_type::{ParamType, Reader};
+use ethabi::token::{LenientTokenizer, Tokenizer};
+use ethers::types::{Bytes, H160, U256, U64};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct FixtureBlock {
+    pub number: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Deserialize)]
+struct FixtureFile {
+    block: FixtureBlock,
+    calls: Vec<FixtureCall>,
+}
+
+#[derive(Deserialize)]
+struct FixtureCall {
+    to: String,
+    function: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    returns: Vec<String>,
+    #[serde(default)]
+    revert: Option<String>,
+}
+
+/// The block and `eth_call` answers of one chain, keyed by contract and calldata.
+#[derive(Clone)]
+pub struct EvmFixture {
+    pub block: FixtureBlock,
+    calls: HashMap<(H160, Vec<u8>), Result<Vec<u8>, String>>,
+    /// Calls answered with an internal error instead, see `fail`.
+    failing: HashSet<(H160, Vec<u8>)>,
+}
+
+impl EvmFixture {
+    pub fn load(json: &str) -> EvmFixture {
+        let file: FixtureFile = serde_json::from_str(json).expect("invalid fixture");
+        let mut fixture = EvmFixture {
+            block: file.block,
+            calls: HashMap::new(),
+            failing: HashSet::new(),
+        };
+        for call in file.calls.iter() {
+            let args: Vec<&str> = call.args.iter().map(String::as_str).collect();
+            match &call.revert {
+                Some(reason) => fixture.revert(&call.to, &call.function, &args, reason),
+                None => {
+                    let returns: Vec<&str> = call.returns.iter().map(String::as_str).collect();
+                    fixture.set(&call.to, &call.function, &args, &returns)
+                }
+            }
+        }
+        fixture
+    }
+
+    /// Answers `function` called with `args` on `to` with `returns`.
+    pub fn set(&mut self, to: &str, function: &str, args: &[&str], returns: &[&str]) {
+        let (calldata, outputs) = encode_call(function, args);
+        let result = ethabi::encode(&tokenize(&outputs, returns));
+        self.calls.insert((parse_address(to), calldata), Ok(result));
+    }
+
+    /// Reverts `function` called with `args` on `to` with `reason`.
+    pub fn revert(&mut self, to: &str, function: &str, args: &[&str], reason: &str) {
+        let (calldata, _) = encode_call(function, args);
+        self.calls
+            .insert((parse_address(to), calldata), Err(reason.to_string()));
+    }
+
+    /// Answers `function` called with `args` on `to` with an internal error, like a node
+    /// failing rather than the call reverting.
+    pub fn fail(&mut self, to: &str, function: &str, args: &[&str]) {
+        let (calldata, _) = encode_call(function, args);
+        self.failing.insert((parse_address(to), calldata));
+    }
+
+    /// Starts a node serving this fixture.
+    pub async fn serve(self) -> MockServer {
+        MockServer::start(move |method, params| self.reply(method, params)).await
+    }
+
+    fn reply(&self, method: &str, params: &Value) -> Reply {
+        match method {
+            "eth_getBlockByNumber" => Reply::Result(json!({
+                "number": U64::from(self.block.number),
+                "timestamp": U256::from(self.block.timestamp),
+            })),
+            "eth_call" => self.call(params),
+            _ => Reply::Error {
+                code: -32601,
+                message: format!("method {} not found", method),
+            },
+        }
+    }
+
+    fn call(&self, params: &Value) -> Reply {
+        let block = json!(U64::from(self.block.number));
+        if params[1] != block {
+            return Reply::Error {
+                code: -32000,
+                message: format!("call at block {}, the fixture is at {}", params[1], block),
+            };
+        }
+        let to: H160 = serde_json::from_value(params[0]["to"].clone()).unwrap_or_default();
+        let data = if params[0]["data"].is_null() {
+            &params[0]["input"]
+        } else {
+            &params[0]["data"]
+        };
+        let data: Bytes = serde_json::from_value(data.clone()).unwrap_or_default();
+        if self.failing.contains(&(to, data.to_vec())) {
+            return Reply::Error {
+                code: -32603,
+                message: "internal error".to_string(),
+            };
+        }
+        match self.calls.get(&(to, data.to_vec())) {
+            Some(Ok(result)) => Reply::Result(json!(Bytes::from(result.clone()))),
+            // like geth, with the reason ABI encoded as Error(string)
+            Some(Err(reason)) => Reply::ErrorWithData {
+                code: 3,
+                message: format!("execution reverted: {}", reason),
+                data: json!(Bytes::from(
+                    [
+                        ethabi::short_signature("Error", &[ParamType::String]).to_vec(),
+                        ethabi::encode(&[ethabi::Token::String(reason.clone())]),
+                    ]
+                    .concat()
+                )),
+            },
+            None => Reply::Error {
+                code: -32000,
+                message: format!("execution reverted: no fixture for {:?} {:?}", to, data),
+            },
+        }
+    }
+}
+
+/// Calldata of `name(inputs) returns (outputs)` called with `args`, and the output types.
+fn encode_call(function: &str, args: &[&str]) -> (Vec<u8>, Vec<ParamType>) {
+    let (call, returns) = function.split_once(" returns ").unwrap_or((function, "()"));
+    let (name, inputs) = call
+        .split_once('(')
+        .expect("function signature without arguments");
+    let inputs = parse_types(&format!("({}", inputs));
+    let outputs = parse_types(returns);
+    let calldata = [
+        ethabi::short_signature(name.trim(), &inputs).to_vec(),
+        ethabi::encode(&tokenize(&inputs, args)),
+    ]
+    .concat();
+    (calldata, outputs)
+}
+
+/// Types of a flat, parenthesized list such as `(address,uint24)`.
+fn parse_types(list: &str) -> Vec<ParamType> {
+    let list = list.trim().trim_start_matches('(').trim_end_matches(')');
+    list.split(',')
+        .map(str::trim)
+        .filter(|ty| !ty.is_empty())
+        .map(|ty| Reader::read(ty).unwrap_or_else(|e| panic!("invalid type {:?}: {}", ty, e)))
+        .collect()
+}
+
+fn tokenize(types: &[ParamType], values: &[&str]) -> Vec<ethabi::Token> {
+    assert_eq!(
+        types.len(),
+        values.len(),
+        "{:?} needs {} values",
+        types,
+        types.len()
+    );
+    types
+        .iter()
+        .zip(values)
+        .map(|(ty, value)| {
+            LenientTokenizer::tokenize(ty, value)
+                .unwrap_or_else(|e| panic!("invalid {} value {:?}: {}", ty, value, e))
+        })
+        .collect()
+}
+
+fn parse_address(address: &str) -> H160 {
+    address
+        .parse()
+        .unwrap_or_else(|e| panic!("invalid address {:?}: {}", address, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PinnedBlock;
+    use ethers_contract_derive::abigen;
+
+    abigen!(
+        Fixture,
+        r#"[
+            function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address)
+            function observe(uint32[] secondsAgos) external view returns (int56[], uint160[])
+        ]"#
+    );
+
+    const CONTRACT: &str = "0x25780dc8Fc3cfBD75F33bFDAB65e969b603b2035";
+
+    fn provider(server: &MockServer) -> crate::EvmProvider {
+        let rpc = crate::RpcConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        crate::EvmProvider::new(crate::FailoverClient::new(&[server.url.clone()], rpc).unwrap())
+    }
+
+    fn fixture() -> EvmFixture {
+        let mut fixture =
+            EvmFixture::load(r#"{"block": {"number": 100, "timestamp": 1700000000}, "calls": []}"#);
+        fixture.set(
+            CONTRACT,
+            "getPool(address,address,uint24) returns (address)",
+            &[
+                "0x0000000000000000000000000000000000000001",
+                "0x0000000000000000000000000000000000000002",
+                "500",
+            ],
+            &["0x1111111111111111111111111111111111111111"],
+        );
+        fixture.set(
+            CONTRACT,
+            "observe(uint32[]) returns (int56[],uint160[])",
+            &["[1800,0]"],
+            &["[-3600,0]", "[0,0]"],
+        );
+        fixture.revert(
+            CONTRACT,
+            "observe(uint32[]) returns (int56[],uint160[])",
+            &["[3600,0]"],
+            "OLD",
+        );
+        fixture
+    }
+
+    #[tokio::test]
+    async fn calls_are_answered_from_the_fixture() {
+        let server = fixture().serve().await;
+        let contract = Fixture::new(parse_address(CONTRACT), provider(&server).into());
+        let block = PinnedBlock {
+            number: 100,
+            timestamp: 1_700_000_000,
+        }
+        .id();
+
+        let pool = contract
+            .get_pool(H160::from_low_u64_be(1), H160::from_low_u64_be(2), 500)
+            .block(block)
+            .call()
+            .await
+            .unwrap();
+        assert_eq!(
+            pool,
+            parse_address("0x1111111111111111111111111111111111111111")
+        );
+
+        let (tick_cumulatives, _) = contract
+            .observe(vec![1800, 0])
+            .block(block)
+            .call()
+            .await
+            .unwrap();
+        assert_eq!(tick_cumulatives, vec![-3600, 0]);
+
+        let old = contract
+            .observe(vec![3600, 0])
+            .block(block)
+            .call()
+            .await
+            .unwrap_err();
+        assert_eq!(crate::revert_reason(&old).as_deref(), Some("OLD"));
+
+        // not in the fixture
+        assert!(contract
+            .get_pool(H160::from_low_u64_be(1), H160::from_low_u64_be(2), 3000)
+            .block(block)
+            .call()
+            .await
+            .is_err());
+        // not at the fixture's block
+        assert!(contract
+            .get_pool(H160::from_low_u64_be(1), H160::from_low_u64_be(2), 500)
+            .block(
+                PinnedBlock {
+                    number: 101,
+                    timestamp: 1_700_000_000,
+                }
+                .id(),
+            )
+            .call()
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! A minimal HTTP server answering JSON-RPC or plain HTTP requests, for tests that
+//! exercise real providers and REST clients without network access.
+
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// How the server answers a request.
+pub enum Reply {
+    /// A JSON-RPC response with `result`, or just the value for plain HTTP requests.
+    Result(Value),
+    /// A JSON-RPC error response.
+    Error { code: i64, message: String },
+    /// A JSON-RPC error response with `data`, e.g. the revert data of a reverted call.
+    ErrorWithData { code: i64, message: String, data: Value },
+    /// A 200 response with this body as is, e.g. malformed JSON.
+    Body(String),
+    /// An HTTP error status with a non-JSON body, like a failing load balancer.
+    Status(u16),
+    /// Never answers.
+    Hang,
+}
+
+/// Answers a request given its path and body, returning the status and body to send or
+/// `None` to never answer.
+type Handler = dyn Fn(&str, &[u8]) -> Option<(u16, String)> + Send + Sync;
+
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    /// Listens on a random local port and answers every JSON-RPC request with
+    /// `handler(method, params)`.
+    pub async fn start(handler: impl Fn(&str, &Value) -> Reply + Send + Sync + 'static) -> MockServer {
+        Self::listen(move |_, body| {
+            let request: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+            let method = request["method"].as_str().unwrap_or_default();
+            respond(handler(method, &request["params"]), Some(&request["id"]))
+        })
+        .await
+    }
+
+    /// Listens on a random local port and answers every plain HTTP request with
+    /// `handler(path)`, where the path includes the query string.
+    pub async fn start_http(handler: impl Fn(&str) -> Reply + Send + Sync + 'static) -> MockServer {
+        Self::listen(move |path, _| respond(handler(path), None)).await
+    }
+
+    async fn listen(
+        handler: impl Fn(&str, &[u8]) -> Option<(u16, String)> + Send + Sync + 'static,
+    ) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let counter = counter.clone();
+                tokio::spawn(async move { serve(stream, handler, counter).await });
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    /// Number of requests received so far.
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+async fn serve(mut stream: TcpStream, handler: Arc<Handler>, counter: Arc<AtomicUsize>) {
+    let mut buf = vec![];
+    let mut chunk = [0u8; 4096];
+    let (path, body) = loop {
+        let n = match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        buf.extend_from_slice(&chunk[..n]);
+        let header_end = match buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(header_end) => header_end,
+            None => continue,
+        };
+        let headers = String::from_utf8_lossy(&buf[..header_end]).to_lowercase();
+        let content_length = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        if buf.len() >= header_end + 4 + content_length {
+            // request line: METHOD PATH VERSION, the path is case sensitive
+            let path = String::from_utf8_lossy(&buf[..header_end])
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or("/")
+                .to_string();
+            break (path, buf[header_end + 4..header_end + 4 + content_length].to_vec());
+        }
+    };
+    counter.fetch_add(1, Ordering::SeqCst);
+
+    let (status, body) = match handler(&path, &body) {
+        Some(response) => response,
+        None => {
+            tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+            return;
+        }
+    };
+
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await.ok();
+    stream.shutdown().await.ok();
+}
+
+/// The status and body answering `reply`, wrapped in a JSON-RPC response when the request
+/// had an `id`.
+fn respond(reply: Reply, id: Option<&Value>) -> Option<(u16, String)> {
+    Some(match (reply, id) {
+        (Reply::Result(result), Some(id)) => (200, json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string()),
+        (Reply::Result(result), None) => (200, result.to_string()),
+        (Reply::Error { code, message }, Some(id)) => (
+            200,
+            json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}}).to_string(),
+        ),
+        (Reply::Error { code, message }, None) => (200, json!({"code": code, "message": message}).to_string()),
+        (Reply::ErrorWithData { code, message, data }, Some(id)) => (
+            200,
+            json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message, "data": data}})
+                .to_string(),
+        ),
+        (Reply::ErrorWithData { code, message, data }, None) => (
+            200,
+            json!({"code": code, "message": message, "data": data}).to_string(),
+        ),
+        (Reply::Body(body), _) => (200, body),
+        (Reply::Status(status), _) => (status, "upstream unavailable".to_string()),
+        (Reply::Hang, _) => return None,
+    })
+}
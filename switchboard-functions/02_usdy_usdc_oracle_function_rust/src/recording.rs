@@ -0,0 +1,554 @@
+//! Record and replay of every upstream request of a run, so a production run can be
+//! reproduced exactly, locally or as a regression test.
+//!
+//! A run with `RPC_RECORD_PATH` set writes every EVM and Solana JSON-RPC request, exchange
+//! ticker request and clock reading it made, with its outcome, to that file, or to stdout
+//! when the path is `-`. The enclave can write to `/data/recordings`, see
+//! `config/app.manifest.template`. A run with `RPC_REPLAY_PATH` set answers the same
+//! requests from such a recording instead of contacting any endpoint, e.g. with the dry run:
+//!
+//! ```sh
+//! RPC_REPLAY_PATH=run.json cargo run --features dry-run
+//! ```
+//!
+//! Recordings are JSON, one record per request:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "records": [
+//!     { "chain": "mantle", "method": "eth_call", "params": [{"to": "0x..", "data": "0x.."}, "0x3ef1480"], "block": 66000000, "result": "0x.." },
+//!     { "chain": "ethereum", "method": "eth_call", "params": [..], "block": 20000000, "error": { "code": 3, "message": "execution reverted" } },
+//!     { "chain": "offchain", "method": "clock", "params": null, "result": 1720000030 },
+//!     { "chain": "offchain", "method": "runner", "params": null, "result": { "function": "..", "function_request": null } }
+//!   ]
+//! }
+//! ```
+//!
+//! Errors without a `code` are requests no endpoint answered. Replays serve the responses
+//! to identical requests in recorded order and the last one again once they're used up.
+
+use crate::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::{RpcClient, RpcClientConfig};
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Version of the recording format, bumped on incompatible changes.
+pub const RECORDING_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub records: Vec<RpcRecord>,
+}
+
+/// One upstream request of a run and its outcome.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RpcRecord {
+    pub chain: Chain,
+    pub method: String,
+    pub params: Value,
+    /// The block (slot on Solana) the request was answered at, when it names one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<u64>,
+    #[serde(flatten)]
+    pub outcome: RpcOutcome,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcOutcome {
+    Result(Value),
+    Error(RecordedError),
+}
+
+/// A failed request, either a JSON-RPC error answer or no answer at all.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedError {
+    /// The JSON-RPC error code, `None` when no endpoint answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<i64>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RecordedError {
+    /// A request that got no answer, e.g. after every endpoint timed out.
+    pub fn unanswered<E: std::fmt::Display>(e: E) -> RecordedError {
+        RecordedError {
+            code: None,
+            message: e.to_string(),
+            data: None,
+        }
+    }
+}
+
+impl From<RpcOutcome> for Result<Value, RecordedError> {
+    fn from(outcome: RpcOutcome) -> Self {
+        match outcome {
+            RpcOutcome::Result(result) => Ok(result),
+            RpcOutcome::Error(e) => Err(e),
+        }
+    }
+}
+
+impl Recording {
+    pub fn load(path: &str) -> Result<Recording, SbError> {
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            SbError::CustomMessage(format!("failed to read recording {:?}: {}", path, e))
+        })?;
+        let recording: Recording = serde_json::from_str(&json)
+            .map_err(|e| SbError::CustomMessage(format!("invalid recording {:?}: {}", path, e)))?;
+        if recording.version != RECORDING_VERSION {
+            return Err(SbError::CustomMessage(format!(
+                "recording {:?} is version {}, expected {}",
+                path, recording.version, RECORDING_VERSION
+            )));
+        }
+        Ok(recording)
+    }
+
+    /// Writes the recording to `path`, or prints it on one line when `path` is `-`.
+    pub fn save(&self, path: &str) -> Result<(), SbError> {
+        if path == "-" {
+            let json = serde_json::to_string(self).map_err(|e| {
+                SbError::CustomMessage(format!("failed to serialize recording: {}", e))
+            })?;
+            println!("{}", json);
+            return Ok(());
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| SbError::CustomMessage(format!("failed to serialize recording: {}", e)))?;
+        std::fs::write(path, json).map_err(|e| {
+            SbError::CustomMessage(format!("failed to write recording {:?}: {}", path, e))
+        })
+    }
+}
+
+type RequestKey = (Chain, String, String);
+
+/// Where the upstream requests of a run go: to the network as usual, to the network and
+/// into a recording, or to a recording only. Clones share the same recording.
+#[derive(Clone, Debug, Default)]
+pub enum RpcTape {
+    #[default]
+    Off,
+    Record {
+        path: Option<String>,
+        records: Arc<Mutex<Vec<RpcRecord>>>,
+    },
+    Replay {
+        responses: Arc<Mutex<HashMap<RequestKey, VecDeque<RpcOutcome>>>>,
+    },
+}
+
+impl RpcTape {
+    /// Records to `RPC_RECORD_PATH` or replays `RPC_REPLAY_PATH`, whichever is set.
+    pub fn from_env() -> Result<RpcTape, SbError> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        match (var("RPC_RECORD_PATH"), var("RPC_REPLAY_PATH")) {
+            (Some(_), Some(_)) => Err(SbError::CustomMessage(
+                "RPC_RECORD_PATH and RPC_REPLAY_PATH can't both be set".to_string(),
+            )),
+            (Some(path), None) => Ok(RpcTape::recorder(Some(path))),
+            (None, Some(path)) => Ok(RpcTape::replayer(Recording::load(&path)?)),
+            (None, None) => Ok(RpcTape::Off),
+        }
+    }
+
+    /// Records every request, saving them to `path` on `save()` when set.
+    pub fn recorder(path: Option<String>) -> RpcTape {
+        RpcTape::Record {
+            path,
+            records: Arc::default(),
+        }
+    }
+
+    /// Answers every request from `recording`.
+    pub fn replayer(recording: Recording) -> RpcTape {
+        let mut responses: HashMap<RequestKey, VecDeque<RpcOutcome>> = HashMap::new();
+        for record in recording.records.into_iter() {
+            responses
+                .entry((record.chain, record.method, record.params.to_string()))
+                .or_default()
+                .push_back(record.outcome);
+        }
+        RpcTape::Replay {
+            responses: Arc::new(Mutex::new(responses)),
+        }
+    }
+
+    /// Everything recorded so far, empty unless recording.
+    pub fn recording(&self) -> Recording {
+        let records = match self {
+            RpcTape::Record { records, .. } => records.lock().unwrap().clone(),
+            _ => vec![],
+        };
+        Recording {
+            version: RECORDING_VERSION,
+            records,
+        }
+    }
+
+    /// Writes the recording to its path, if recording to one.
+    pub fn save(&self) -> Result<(), SbError> {
+        if let RpcTape::Record {
+            path: Some(path), ..
+        } = self
+        {
+            let recording = self.recording();
+            recording.save(path)?;
+            println!("recorded {} requests to {}", recording.records.len(), path);
+        }
+        Ok(())
+    }
+
+    /// Adds the outcome of a request made over the network to the recording.
+    pub fn record(
+        &self,
+        chain: Chain,
+        method: &str,
+        params: &Value,
+        outcome: &Result<Value, RecordedError>,
+    ) {
+        if let RpcTape::Record { records, .. } = self {
+            let record = RpcRecord {
+                chain,
+                method: method.to_string(),
+                params: params.clone(),
+                block: block_of(method, params, outcome.as_ref().ok()),
+                outcome: match outcome {
+                    Ok(result) => RpcOutcome::Result(result.clone()),
+                    Err(e) => RpcOutcome::Error(e.clone()),
+                },
+            };
+            records.lock().unwrap().push(record);
+        }
+    }
+
+    /// The recorded outcome of a request when replaying, which must not reach the network
+    /// then, and `None` otherwise.
+    pub fn replayed(
+        &self,
+        chain: Chain,
+        method: &str,
+        params: &Value,
+    ) -> Option<Result<Value, RecordedError>> {
+        let responses = match self {
+            RpcTape::Replay { responses } => responses,
+            _ => return None,
+        };
+        let mut responses = responses.lock().unwrap();
+        let outcome = match responses.get_mut(&(chain, method.to_string(), params.to_string())) {
+            Some(queue) if queue.len() > 1 => queue.pop_front(),
+            Some(queue) => queue.front().cloned(),
+            None => None,
+        };
+        Some(match outcome {
+            Some(outcome) => outcome.into(),
+            None => Err(RecordedError::unanswered(format!(
+                "no recorded {:?} response to {} {}",
+                chain, method, params
+            ))),
+        })
+    }
+
+    /// The current time, recorded like a request so replays see the time of the recorded run.
+    pub fn now(&self) -> PinnedBlock {
+        let (method, params) = ("clock", Value::Null);
+        if let Some(outcome) = self.replayed(Chain::Offchain, method, &params) {
+            match outcome.ok().and_then(|timestamp| timestamp.as_i64()) {
+                Some(timestamp) => {
+                    return PinnedBlock {
+                        number: 0,
+                        timestamp,
+                    }
+                }
+                None => println!("no recorded clock, using the current time"),
+            }
+        }
+        let now = PinnedBlock::now();
+        self.record(Chain::Offchain, method, &params, &Ok(json!(now.timestamp)));
+        now
+    }
+
+    /// Records the accounts the run was started for, so a replay can run as the same function.
+    pub fn record_runner(&self, function: &Pubkey, function_request: Option<&Pubkey>) {
+        let runner = json!({
+            "function": function.to_string(),
+            "function_request": function_request.map(|key| key.to_string()),
+        });
+        self.record(Chain::Offchain, "runner", &Value::Null, &Ok(runner));
+    }
+
+    /// The function and function request of the recorded run when replaying one.
+    pub fn replayed_runner(&self) -> Option<(Pubkey, Option<Pubkey>)> {
+        let runner = self
+            .replayed(Chain::Offchain, "runner", &Value::Null)?
+            .ok()?;
+        let key = |value: &Value| value.as_str().and_then(|key| Pubkey::from_str(key).ok());
+        Some((key(&runner["function"])?, key(&runner["function_request"])))
+    }
+
+    /// Status and body of a plain HTTP GET, e.g. an exchange ticker.
+    pub async fn get(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        timeout: std::time::Duration,
+    ) -> Result<(u16, String), String> {
+        let params = json!([url]);
+        let outcome = match self.replayed(Chain::Offchain, "GET", &params) {
+            Some(outcome) => outcome,
+            None => {
+                let outcome = http_get(client, url, timeout).await;
+                self.record(Chain::Offchain, "GET", &params, &outcome);
+                outcome
+            }
+        };
+        let response = outcome.map_err(|e| e.message)?;
+        match (response["status"].as_u64(), response["body"].as_str()) {
+            (Some(status), Some(body)) => Ok((status as u16, body.to_string())),
+            _ => Err(format!("invalid recorded response {}", response)),
+        }
+    }
+
+    /// The runner's Solana client, or one sending its requests through the tape.
+    pub fn solana_client(&self, client: &SolanaClient) -> SolanaClient {
+        if let RpcTape::Off = self {
+            return client.clone();
+        }
+        let sender = TapeSender {
+            client: solana_client::nonblocking::rpc_client::RpcClient::new(client.url()),
+            tape: self.clone(),
+        };
+        Arc::new(RpcClient::new_sender(
+            sender,
+            RpcClientConfig::with_commitment(client.commitment()),
+        ))
+    }
+}
+
+async fn http_get(
+    client: &reqwest::Client,
+    url: &str,
+    timeout: std::time::Duration,
+) -> Result<Value, RecordedError> {
+    let response = client
+        .get(url)
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(RecordedError::unanswered)?;
+    let status = response.status().as_u16();
+    let body = response.text().await.map_err(RecordedError::unanswered)?;
+    Ok(json!({ "status": status, "body": body }))
+}
+
+/// The block a request was answered at, from the block it names or its result.
+fn block_of(method: &str, params: &Value, result: Option<&Value>) -> Option<u64> {
+    let quantity = |value: &Value| {
+        value
+            .as_str()
+            .and_then(|value| value.strip_prefix("0x"))
+            .and_then(|value| u64::from_str_radix(value, 16).ok())
+    };
+    match method {
+        "eth_getBlockByNumber" => result.and_then(|result| quantity(&result["number"])),
+        "eth_call" => params
+            .as_array()
+            .and_then(|params| params.last())
+            .and_then(quantity),
+        "getSlot" => result.and_then(Value::as_u64),
+        "getBlockTime" => params[0].as_u64(),
+        // Solana account reads answer with the slot in their context
+        _ => result.and_then(|result| result["context"]["slot"].as_u64()),
+    }
+}
+
+/// Sends the requests of a Solana `RpcClient` through a tape.
+struct TapeSender {
+    client: solana_client::nonblocking::rpc_client::RpcClient,
+    tape: RpcTape,
+}
+
+#[async_trait::async_trait]
+impl RpcSender for TapeSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let method = request.to_string();
+        if let Some(outcome) = self.tape.replayed(Chain::Solana, &method, &params) {
+            return outcome.map_err(|e| {
+                let kind = match e.code {
+                    Some(code) => ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                        code,
+                        message: e.message,
+                        data: RpcResponseErrorData::Empty,
+                    }),
+                    None => ClientErrorKind::Custom(e.message),
+                };
+                ClientError::new_with_request(kind, request)
+            });
+        }
+
+        let result = self.client.send::<Value>(request, params.clone()).await;
+        let outcome = match &result {
+            Ok(result) => Ok(result.clone()),
+            Err(e) => Err(match e.kind() {
+                ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. }) => {
+                    RecordedError {
+                        code: Some(*code),
+                        message: message.clone(),
+                        data: None,
+                    }
+                }
+                _ => RecordedError::unanswered(e),
+            }),
+        };
+        self.tape.record(Chain::Solana, &method, &params, &outcome);
+        result
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        self.client.url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(block: &str) -> Value {
+        json!([{"to": "0xa0219aa5b31e65bc920b5b6dfb8edf0988121de0", "data": "0x98d5fdca"}, block])
+    }
+
+    #[test]
+    fn replays_recorded_responses() {
+        let recorder = RpcTape::recorder(None);
+        let reverted = RecordedError {
+            code: Some(3),
+            message: "execution reverted".to_string(),
+            data: None,
+        };
+        recorder.record(
+            Chain::Ethereum,
+            "eth_call",
+            &call("0x10"),
+            &Ok(json!("0x01")),
+        );
+        recorder.record(
+            Chain::Ethereum,
+            "eth_call",
+            &call("0x10"),
+            &Ok(json!("0x02")),
+        );
+        recorder.record(
+            Chain::Ethereum,
+            "eth_call",
+            &call("0x11"),
+            &Err(reverted.clone()),
+        );
+        let recording = recorder.recording();
+        assert_eq!(recording.records[0].block, Some(16));
+
+        // the format round trips through JSON
+        let json = serde_json::to_string(&recording).unwrap();
+        assert!(json.contains(r#""result":"0x01""#));
+        let recording: Recording = serde_json::from_str(&json).unwrap();
+        assert_eq!(recording, recorder.recording());
+
+        let replayer = RpcTape::replayer(recording);
+        let replay = |block| {
+            replayer
+                .replayed(Chain::Ethereum, "eth_call", &call(block))
+                .unwrap()
+        };
+        assert_eq!(replay("0x10"), Ok(json!("0x01")));
+        assert_eq!(replay("0x10"), Ok(json!("0x02")));
+        // the last response is served again
+        assert_eq!(replay("0x10"), Ok(json!("0x02")));
+        assert_eq!(replay("0x11"), Err(reverted));
+        assert_eq!(replay("0x12").unwrap_err().code, None);
+        // other chains are separate
+        assert!(replayer
+            .replayed(Chain::Mantle, "eth_call", &call("0x10"))
+            .unwrap()
+            .is_err());
+
+        assert!(RpcTape::Off
+            .replayed(Chain::Ethereum, "eth_call", &call("0x10"))
+            .is_none());
+    }
+
+    #[test]
+    fn replays_the_recorded_clock() {
+        let recorder = RpcTape::recorder(None);
+        let now = recorder.now();
+        let replayer = RpcTape::replayer(Recording {
+            version: RECORDING_VERSION,
+            records: vec![RpcRecord {
+                chain: Chain::Offchain,
+                method: "clock".to_string(),
+                params: Value::Null,
+                block: None,
+                outcome: RpcOutcome::Result(json!(1_700_000_000)),
+            }],
+        });
+        assert_eq!(replayer.now().timestamp, 1_700_000_000);
+        assert_eq!(
+            recorder.recording().records[0].outcome,
+            RpcOutcome::Result(json!(now.timestamp))
+        );
+    }
+
+    #[test]
+    fn replays_the_recorded_runner() {
+        let (function, request) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let recorder = RpcTape::recorder(None);
+        recorder.record_runner(&function, Some(&request));
+        assert_eq!(recorder.replayed_runner(), None);
+
+        let replayer = RpcTape::replayer(recorder.recording());
+        assert_eq!(replayer.replayed_runner(), Some((function, Some(request))));
+
+        let recorder = RpcTape::recorder(None);
+        recorder.record_runner(&function, None);
+        let replayer = RpcTape::replayer(recorder.recording());
+        assert_eq!(replayer.replayed_runner(), Some((function, None)));
+        let empty = Recording {
+            version: RECORDING_VERSION,
+            records: vec![],
+        };
+        assert_eq!(RpcTape::replayer(empty).replayed_runner(), None);
+    }
+
+    #[test]
+    fn recordings_are_saved_and_loaded() {
+        let path = std::env::temp_dir().join(format!("recording-{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let recorder = RpcTape::recorder(Some(path.clone()));
+        recorder.record(
+            Chain::Mantle,
+            "eth_blockNumber",
+            &json!([]),
+            &Ok(json!("0x10")),
+        );
+        recorder.save().unwrap();
+        assert_eq!(Recording::load(&path).unwrap(), recorder.recording());
+
+        let mut newer = recorder.recording();
+        newer.version += 1;
+        newer.save(&path).unwrap();
+        assert!(Recording::load(&path).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,430 @@
+use crate::*;
+use ethers::contract::ContractError;
+use ethers::providers::{Http, HttpClientError, JsonRpcClient, JsonRpcError, ProviderError};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::time::Duration;
+
+/// Timeouts and retry limits for EVM JSON-RPC calls, see `[rpc]` in `function.toml`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RpcConfig {
+    /// How long a single request may take before it is abandoned.
+    pub timeout_ms: u64,
+    /// How many times a failed request is retried on the same endpoint before failing over
+    /// to the next one.
+    pub max_retries: u32,
+    /// Base delay between retries, doubled after every attempt and jittered by up to
+    /// the same amount again.
+    pub backoff_ms: u64,
+    /// How long a source may take to produce a quote, across all of its calls.
+    pub source_timeout_ms: u64,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            timeout_ms: 5_000,
+            max_retries: 2,
+            backoff_ms: 200,
+            source_timeout_ms: 30_000,
+        }
+    }
+}
+
+impl RpcConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    pub fn source_timeout(&self) -> Duration {
+        Duration::from_millis(self.source_timeout_ms)
+    }
+
+    /// Delay before retry number `attempt`, counting from zero.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let base = self.backoff_ms.saturating_mul(1 << attempt.min(16));
+        let jitter = rand::thread_rng().gen_range(0..=self.backoff_ms);
+        Duration::from_millis(base.saturating_add(jitter))
+    }
+
+    pub fn validate(&self) -> Result<(), SbError> {
+        if self.timeout_ms == 0 || self.source_timeout_ms == 0 {
+            return Err(SbError::CustomMessage("rpc timeouts must not be zero".to_string()));
+        }
+        if self.max_retries > 10 {
+            return Err(SbError::CustomMessage("rpc.max_retries must be at most 10".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum FailoverError {
+    /// The call reverted. These are never retried since another attempt would get the
+    /// same answer, see `is_execution_revert`.
+    JsonRpc(HttpClientError),
+    Params(serde_json::Error),
+    /// The result doesn't have the requested type.
+    Response(serde_json::Error),
+    /// Every endpoint failed or timed out on every attempt. Holds the last failure.
+    Exhausted(String),
+}
+
+/// Whether a JSON-RPC error means the call itself reverted, which every node would answer
+/// the same. Geth answers reverts with code 3 when there is revert data and with -32000
+/// otherwise, some providers use -32015. Other errors, like rate limits (-32005, 429),
+/// `header not found` on a node that is behind, or internal errors (-32603), are worth
+/// retrying and failing over.
+pub fn is_execution_revert(e: &JsonRpcError) -> bool {
+    match e.code {
+        3 => true,
+        -32000 | -32015 => e.message.to_lowercase().contains("revert"),
+        _ => false,
+    }
+}
+
+/// Selector of `Error(string)`, the revert data of `require(condition, "reason")`.
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// The node's answer to a contract call that reverted, `None` when the call failed for
+/// any other reason.
+fn call_revert(e: &ContractError<EvmProvider>) -> Option<&JsonRpcError> {
+    let e = match e {
+        ContractError::MiddlewareError(e) | ContractError::ProviderError(e) => e,
+        _ => return None,
+    };
+    match e {
+        ProviderError::JsonRpcClientError(e) => match e.downcast_ref::<FailoverError>()? {
+            FailoverError::JsonRpc(HttpClientError::JsonRpcError(e)) if is_execution_revert(e) => Some(e),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a contract call reverted, rather than failing to reach a node or to decode.
+pub fn is_revert(e: &ContractError<EvmProvider>) -> bool {
+    call_revert(e).is_some()
+}
+
+/// The reason a contract call reverted with, decoded from the `Error(string)` revert data
+/// rather than matched in the node's message, which differs between clients.
+pub fn revert_reason(e: &ContractError<EvmProvider>) -> Option<String> {
+    decode_revert_reason(call_revert(e)?.data.as_ref()?)
+}
+
+/// Decodes `Error(string)` revert data, sent as a hex string or as `{"data": "0x.."}`.
+fn decode_revert_reason(data: &serde_json::Value) -> Option<String> {
+    let hex = data.as_str().or_else(|| data["data"].as_str())?;
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    let data = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let payload = data.strip_prefix(&ERROR_STRING_SELECTOR[..])?;
+    match ethabi::decode(&[ethabi::ParamType::String], payload).ok()?.pop()? {
+        ethabi::Token::String(reason) => Some(reason),
+        _ => None,
+    }
+}
+
+impl std::fmt::Display for FailoverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailoverError::JsonRpc(e) => write!(f, "{}", e),
+            FailoverError::Params(e) => write!(f, "failed to serialize params: {}", e),
+            FailoverError::Response(e) => write!(f, "failed to deserialize result: {}", e),
+            FailoverError::Exhausted(last) => write!(f, "all rpc endpoints failed, last error: {}", last),
+        }
+    }
+}
+
+impl std::error::Error for FailoverError {}
+
+impl From<FailoverError> for ProviderError {
+    fn from(e: FailoverError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(e))
+    }
+}
+
+/// JSON-RPC transport over an ordered list of HTTP endpoints.
+///
+/// Each request is tried on the first endpoint with a timeout, retried with jittered
+/// exponential backoff up to `max_retries` times, then failed over to the next endpoint.
+/// With a tape, requests and their outcomes are also recorded or replayed, see
+/// `recording.rs`.
+#[derive(Clone, Debug)]
+pub struct FailoverClient {
+    endpoints: Vec<(String, Http)>,
+    config: RpcConfig,
+    tape: Option<(Chain, RpcTape)>,
+}
+
+impl FailoverClient {
+    pub fn new(urls: &[String], config: RpcConfig) -> Result<FailoverClient, SbError> {
+        if urls.is_empty() {
+            return Err(SbError::CustomMessage("no rpc urls configured".to_string()));
+        }
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                let http = Http::from_str(url).map_err(|e| {
+                    SbError::CustomMessage(format!("invalid rpc url {:?}: {}", url, e))
+                })?;
+                Ok((url.clone(), http))
+            })
+            .collect::<Result<Vec<_>, SbError>>()?;
+        Ok(FailoverClient {
+            endpoints,
+            config,
+            tape: None,
+        })
+    }
+
+    /// Sends the requests of `chain` through `tape`.
+    pub fn with_tape(mut self, chain: Chain, tape: RpcTape) -> FailoverClient {
+        self.tape = Some((chain, tape));
+        self
+    }
+
+    async fn send(
+        &self,
+        method: &str,
+        params: &serde_json::Value,
+    ) -> Result<serde_json::Value, FailoverError> {
+        let mut last_error = String::new();
+        for (url, http) in self.endpoints.iter() {
+            for attempt in 0..=self.config.max_retries {
+                if attempt > 0 {
+                    tokio::time::sleep(self.config.backoff(attempt - 1)).await;
+                }
+                match tokio::time::timeout(self.config.timeout(), http.request(method, params)).await {
+                    Ok(Ok(result)) => return Ok(result),
+                    Ok(Err(HttpClientError::JsonRpcError(e))) if is_execution_revert(&e) => {
+                        return Err(FailoverError::JsonRpc(HttpClientError::JsonRpcError(e)))
+                    }
+                    Ok(Err(e)) => last_error = format!("{}: {}", url, e),
+                    Err(_) => {
+                        last_error = format!("{}: timed out after {:?}", url, self.config.timeout())
+                    }
+                }
+            }
+            println!(
+                "{} failed {} times, {}",
+                method,
+                self.config.max_retries + 1,
+                last_error
+            );
+        }
+        Err(FailoverError::Exhausted(last_error))
+    }
+}
+
+impl From<&FailoverError> for RecordedError {
+    fn from(e: &FailoverError) -> Self {
+        match e {
+            FailoverError::JsonRpc(HttpClientError::JsonRpcError(e)) => RecordedError {
+                code: Some(e.code),
+                message: e.message.clone(),
+                data: e.data.clone(),
+            },
+            FailoverError::Exhausted(last) => RecordedError::unanswered(last),
+            e => RecordedError::unanswered(e),
+        }
+    }
+}
+
+impl From<RecordedError> for FailoverError {
+    fn from(e: RecordedError) -> Self {
+        match e.code {
+            Some(code) => FailoverError::JsonRpc(HttpClientError::JsonRpcError(JsonRpcError {
+                code,
+                message: e.message,
+                data: e.data,
+            })),
+            None => FailoverError::Exhausted(e.message),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl JsonRpcClient for FailoverClient {
+    type Error = FailoverError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, FailoverError>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        // serialized once so the same params can be sent to every endpoint
+        let params = serde_json::to_value(params).map_err(FailoverError::Params)?;
+
+        let result = match &self.tape {
+            Some((chain, tape)) => match tape.replayed(*chain, method, &params) {
+                Some(outcome) => outcome.map_err(FailoverError::from),
+                None => {
+                    let result = self.send(method, &params).await;
+                    let outcome = result.as_ref().cloned().map_err(RecordedError::from);
+                    tape.record(*chain, method, &params, &outcome);
+                    result
+                }
+            },
+            None => self.send(method, &params).await,
+        };
+        serde_json::from_value(result?).map_err(FailoverError::Response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, Reply};
+    use ethers::providers::{Middleware, Provider};
+
+    fn config() -> RpcConfig {
+        RpcConfig {
+            timeout_ms: 200,
+            max_retries: 1,
+            backoff_ms: 10,
+            source_timeout_ms: 1_000,
+        }
+    }
+
+    fn provider(servers: &[&MockServer]) -> Provider<FailoverClient> {
+        let urls: Vec<String> = servers.iter().map(|server| server.url.clone()).collect();
+        Provider::new(FailoverClient::new(&urls, config()).unwrap())
+    }
+
+    fn block_number(number: u64) -> impl Fn(&str, &serde_json::Value) -> Reply + Send + Sync {
+        move |method, _| match method {
+            "eth_blockNumber" => Reply::Result(serde_json::json!(format!("{:#x}", number))),
+            _ => Reply::Error { code: -32601, message: "method not found".to_string() },
+        }
+    }
+
+    #[tokio::test]
+    async fn stalled_endpoint_fails_over() {
+        let stalled = MockServer::start(|_, _| Reply::Hang).await;
+        let healthy = MockServer::start(block_number(42)).await;
+
+        let number = provider(&[&stalled, &healthy]).get_block_number().await.unwrap();
+        assert_eq!(number.as_u64(), 42);
+        assert_eq!(stalled.requests(), 2);
+        assert_eq!(healthy.requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let flaky = MockServer::start(move |method, params| {
+            if counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                Reply::Status(502)
+            } else {
+                block_number(7)(method, params)
+            }
+        })
+        .await;
+
+        let number = provider(&[&flaky]).get_block_number().await.unwrap();
+        assert_eq!(number.as_u64(), 7);
+        assert_eq!(flaky.requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn reverts_are_not_retried() {
+        let reverting = MockServer::start(|_, _| Reply::Error {
+            code: 3,
+            message: "execution reverted: OLD".to_string(),
+        })
+        .await;
+        let backup = MockServer::start(block_number(1)).await;
+
+        let e = provider(&[&reverting, &backup]).get_block_number().await.unwrap_err();
+        assert!(e.to_string().contains("OLD"));
+        assert_eq!(reverting.requests(), 1);
+        assert_eq!(backup.requests(), 0);
+    }
+
+    #[tokio::test]
+    async fn transient_rpc_errors_fail_over() {
+        for (code, message) in [
+            (-32005, "rate limit exceeded"),
+            (429, "too many requests"),
+            (-32000, "header not found"),
+            (-32603, "internal error"),
+        ] {
+            let failing = MockServer::start(move |_, _| Reply::Error {
+                code,
+                message: message.to_string(),
+            })
+            .await;
+            let backup = MockServer::start(block_number(5)).await;
+
+            let number = provider(&[&failing, &backup]).get_block_number().await.unwrap();
+            assert_eq!(number.as_u64(), 5);
+            assert_eq!(failing.requests(), 2);
+            assert_eq!(backup.requests(), 1);
+        }
+    }
+
+    #[test]
+    fn only_reverts_are_terminal() {
+        let error = |code, message: &str| JsonRpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        };
+        assert!(is_execution_revert(&error(3, "execution reverted: OLD")));
+        assert!(is_execution_revert(&error(-32000, "execution reverted")));
+        assert!(is_execution_revert(&error(-32015, "VM execution error: Reverted")));
+        assert!(!is_execution_revert(&error(-32000, "header not found")));
+        assert!(!is_execution_revert(&error(-32005, "limit exceeded")));
+        assert!(!is_execution_revert(&error(-32603, "internal error")));
+    }
+
+    #[test]
+    fn revert_reasons_are_decoded_from_the_error_data() {
+        let data = [
+            ERROR_STRING_SELECTOR.to_vec(),
+            ethabi::encode(&[ethabi::Token::String("OLD".to_string())]),
+        ]
+        .concat();
+        let hex: String = data.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        let reason = decode_revert_reason(&serde_json::json!(format!("0x{}", hex)));
+        assert_eq!(reason.as_deref(), Some("OLD"));
+        let reason = decode_revert_reason(&serde_json::json!({ "data": format!("0x{}", hex) }));
+        assert_eq!(reason.as_deref(), Some("OLD"));
+
+        // a custom error, or no data at all
+        assert_eq!(decode_revert_reason(&serde_json::json!("0x4e487b71")), None);
+        assert_eq!(decode_revert_reason(&serde_json::json!("0x")), None);
+    }
+
+    #[tokio::test]
+    async fn retries_are_bounded() {
+        let down = MockServer::start(|_, _| Reply::Status(503)).await;
+        let stalled = MockServer::start(|_, _| Reply::Hang).await;
+
+        let e = provider(&[&down, &stalled]).get_block_number().await.unwrap_err();
+        assert!(e.to_string().contains("timed out"));
+        assert_eq!(down.requests(), 2);
+        assert_eq!(stalled.requests(), 2);
+    }
+
+    #[test]
+    fn backoff_grows_with_jitter() {
+        let config = RpcConfig { backoff_ms: 100, ..config() };
+        for _ in 0..20 {
+            let first = config.backoff(0).as_millis();
+            let third = config.backoff(2).as_millis();
+            assert!((100..=200).contains(&first));
+            assert!((400..=500).contains(&third));
+        }
+    }
+}
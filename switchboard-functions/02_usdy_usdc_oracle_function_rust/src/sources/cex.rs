@@ -0,0 +1,708 @@
+use crate::*;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+/// A centralized exchange with public REST ticker and trades endpoints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Exchange {
+    /// `GET /api/v3/ticker/24hr?symbol=USDYUSDC` and `GET /api/v3/trades?symbol=USDYUSDC`
+    Binance,
+    /// `GET /v5/market/tickers?category=spot&symbol=USDYUSDC` and
+    /// `GET /v5/market/recent-trade?category=spot&symbol=USDYUSDC`
+    Bybit,
+    /// `GET /api/v5/market/ticker?instId=USDY-USDC` and
+    /// `GET /api/v5/market/trades?instId=USDY-USDC`
+    Okx,
+}
+
+/// Which ticker field a CEX source reports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TickerPrice {
+    /// Midpoint of the best bid and ask.
+    #[default]
+    Mid,
+    /// Price of the last trade.
+    Last,
+}
+
+/// A validated exchange ticker.
+///
+/// Ticker timestamps are the server time or the end of the 24 hour window, which say
+/// nothing about how old the prices are, so a ticker is dated by the market's last trade.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CexTicker {
+    pub bid: Decimal,
+    pub ask: Decimal,
+    pub last: Decimal,
+    /// Traded volume over the last 24 hours, in the base currency.
+    pub volume: Decimal,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinanceTicker {
+    symbol: String,
+    bid_price: String,
+    ask_price: String,
+    last_price: String,
+    volume: String,
+}
+
+#[derive(Deserialize)]
+struct BinanceTrade {
+    time: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitResponse<T> {
+    ret_code: i64,
+    ret_msg: String,
+    result: Option<BybitResult<T>>,
+}
+
+#[derive(Deserialize)]
+struct BybitResult<T> {
+    list: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitTicker {
+    symbol: String,
+    bid1_price: String,
+    ask1_price: String,
+    last_price: String,
+    volume24h: String,
+}
+
+#[derive(Deserialize)]
+struct BybitTrade {
+    symbol: String,
+    time: String,
+}
+
+#[derive(Deserialize)]
+struct OkxResponse<T> {
+    code: String,
+    msg: String,
+    data: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OkxTicker {
+    inst_id: String,
+    bid_px: String,
+    ask_px: String,
+    last: String,
+    vol24h: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OkxTrade {
+    inst_id: String,
+    ts: String,
+}
+
+fn parse_json<T: DeserializeOwned>(body: &str) -> Result<T, SourceError> {
+    serde_json::from_str(body)
+        .map_err(|e| SourceError::InvalidData(format!("unexpected ticker response: {}", e)))
+}
+
+fn parse_decimal(field: &str, value: &str) -> Result<Decimal, SourceError> {
+    Decimal::from_str(value)
+        .map_err(|_| SourceError::InvalidData(format!("{} {:?} is not a number", field, value)))
+}
+
+fn parse_timestamp_ms(field: &str, value: &str) -> Result<i64, SourceError> {
+    value
+        .parse()
+        .map_err(|_| SourceError::InvalidData(format!("{} {:?} is not a timestamp", field, value)))
+}
+
+fn check_symbol(expected: &str, actual: &str) -> Result<(), SourceError> {
+    if expected != actual {
+        return Err(SourceError::InvalidData(format!(
+            "ticker is for {}, not {}",
+            actual, expected
+        )));
+    }
+    Ok(())
+}
+
+/// The single ticker or trade of a list response.
+fn single<T>(list: Vec<T>, symbol: &str) -> Result<T, SourceError> {
+    if list.len() != 1 {
+        return Err(SourceError::InvalidData(format!(
+            "expected one entry for {}, got {}",
+            symbol,
+            list.len()
+        )));
+    }
+    Ok(list.into_iter().next().unwrap())
+}
+
+fn check_bybit<T>(response: BybitResponse<T>) -> Result<Vec<T>, SourceError> {
+    if response.ret_code != 0 {
+        return Err(SourceError::InvalidData(format!(
+            "error {}: {}",
+            response.ret_code, response.ret_msg
+        )));
+    }
+    Ok(response.result.map(|result| result.list).unwrap_or_default())
+}
+
+fn check_okx<T>(response: OkxResponse<T>) -> Result<Vec<T>, SourceError> {
+    if response.code != "0" {
+        return Err(SourceError::InvalidData(format!(
+            "error {}: {}",
+            response.code, response.msg
+        )));
+    }
+    Ok(response.data)
+}
+
+impl Exchange {
+    /// Path and query of the exchange's 24 hour ticker for `symbol`.
+    pub fn ticker_path(&self, symbol: &str) -> String {
+        match self {
+            Exchange::Binance => format!("/api/v3/ticker/24hr?symbol={}", symbol),
+            Exchange::Bybit => format!("/v5/market/tickers?category=spot&symbol={}", symbol),
+            Exchange::Okx => format!("/api/v5/market/ticker?instId={}", symbol),
+        }
+    }
+
+    /// Path and query of the exchange's most recent trade for `symbol`.
+    pub fn trades_path(&self, symbol: &str) -> String {
+        match self {
+            Exchange::Binance => format!("/api/v3/trades?symbol={}&limit=1", symbol),
+            Exchange::Bybit => format!("/v5/market/recent-trade?category=spot&symbol={}&limit=1", symbol),
+            Exchange::Okx => format!("/api/v5/market/trades?instId={}&limit=1", symbol),
+        }
+    }
+
+    /// Whether `symbol` is a USDC market, the only quote currency the oracle reports in.
+    pub fn quotes_usdc(&self, symbol: &str) -> bool {
+        match self {
+            Exchange::Binance | Exchange::Bybit => symbol.len() > 4 && symbol.ends_with("USDC"),
+            Exchange::Okx => symbol.len() > 5 && symbol.ends_with("-USDC"),
+        }
+    }
+
+    /// Parses a ticker response, checking it has every field and is for `symbol`.
+    pub fn parse_ticker(&self, symbol: &str, body: &str) -> Result<CexTicker, SourceError> {
+        match self {
+            Exchange::Binance => {
+                let ticker: BinanceTicker = parse_json(body)?;
+                check_symbol(symbol, &ticker.symbol)?;
+                Ok(CexTicker {
+                    bid: parse_decimal("bidPrice", &ticker.bid_price)?,
+                    ask: parse_decimal("askPrice", &ticker.ask_price)?,
+                    last: parse_decimal("lastPrice", &ticker.last_price)?,
+                    volume: parse_decimal("volume", &ticker.volume)?,
+                })
+            }
+            Exchange::Bybit => {
+                let list: Vec<BybitTicker> = check_bybit(parse_json(body)?)?;
+                let ticker = single(list, symbol)?;
+                check_symbol(symbol, &ticker.symbol)?;
+                Ok(CexTicker {
+                    bid: parse_decimal("bid1Price", &ticker.bid1_price)?,
+                    ask: parse_decimal("ask1Price", &ticker.ask1_price)?,
+                    last: parse_decimal("lastPrice", &ticker.last_price)?,
+                    volume: parse_decimal("volume24h", &ticker.volume24h)?,
+                })
+            }
+            Exchange::Okx => {
+                let list: Vec<OkxTicker> = check_okx(parse_json(body)?)?;
+                let ticker = single(list, symbol)?;
+                check_symbol(symbol, &ticker.inst_id)?;
+                Ok(CexTicker {
+                    bid: parse_decimal("bidPx", &ticker.bid_px)?,
+                    ask: parse_decimal("askPx", &ticker.ask_px)?,
+                    last: parse_decimal("last", &ticker.last)?,
+                    volume: parse_decimal("vol24h", &ticker.vol24h)?,
+                })
+            }
+        }
+    }
+
+    /// Parses a trades response, returning the Unix timestamp in milliseconds of the last
+    /// trade of `symbol`.
+    pub fn parse_last_trade(&self, symbol: &str, body: &str) -> Result<i64, SourceError> {
+        match self {
+            // the trades of the requested symbol, without the symbol
+            Exchange::Binance => {
+                let trades: Vec<BinanceTrade> = parse_json(body)?;
+                Ok(single(trades, symbol)?.time)
+            }
+            Exchange::Bybit => {
+                let list: Vec<BybitTrade> = check_bybit(parse_json(body)?)?;
+                let trade = single(list, symbol)?;
+                check_symbol(symbol, &trade.symbol)?;
+                parse_timestamp_ms("time", &trade.time)
+            }
+            Exchange::Okx => {
+                let list: Vec<OkxTrade> = check_okx(parse_json(body)?)?;
+                let trade = single(list, symbol)?;
+                check_symbol(symbol, &trade.inst_id)?;
+                parse_timestamp_ms("ts", &trade.ts)
+            }
+        }
+    }
+}
+
+impl CexTicker {
+    /// Checks the ticker is usable and returns the configured `price` field.
+    ///
+    /// The book must be positive and not crossed, its spread at most `max_spread_bps` of the
+    /// mid, the 24 hour volume at least `min_volume` and the last trade, at `last_trade_ms`,
+    /// no older than `max_age` seconds at `now`.
+    pub fn price(
+        &self,
+        price: TickerPrice,
+        last_trade_ms: i64,
+        now: i64,
+        max_age: u64,
+        max_spread_bps: u32,
+        min_volume: u64,
+    ) -> Result<Decimal, SourceError> {
+        if self.bid <= Decimal::ZERO || self.ask <= Decimal::ZERO || self.last <= Decimal::ZERO {
+            return Err(SourceError::InvalidData(format!(
+                "bid {}, ask {} and last {} must be positive",
+                self.bid, self.ask, self.last
+            )));
+        }
+        if self.bid > self.ask {
+            return Err(SourceError::InvalidData(format!(
+                "book is crossed, bid {} > ask {}",
+                self.bid, self.ask
+            )));
+        }
+        let age = i128::from(now) - i128::from(last_trade_ms / 1000);
+        if age > max_age as i128 {
+            return Err(SourceError::Stale(format!(
+                "last trade was {}s ago, max age is {}s",
+                age, max_age
+            )));
+        }
+
+        let mid = (self.bid + self.ask) / Decimal::TWO;
+        let spread_bps = (self.ask - self.bid) / mid * Decimal::from(10_000);
+        if spread_bps > Decimal::from(max_spread_bps) {
+            return Err(SourceError::NoLiquidity(format!(
+                "spread {}bps is wider than {}bps",
+                spread_bps.round_dp(2),
+                max_spread_bps
+            )));
+        }
+        if self.volume < Decimal::from(min_volume) {
+            return Err(SourceError::NoLiquidity(format!(
+                "24h volume {} is below {}",
+                self.volume, min_volume
+            )));
+        }
+
+        Ok(match price {
+            TickerPrice::Mid => mid,
+            TickerPrice::Last => self.last,
+        })
+    }
+}
+
+/// USDY price from a centralized exchange's public REST ticker.
+///
+/// The price is in the market's quote currency, so only USDC markets are accepted when the
+/// config is validated. The ticker is dated by the market's last trade, read from the
+/// trades endpoint. Tickers have no notion of depth comparable to pool liquidity, so the
+/// quote is unweighted unless a `weight` is configured.
+pub struct CexTickerSource {
+    pub name: String,
+    pub client: reqwest::Client,
+    pub exchange: Exchange,
+    /// API root, e.g. `https://api.bybit.com`.
+    pub base_url: String,
+    /// The exchange's symbol for the market, e.g. `USDYUSDC` or `USDY-USDC`.
+    pub symbol: String,
+    pub price: TickerPrice,
+    pub max_age_seconds: u64,
+    pub max_spread_bps: u32,
+    /// Minimum 24 hour volume in USDY.
+    pub min_volume: u64,
+    /// The configured weight of the quote, see `Quote.weight`.
+    pub weight: Option<Decimal>,
+    /// Timeout of the ticker and trades requests.
+    pub timeout: std::time::Duration,
+    /// Records or replays the ticker and trades requests.
+    pub tape: RpcTape,
+}
+
+impl CexTickerSource {
+    /// The body of a successful `GET` of `path` on the exchange.
+    async fn get(&self, call: &'static str, path: String) -> Result<String, SourceError> {
+        let url = format!("{}{}", self.base_url.trim_end_matches('/'), path);
+        let (status, body) = self
+            .tape
+            .get(&self.client, &url, self.timeout)
+            .await
+            .map_err(SourceError::rpc(call))?;
+        let status = reqwest::StatusCode::from_u16(status).map_err(SourceError::rpc(call))?;
+        if !status.is_success() {
+            return Err(SourceError::Rpc {
+                call,
+                message: format!("HTTP {}: {}", status, body),
+            });
+        }
+        Ok(body)
+    }
+}
+
+impl PriceSource for CexTickerSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> SourceKind {
+        SourceKind::Market
+    }
+
+    fn chain(&self) -> Chain {
+        Chain::Offchain
+    }
+
+    fn fetch(&self, block: PinnedBlock) -> SourceFuture<'_> {
+        Box::pin(async move {
+            let (ticker, trades) = futures::future::try_join(
+                self.get("ticker", self.exchange.ticker_path(&self.symbol)),
+                self.get("trades", self.exchange.trades_path(&self.symbol)),
+            )
+            .await?;
+
+            let ticker = self.exchange.parse_ticker(&self.symbol, &ticker)?;
+            let last_trade_ms = self.exchange.parse_last_trade(&self.symbol, &trades)?;
+            println!(
+                "{}: {:?} {} {:?}, last trade at {}ms",
+                self.name, self.exchange, self.symbol, ticker, last_trade_ms
+            );
+            let price = ticker.price(
+                self.price,
+                last_trade_ms,
+                block.timestamp,
+                self.max_age_seconds,
+                self.max_spread_bps,
+                self.min_volume,
+            )?;
+            Ok(Quote {
+                weight: self.weight,
+                ..Quote::new(price)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, Reply};
+    use serde_json::json;
+
+    const NOW: i64 = 1_700_000_000;
+    const LAST_TRADE_MS: i64 = 1_699_999_995_000;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn ticker() -> CexTicker {
+        CexTicker {
+            bid: dec("1.0490"),
+            ask: dec("1.0510"),
+            last: dec("1.0505"),
+            volume: dec("250000"),
+        }
+    }
+
+    fn binance_body() -> serde_json::Value {
+        json!({
+            "symbol": "USDYUSDC",
+            "priceChange": "0.0010",
+            "bidPrice": "1.0490",
+            "bidQty": "1200.00",
+            "askPrice": "1.0510",
+            "askQty": "800.00",
+            "lastPrice": "1.0505",
+            "volume": "250000.00",
+            "openTime": 1699913600000_i64,
+            // the end of the 24 hour window, not the last trade
+            "closeTime": 1700000000000_i64,
+        })
+    }
+
+    fn binance_trades() -> serde_json::Value {
+        json!([{
+            "id": 28457,
+            "price": "1.0505",
+            "qty": "100.00",
+            "quoteQty": "105.05",
+            "time": LAST_TRADE_MS,
+            "isBuyerMaker": true,
+            "isBestMatch": true,
+        }])
+    }
+
+    fn source(server: &MockServer, exchange: Exchange, symbol: &str) -> CexTickerSource {
+        CexTickerSource {
+            name: "cex".to_string(),
+            client: reqwest::Client::new(),
+            exchange,
+            base_url: format!("{}/", server.url),
+            symbol: symbol.to_string(),
+            price: TickerPrice::Mid,
+            max_age_seconds: 60,
+            max_spread_bps: 100,
+            min_volume: 0,
+            weight: None,
+            timeout: std::time::Duration::from_secs(5),
+            tape: RpcTape::Off,
+        }
+    }
+
+    fn now() -> PinnedBlock {
+        PinnedBlock {
+            number: 0,
+            timestamp: NOW,
+        }
+    }
+
+    #[test]
+    fn parses_every_exchange() {
+        assert_eq!(
+            Exchange::Binance
+                .parse_ticker("USDYUSDC", &binance_body().to_string())
+                .unwrap(),
+            ticker()
+        );
+        assert_eq!(
+            Exchange::Binance
+                .parse_last_trade("USDYUSDC", &binance_trades().to_string())
+                .unwrap(),
+            LAST_TRADE_MS
+        );
+
+        let bybit = json!({
+            "retCode": 0,
+            "retMsg": "OK",
+            "result": {"category": "spot", "list": [{
+                "symbol": "USDYUSDC",
+                "bid1Price": "1.0490",
+                "bid1Size": "1200",
+                "ask1Price": "1.0510",
+                "ask1Size": "800",
+                "lastPrice": "1.0505",
+                "volume24h": "250000",
+                "turnover24h": "262600",
+            }]},
+            // the server time
+            "time": 1700000000000_i64,
+        });
+        assert_eq!(
+            Exchange::Bybit
+                .parse_ticker("USDYUSDC", &bybit.to_string())
+                .unwrap(),
+            ticker()
+        );
+        let bybit_trades = json!({
+            "retCode": 0,
+            "retMsg": "OK",
+            "result": {"category": "spot", "list": [{
+                "execId": "2100000000007764263",
+                "symbol": "USDYUSDC",
+                "price": "1.0505",
+                "size": "100",
+                "side": "Buy",
+                "time": "1699999995000",
+                "isBlockTrade": false,
+            }]},
+            "time": 1700000000000_i64,
+        });
+        assert_eq!(
+            Exchange::Bybit
+                .parse_last_trade("USDYUSDC", &bybit_trades.to_string())
+                .unwrap(),
+            LAST_TRADE_MS
+        );
+
+        let okx = json!({
+            "code": "0",
+            "msg": "",
+            "data": [{
+                "instType": "SPOT",
+                "instId": "USDY-USDC",
+                "last": "1.0505",
+                "askPx": "1.0510",
+                "bidPx": "1.0490",
+                "vol24h": "250000",
+                "volCcy24h": "262600",
+                "ts": "1700000000000",
+            }],
+        });
+        assert_eq!(
+            Exchange::Okx
+                .parse_ticker("USDY-USDC", &okx.to_string())
+                .unwrap(),
+            ticker()
+        );
+        let okx_trades = json!({
+            "code": "0",
+            "msg": "",
+            "data": [{
+                "instId": "USDY-USDC",
+                "side": "buy",
+                "sz": "100",
+                "px": "1.0505",
+                "tradeId": "130639474",
+                "ts": "1699999995000",
+            }],
+        });
+        assert_eq!(
+            Exchange::Okx
+                .parse_last_trade("USDY-USDC", &okx_trades.to_string())
+                .unwrap(),
+            LAST_TRADE_MS
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_responses() {
+        // wrong market
+        assert!(Exchange::Binance
+            .parse_ticker("USDYUSDT", &binance_body().to_string())
+            .is_err());
+        // missing field
+        let mut body = binance_body();
+        body.as_object_mut().unwrap().remove("askPrice");
+        assert!(Exchange::Binance
+            .parse_ticker("USDYUSDC", &body.to_string())
+            .is_err());
+        // empty book side
+        let mut body = binance_body();
+        body["bidPrice"] = json!("");
+        assert!(Exchange::Binance
+            .parse_ticker("USDYUSDC", &body.to_string())
+            .is_err());
+        // no trades yet
+        assert!(Exchange::Binance.parse_last_trade("USDYUSDC", "[]").is_err());
+        // api errors
+        let error = json!({"retCode": 10001, "retMsg": "Not supported symbols", "result": {}, "time": 0});
+        assert!(Exchange::Bybit
+            .parse_ticker("USDYUSDC", &error.to_string())
+            .is_err());
+        assert!(Exchange::Bybit
+            .parse_last_trade("USDYUSDC", &error.to_string())
+            .is_err());
+        let error = json!({"code": "51001", "msg": "Instrument ID does not exist", "data": []});
+        assert!(Exchange::Okx
+            .parse_ticker("USDY-USDC", &error.to_string())
+            .is_err());
+        assert!(Exchange::Okx.parse_ticker("USDY-USDC", "<html>").is_err());
+    }
+
+    #[test]
+    fn only_usdc_markets_are_accepted() {
+        assert!(Exchange::Binance.quotes_usdc("USDYUSDC"));
+        assert!(Exchange::Bybit.quotes_usdc("USDYUSDC"));
+        assert!(Exchange::Okx.quotes_usdc("USDY-USDC"));
+
+        assert!(!Exchange::Binance.quotes_usdc("USDYUSDT"));
+        assert!(!Exchange::Bybit.quotes_usdc("USDC"));
+        assert!(!Exchange::Okx.quotes_usdc("USDYUSDC"));
+        assert!(!Exchange::Okx.quotes_usdc("USDY-USDT"));
+    }
+
+    #[test]
+    fn validates_tickers() {
+        assert_eq!(
+            ticker().price(TickerPrice::Mid, LAST_TRADE_MS, NOW, 60, 100, 0).unwrap(),
+            dec("1.05")
+        );
+        assert_eq!(
+            ticker().price(TickerPrice::Last, LAST_TRADE_MS, NOW, 60, 100, 0).unwrap(),
+            dec("1.0505")
+        );
+
+        assert!(matches!(
+            ticker().price(TickerPrice::Mid, LAST_TRADE_MS, NOW + 60, 60, 100, 0),
+            Err(SourceError::Stale(_))
+        ));
+        // the spread is 19bps
+        assert!(matches!(
+            ticker().price(TickerPrice::Mid, LAST_TRADE_MS, NOW, 60, 10, 0),
+            Err(SourceError::NoLiquidity(_))
+        ));
+        assert!(matches!(
+            ticker().price(TickerPrice::Mid, LAST_TRADE_MS, NOW, 60, 100, 1_000_000),
+            Err(SourceError::NoLiquidity(_))
+        ));
+        let crossed = CexTicker {
+            bid: dec("1.06"),
+            ..ticker()
+        };
+        assert!(crossed.price(TickerPrice::Mid, LAST_TRADE_MS, NOW, 60, 100, 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn fetches_from_the_ticker_and_trades_endpoints() {
+        let server = MockServer::start_http(|path| match path {
+            "/api/v3/ticker/24hr?symbol=USDYUSDC" => Reply::Result(binance_body()),
+            "/api/v3/trades?symbol=USDYUSDC&limit=1" => Reply::Result(binance_trades()),
+            _ => Reply::Status(404),
+        })
+        .await;
+
+        let quote = source(&server, Exchange::Binance, "USDYUSDC")
+            .fetch(now())
+            .await
+            .unwrap();
+        assert_eq!(quote, Quote::new(dec("1.05")));
+        assert_eq!(server.requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn markets_without_recent_trades_are_stale() {
+        let server = MockServer::start_http(|path| {
+            if path.starts_with("/api/v3/trades") {
+                let mut trades = binance_trades();
+                trades[0]["time"] = json!(LAST_TRADE_MS - 3_600_000);
+                Reply::Result(trades)
+            } else {
+                // a fresh looking ticker
+                Reply::Result(binance_body())
+            }
+        })
+        .await;
+
+        assert!(matches!(
+            source(&server, Exchange::Binance, "USDYUSDC").fetch(now()).await,
+            Err(SourceError::Stale(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn http_errors_fail_the_source() {
+        let server = MockServer::start_http(|_| Reply::Status(429)).await;
+        assert!(matches!(
+            source(&server, Exchange::Bybit, "USDYUSDC").fetch(now()).await,
+            Err(SourceError::Rpc { .. })
+        ));
+
+        let server = MockServer::start_http(|_| Reply::Body("{\"retCode\":".to_string())).await;
+        assert!(matches!(
+            source(&server, Exchange::Bybit, "USDYUSDC").fetch(now()).await,
+            Err(SourceError::InvalidData(_))
+        ));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::*;
+use ethers::types::{H160, I256, U256};
+
+// kept private, the generated decimals() call would clash with the ERC-20 bindings
+mod bindings {
+    use super::*;
+
+    abigen!(
+        AggregatorV3,
+        r#"[
+            function decimals() external view returns (uint8)
+            function latestRoundData() external view returns (uint80, int256, uint256, uint256, uint80)
+        ]"#
+    );
+}
+use bindings::AggregatorV3;
+
+/// The fields of `latestRoundData` that are checked before a round is used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoundData {
+    pub round_id: u128,
+    pub answer: I256,
+    pub updated_at: U256,
+    pub answered_in_round: u128,
+}
+
+impl RoundData {
+    /// Checks the round is complete and no older than `max_age` seconds at `now`, then
+    /// scales the answer by the feed's `decimals`.
+    pub fn price(&self, decimals: u8, now: i64, max_age: u64) -> Result<Decimal, SourceError> {
+        if self.updated_at.is_zero() {
+            return Err(SourceError::InvalidData(format!("round {} is incomplete", self.round_id)));
+        }
+        if self.answered_in_round < self.round_id {
+            return Err(SourceError::Stale(format!(
+                "round {} was answered in round {}",
+                self.round_id, self.answered_in_round
+            )));
+        }
+        let age = i128::from(now) - self.updated_at.min(U256::from(u64::MAX)).as_u64() as i128;
+        if age > max_age as i128 {
+            return Err(SourceError::Stale(format!(
+                "round {} was updated {}s ago, max age is {}s",
+                self.round_id, age, max_age
+            )));
+        }
+        if self.answer <= I256::zero() {
+            return Err(SourceError::InvalidData(format!("answer {} is not positive", self.answer)));
+        }
+        if decimals > 28 || self.answer > I256::from(i128::MAX) {
+            return Err(SourceError::InvalidData(format!("answer {} overflows Decimal", self.answer)));
+        }
+        Decimal::try_from_i128_with_scale(self.answer.as_i128(), decimals as u32)
+            .map_err(|_| SourceError::InvalidData(format!("answer {} overflows Decimal", self.answer)))
+    }
+}
+
+/// USDY price from a Chainlink `AggregatorV3Interface` feed.
+///
+/// The feed's age is measured against the timestamp of the pinned block, so a feed is only
+/// stale if it was stale on chain at the block the run read.
+pub struct ChainlinkSource {
+    pub name: String,
+    pub chain: Chain,
+    pub provider: EvmProvider,
+    pub feed: H160,
+    /// Rounds older than this are rejected, usually the feed's heartbeat plus some slack.
+    pub max_age_seconds: u64,
+    pub kind: SourceKind,
+    /// The configured weight of the quote, see `Quote.weight`.
+    pub weight: Option<Decimal>,
+}
+
+impl PriceSource for ChainlinkSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> SourceKind {
+        self.kind
+    }
+
+    fn chain(&self) -> Chain {
+        self.chain
+    }
+
+    fn fetch(&self, block: PinnedBlock) -> SourceFuture<'_> {
+        Box::pin(async move {
+            let feed = AggregatorV3::new(self.feed, self.provider.clone().into());
+            let (decimals, (round_id, answer, _, updated_at, answered_in_round)) =
+                futures::future::try_join(
+                    feed.decimals().block(block.id()).call(),
+                    feed.latest_round_data().block(block.id()).call(),
+                )
+                .await
+                .map_err(SourceError::rpc("decimals/latestRoundData"))?;
+
+            let round = RoundData {
+                round_id,
+                answer,
+                updated_at,
+                answered_in_round,
+            };
+            println!("{}: feed {:?} {:?}", self.name, self.feed, round);
+            Ok(Quote {
+                weight: self.weight,
+                ..Quote::new(round.price(decimals, block.timestamp, self.max_age_seconds)?)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round() -> RoundData {
+        RoundData {
+            round_id: 110680464442257320000,
+            answer: I256::from(100_002_000),
+            updated_at: U256::from(1_700_000_000),
+            answered_in_round: 110680464442257320000,
+        }
+    }
+
+    #[test]
+    fn fresh_rounds_are_scaled_by_decimals() {
+        assert_eq!(
+            round().price(8, 1_700_003_600, 86_400).unwrap(),
+            Decimal::from_str("1.00002").unwrap()
+        );
+    }
+
+    #[test]
+    fn stale_rounds_are_rejected() {
+        assert!(matches!(
+            round().price(8, 1_700_086_401, 86_400),
+            Err(SourceError::Stale(_))
+        ));
+
+        let carried_over = RoundData {
+            answered_in_round: round().round_id - 1,
+            ..round()
+        };
+        assert!(matches!(
+            carried_over.price(8, 1_700_000_000, 86_400),
+            Err(SourceError::Stale(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_answers_are_rejected() {
+        let negative = RoundData {
+            answer: I256::from(-1),
+            ..round()
+        };
+        assert!(negative.price(8, 1_700_000_000, 86_400).is_err());
+
+        let incomplete = RoundData {
+            updated_at: U256::zero(),
+            ..round()
+        };
+        assert!(incomplete.price(8, 1_700_000_000, 86_400).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use crate::*;
+use ethers::types::{H160, U256};
+use serde::Deserialize;
+
+// kept private, the generated call types would clash with the other pool bindings
+mod bindings {
+    use super::*;
+
+    abigen!(
+        CurvePool,
+        r#"[
+            function coins(uint256 i) external view returns (address)
+            function balances(uint256 i) external view returns (uint256)
+            function get_dy(int128 i, int128 j, uint256 dx) external view returns (uint256)
+            function price_oracle(uint256 i) external view returns (uint256)
+        ]"#
+    );
+
+    abigen!(
+        CurveLegacyOracle,
+        r#"[
+            function price_oracle() external view returns (uint256)
+        ]"#
+    );
+}
+use bindings::{CurveLegacyOracle, CurvePool};
+
+/// Curve pools hold at most 8 coins.
+pub const MAX_CURVE_COINS: u64 = 8;
+
+/// Curve's `price_oracle` EMAs are fixed point numbers with 18 decimals.
+pub const CURVE_ORACLE_DECIMALS: u32 = 18;
+
+/// Which EMA price oracle a Curve pool exposes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveOracle {
+    /// No oracle, the pool is priced from `get_dy` alone.
+    #[default]
+    None,
+    /// `price_oracle()` on two coin pools, the price of coin 1 in coin 0.
+    Single,
+    /// `price_oracle(i)` on StableSwap-NG pools, the price of coin `i + 1` in coin 0.
+    Indexed,
+}
+
+/// USDY/USDC price from a Curve StableSwap pool.
+///
+/// The price is the pool's `price_oracle` EMA when it has one, so a single block can't move
+/// it, and otherwise the rate `get_dy` gives for swapping `notional` USDY. The quote is
+/// weighted by the pool's USDY and USDC balances valued in USDC at the quoted price, its
+/// depth in USDC like the weight of every other pool source.
+pub struct CurveSource {
+    pub name: String,
+    pub chain: Chain,
+    pub provider: EvmProvider,
+    pub pool: H160,
+    pub usdy: H160,
+    pub usdc: H160,
+    /// Amount of USDY, in whole tokens, quoted with `get_dy`.
+    pub notional: u64,
+    pub oracle: CurveOracle,
+}
+
+impl CurveSource {
+    /// Indices of USDY and USDC in the pool's coins.
+    async fn coin_indices(
+        &self,
+        pool: &CurvePool<EvmProvider>,
+        block: PinnedBlock,
+    ) -> Result<(u64, u64), SourceError> {
+        let mut base = None;
+        let mut quote = None;
+        for i in 0..MAX_CURVE_COINS {
+            let coin = match pool.coins(U256::from(i)).block(block.id()).call().await {
+                Ok(coin) => coin,
+                // coins(i) reverts past the last coin
+                Err(e) if is_revert(&e) => break,
+                Err(e) => return Err(SourceError::rpc("coins")(e)),
+            };
+            if coin == self.usdy {
+                base = Some(i);
+            } else if coin == self.usdc {
+                quote = Some(i);
+            }
+        }
+        match (base, quote) {
+            (Some(base), Some(quote)) => Ok((base, quote)),
+            _ => Err(SourceError::InvalidData(format!(
+                "pool {:?} doesn't hold both {:?} and {:?}",
+                self.pool, self.usdy, self.usdc
+            ))),
+        }
+    }
+
+    /// Price of every coin up to `max_index` in coin 0, from the pool's EMA oracle.
+    async fn oracle_prices(
+        &self,
+        pool: &CurvePool<EvmProvider>,
+        max_index: u64,
+        block: PinnedBlock,
+    ) -> Result<Vec<Decimal>, SourceError> {
+        let mut prices = vec![Decimal::ONE];
+        for i in 1..=max_index {
+            let price = match self.oracle {
+                CurveOracle::Indexed => pool
+                    .price_oracle(U256::from(i - 1))
+                    .block(block.id())
+                    .call()
+                    .await
+                    .map_err(SourceError::rpc("price_oracle"))?,
+                CurveOracle::Single if i == 1 => {
+                    CurveLegacyOracle::new(self.pool, self.provider.clone().into())
+                        .price_oracle()
+                        .block(block.id())
+                        .call()
+                        .await
+                        .map_err(SourceError::rpc("price_oracle"))?
+                }
+                _ => {
+                    return Err(SourceError::InvalidData(format!(
+                        "{:?} oracle can't price coin {}",
+                        self.oracle, i
+                    )))
+                }
+            };
+            prices.push(oracle_to_decimal(price)?);
+        }
+        Ok(prices)
+    }
+}
+
+fn oracle_to_decimal(price: U256) -> Result<Decimal, SourceError> {
+    if price > U256::from(i128::MAX as u128) {
+        return Err(SourceError::InvalidData(format!("oracle price {} overflows Decimal", price)));
+    }
+    Decimal::try_from_i128_with_scale(price.as_u128() as i128, CURVE_ORACLE_DECIMALS)
+        .map_err(|_| SourceError::InvalidData(format!("oracle price {} overflows Decimal", price)))
+}
+
+/// Price of coin `base` in coin `quote`, given the price of every coin in coin 0.
+pub fn cross_price(base: usize, quote: usize, coin_prices: &[Decimal]) -> Result<Decimal, SourceError> {
+    match (coin_prices.get(base), coin_prices.get(quote)) {
+        (Some(base), Some(quote)) if !quote.is_zero() => Ok(base / quote),
+        _ => Err(SourceError::InvalidData("missing oracle price".to_string())),
+    }
+}
+
+impl PriceSource for CurveSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> SourceKind {
+        SourceKind::Market
+    }
+
+    fn chain(&self) -> Chain {
+        self.chain
+    }
+
+    fn fetch(&self, block: PinnedBlock) -> SourceFuture<'_> {
+        Box::pin(async move {
+            let pool = CurvePool::new(self.pool, self.provider.clone().into());
+            let (base, quote) = self.coin_indices(&pool, block).await?;
+            let (base_decimals, quote_decimals) = futures::future::try_join(
+                token_decimals(&self.provider, self.usdy, block),
+                token_decimals(&self.provider, self.usdc, block),
+            )
+            .await?;
+
+            let (base_balance, quote_balance) = futures::future::try_join(
+                pool.balances(U256::from(base)).block(block.id()).call(),
+                pool.balances(U256::from(quote)).block(block.id()).call(),
+            )
+            .await
+            .map_err(SourceError::rpc("balances"))?;
+
+            let dx = U256::from(self.notional) * U256::exp10(base_decimals as usize);
+            let dy = pool
+                .get_dy(base as i128, quote as i128, dx)
+                .block(block.id())
+                .call()
+                .await
+                .map_err(SourceError::rpc("get_dy"))?;
+            let swap_price = exchange_rate(dx, dy, base_decimals, quote_decimals)?;
+            println!(
+                "{}: pool {:?} get_dy price {} for {} USDY",
+                self.name, self.pool, swap_price, self.notional
+            );
+
+            let price = if self.oracle == CurveOracle::None {
+                swap_price
+            } else {
+                let coin_prices = self.oracle_prices(&pool, base.max(quote), block).await?;
+                let oracle_price = cross_price(base as usize, quote as usize, &coin_prices)?;
+                println!("{}: pool {:?} oracle price {}", self.name, self.pool, oracle_price);
+                oracle_price
+            };
+            let weight = token_amount(base_balance, base_decimals)? * price
+                + token_amount(quote_balance, quote_decimals)?;
+
+            Ok(Quote {
+                price,
+                weight: Some(weight),
+                flags: vec![],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_evm::EvmFixture;
+
+    const POOL: &str = "0x5555555555555555555555555555555555555555";
+    const USDY: &str = "0x96F6eF951840721AdBF46Ac996b59E0235CB985C";
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    /// A two coin pool, USDC then USDY, swapping 1000 USDY for 1050 USDC.
+    fn fixture() -> EvmFixture {
+        let mut fixture =
+            EvmFixture::load(r#"{"block": {"number": 100, "timestamp": 1700000000}, "calls": []}"#);
+        fixture.set(POOL, "coins(uint256) returns (address)", &["0"], &[USDC]);
+        fixture.set(POOL, "coins(uint256) returns (address)", &["1"], &[USDY]);
+        fixture.set(USDY, "decimals() returns (uint8)", &[], &["18"]);
+        fixture.set(USDC, "decimals() returns (uint8)", &[], &["6"]);
+        fixture.set(POOL, "balances(uint256) returns (uint256)", &["0"], &["1000000000000"]);
+        fixture.set(
+            POOL,
+            "balances(uint256) returns (uint256)",
+            &["1"],
+            &["1000000000000000000000000"],
+        );
+        fixture.set(
+            POOL,
+            "get_dy(int128,int128,uint256) returns (uint256)",
+            &["1", "0", "1000000000000000000000"],
+            &["1050000000"],
+        );
+        fixture
+    }
+
+    async fn fetch(fixture: EvmFixture) -> Result<Quote, SourceError> {
+        let block = PinnedBlock {
+            number: fixture.block.number,
+            timestamp: fixture.block.timestamp as i64,
+        };
+        let server = fixture.serve().await;
+        let rpc = RpcConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        let source = CurveSource {
+            name: "curve".to_string(),
+            chain: Chain::Ethereum,
+            provider: EvmProvider::new(FailoverClient::new(&[server.url.clone()], rpc).unwrap()),
+            pool: POOL.parse().unwrap(),
+            usdy: USDY.parse().unwrap(),
+            usdc: USDC.parse().unwrap(),
+            notional: 1_000,
+            oracle: CurveOracle::None,
+        };
+        source.fetch(block).await
+    }
+
+    #[tokio::test]
+    async fn coins_are_listed_until_coins_reverts() {
+        // coins(2) isn't in the fixture, so it reverts
+        let quote = fetch(fixture()).await.unwrap();
+        assert_eq!(quote.price, dec("1.05"));
+        // 1M USDY at 1.05 plus 1M USDC
+        assert_eq!(quote.weight, Some(dec("2050000")));
+    }
+
+    #[tokio::test]
+    async fn node_failures_listing_coins_are_not_taken_for_the_end() {
+        let mut fixture = fixture();
+        fixture.fail(POOL, "coins(uint256) returns (address)", &["1"]);
+        let e = fetch(fixture).await.unwrap_err();
+        assert!(matches!(e, SourceError::Rpc { call: "coins", .. }));
+    }
+
+    #[test]
+    fn cross_prices_go_through_coin_0() {
+        // coin 0 = USDC, coin 1 = USDY at 1.05 USDC
+        let prices = [Decimal::ONE, dec("1.05")];
+        assert_eq!(cross_price(1, 0, &prices).unwrap(), dec("1.05"));
+        assert_eq!(cross_price(0, 1, &prices).unwrap().round_dp(6), dec("0.952381"));
+
+        // coin 0 = DAI, coin 1 = USDC slightly off peg, coin 2 = USDY
+        let prices = [Decimal::ONE, dec("0.999"), dec("1.04895")];
+        assert_eq!(cross_price(2, 1, &prices).unwrap(), dec("1.05"));
+        assert!(cross_price(3, 1, &prices).is_err());
+    }
+
+    #[test]
+    fn oracle_prices_are_scaled() {
+        assert_eq!(
+            oracle_to_decimal(U256::from(1_050_000_000_000_000_000_u64)).unwrap(),
+            dec("1.05")
+        );
+        assert!(oracle_to_decimal(U256::max_value()).is_err());
+    }
+}
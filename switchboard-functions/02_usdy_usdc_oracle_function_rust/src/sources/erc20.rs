@@ -0,0 +1,68 @@
+use crate::*;
+use ethers::types::H160;
+
+abigen!(
+    Erc20,
+    r#"[
+        function decimals() external view returns (uint8)
+    ]"#
+);
+
+/// Reads `decimals()` from an ERC-20 token.
+pub async fn token_decimals(
+    provider: &EvmProvider,
+    token: H160,
+    block: PinnedBlock,
+) -> Result<u8, SourceError> {
+    Erc20::new(token, provider.clone().into())
+        .decimals()
+        .block(block.id())
+        .call()
+        .await
+        .map_err(SourceError::rpc("decimals"))
+}
+
+/// Token layout of a pool, normalized so prices can be quoted as "USDY in quote currency".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolTokens {
+    pub token0_decimals: u8,
+    pub token1_decimals: u8,
+    pub base_is_token0: bool,
+}
+
+impl PoolTokens {
+    /// Checks that the pool's `token0`/`token1` are exactly `base` and `quote` in either order,
+    /// then reads both tokens' decimals.
+    pub async fn resolve(
+        provider: &EvmProvider,
+        pool: H160,
+        token0: H160,
+        token1: H160,
+        base: H160,
+        quote: H160,
+        block: PinnedBlock,
+    ) -> Result<PoolTokens, SourceError> {
+        let base_is_token0 = if token0 == base && token1 == quote {
+            true
+        } else if token0 == quote && token1 == base {
+            false
+        } else {
+            return Err(SourceError::InvalidData(format!(
+                "pool {:?} holds {:?}/{:?}, expected {:?}/{:?}",
+                pool, token0, token1, base, quote
+            )));
+        };
+
+        let (token0_decimals, token1_decimals) = futures::future::try_join(
+            token_decimals(provider, token0, block),
+            token_decimals(provider, token1, block),
+        )
+        .await?;
+
+        Ok(PoolTokens {
+            token0_decimals,
+            token1_decimals,
+            base_is_token0,
+        })
+    }
+}
@@ -0,0 +1,303 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+
+pub mod erc20;
+pub use erc20::*;
+
+pub mod ondo;
+pub use ondo::*;
+
+pub mod uniswap_v3;
+pub use uniswap_v3::*;
+
+pub mod uniswap_v2;
+pub use uniswap_v2::*;
+
+pub mod curve;
+pub use curve::*;
+
+pub mod chainlink;
+pub use chainlink::*;
+
+pub mod solana_clmm;
+pub use solana_clmm::*;
+
+pub mod cex;
+pub use cex::*;
+
+pub type EvmProvider = ethers::providers::Provider<FailoverClient>;
+
+/// The `FunctionRunner`'s Solana RPC client.
+pub type SolanaClient = std::sync::Arc<solana_client::rpc_client::RpcClient>;
+
+pub type SourceFuture<'a> = Pin<Box<dyn Future<Output = Result<Quote, SourceError>> + Send + 'a>>;
+
+/// Why a source failed to produce a quote.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SourceError {
+    /// A contract call failed or its result couldn't be decoded.
+    Rpc { call: &'static str, message: String },
+    /// The chain returned data that can't be priced, e.g. a pool for the wrong pair.
+    InvalidData(String),
+    /// There is nothing to price from, e.g. no pool with enough liquidity.
+    NoLiquidity(String),
+    /// The source's own data is too old to use, e.g. a Chainlink round past its max age.
+    Stale(String),
+    /// The source didn't produce a quote within `rpc.source_timeout_ms`.
+    Timeout(std::time::Duration),
+}
+
+impl SourceError {
+    /// Maps the error of a failed `call`, for use with `map_err`.
+    pub fn rpc<E: std::fmt::Display>(call: &'static str) -> impl FnOnce(E) -> SourceError {
+        move |e| SourceError::Rpc {
+            call,
+            message: e.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceError::Rpc { call, message } => write!(f, "{} failed: {}", call, message),
+            SourceError::InvalidData(message) => write!(f, "invalid data: {}", message),
+            SourceError::NoLiquidity(message) => write!(f, "no liquidity: {}", message),
+            SourceError::Stale(message) => write!(f, "stale: {}", message),
+            SourceError::Timeout(timeout) => write!(f, "timed out after {:?}", timeout),
+        }
+    }
+}
+
+impl From<SbError> for SourceError {
+    fn from(e: SbError) -> Self {
+        match e {
+            SbError::CustomMessage(message) => SourceError::InvalidData(message),
+            e => SourceError::InvalidData(format!("{:?}", e)),
+        }
+    }
+}
+
+/// Caveats attached to a quote so they can be reported in the function output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuoteFlag {
+    /// A TWAP was requested but the pool's observation history doesn't cover the window,
+    /// so the instantaneous slot0 price was used instead.
+    SpotPriceFallback,
+}
+
+/// A price for USDY, quoted in USD, as returned by a `PriceSource`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quote {
+    pub price: Decimal,
+    /// Weight of this quote when aggregating: the depth of a pool in USDC, twice its USDC
+    /// reserve, or the configured `weight` of a CEX or Chainlink source. `None` for sources
+    /// without a notion of depth.
+    pub weight: Option<Decimal>,
+    pub flags: Vec<QuoteFlag>,
+}
+
+impl Quote {
+    pub fn new(price: Decimal) -> Quote {
+        Quote {
+            price,
+            weight: None,
+            flags: vec![],
+        }
+    }
+}
+
+/// What a source's price represents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    /// Issuer-published net asset value, e.g. Ondo's USDY oracle.
+    Nav,
+    /// Price discovered by trading, e.g. a DEX pool.
+    Market,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Chain {
+    Ethereum,
+    Mantle,
+    Solana,
+    /// Sources read over HTTP rather than from a chain, e.g. exchange tickers.
+    Offchain,
+}
+
+/// A single price input for USDY, quoted in USD.
+pub trait PriceSource: Send + Sync {
+    fn name(&self) -> &str;
+    fn kind(&self) -> SourceKind;
+    fn chain(&self) -> Chain;
+    /// Fetches a quote with every read issued at `block` on the source's chain.
+    fn fetch(&self, block: PinnedBlock) -> SourceFuture<'_>;
+}
+
+pub type Providers = std::collections::HashMap<Chain, EvmProvider>;
+
+/// Builds a provider for every EVM chain in the config, sending its requests through `tape`.
+pub fn build_providers(config: &FunctionConfig, tape: &RpcTape) -> Result<Providers, SbError> {
+    let mut providers = Providers::default();
+    for (chain, chain_config) in [(Chain::Ethereum, &config.ethereum), (Chain::Mantle, &config.mantle)] {
+        // urls were validated when loading the config
+        let client = FailoverClient::new(&chain_config.rpc_urls, config.rpc.clone())
+            .map_err(|e| SbError::CustomMessage(format!("invalid {:?} rpc urls: {:?}", chain, e)))?
+            .with_tape(chain, tape.clone());
+        providers.insert(chain, EvmProvider::new(client));
+    }
+    Ok(providers)
+}
+
+/// Builds a `PriceSource` for every entry in `config.sources`.
+///
+/// EVM sources read through `providers`, Solana sources through the runner's `solana` client
+/// and exchange tickers over plain HTTP through `tape`, which also keeps the run's clock.
+/// The config must have been validated, so every source's chain is configured.
+pub fn build_sources(
+    config: &FunctionConfig,
+    providers: &Providers,
+    solana: &SolanaClient,
+    tape: &RpcTape,
+) -> Vec<Box<dyn PriceSource>> {
+    config
+        .sources
+        .iter()
+        .map(|source| -> Box<dyn PriceSource> {
+            if let SourceConfig::SolanaClmm {
+                name,
+                program,
+                pool,
+                min_liquidity,
+            } = source
+            {
+                let solana_config = config.solana.clone().unwrap_or_default();
+                return Box::new(SolanaClmmSource {
+                    name: name.clone(),
+                    client: solana.clone(),
+                    program: *program,
+                    pool: *pool,
+                    usdy: solana_config.usdy,
+                    usdc: solana_config.usdc,
+                    block_tag: solana_config.block_tag,
+                    min_liquidity: *min_liquidity,
+                });
+            }
+            if let SourceConfig::CexTicker {
+                name,
+                exchange,
+                base_url,
+                symbol,
+                price,
+                max_age_seconds,
+                max_spread_bps,
+                min_volume,
+                weight,
+            } = source
+            {
+                return Box::new(CexTickerSource {
+                    name: name.clone(),
+                    client: reqwest::Client::new(),
+                    exchange: *exchange,
+                    base_url: base_url.clone(),
+                    symbol: symbol.clone(),
+                    price: *price,
+                    max_age_seconds: *max_age_seconds,
+                    max_spread_bps: *max_spread_bps,
+                    min_volume: *min_volume,
+                    weight: weight.map(Decimal::from),
+                    timeout: config.rpc.timeout(),
+                    tape: tape.clone(),
+                });
+            }
+            let provider = providers[&source.chain()].clone();
+            let tokens = config.chain(source.chain()).expect("EVM chains are always configured");
+            match source {
+                SourceConfig::UniswapV3 {
+                    name,
+                    chain,
+                    factory,
+                    twap_seconds,
+                    fee_tiers,
+                    min_liquidity,
+                } => Box::new(UniswapV3Source {
+                    name: name.clone(),
+                    chain: *chain,
+                    provider,
+                    factory: *factory,
+                    usdy: tokens.usdy,
+                    usdc: tokens.usdc,
+                    twap_seconds: *twap_seconds,
+                    fee_tiers: fee_tiers.clone(),
+                    min_liquidity: *min_liquidity,
+                }),
+                SourceConfig::UniswapV2 {
+                    name,
+                    chain,
+                    pair,
+                    stable,
+                    min_liquidity,
+                } => Box::new(UniswapV2Source {
+                    name: name.clone(),
+                    chain: *chain,
+                    provider,
+                    pair: *pair,
+                    usdy: tokens.usdy,
+                    usdc: tokens.usdc,
+                    stable: *stable,
+                    min_liquidity: *min_liquidity,
+                }),
+                SourceConfig::Curve {
+                    name,
+                    chain,
+                    pool,
+                    notional,
+                    price_oracle,
+                } => Box::new(CurveSource {
+                    name: name.clone(),
+                    chain: *chain,
+                    provider,
+                    pool: *pool,
+                    usdy: tokens.usdy,
+                    usdc: tokens.usdc,
+                    notional: *notional,
+                    oracle: *price_oracle,
+                }),
+                SourceConfig::Chainlink {
+                    name,
+                    chain,
+                    feed,
+                    max_age_seconds,
+                    kind,
+                    weight,
+                } => Box::new(ChainlinkSource {
+                    name: name.clone(),
+                    chain: *chain,
+                    provider,
+                    feed: *feed,
+                    max_age_seconds: *max_age_seconds,
+                    kind: *kind,
+                    weight: weight.map(Decimal::from),
+                }),
+                SourceConfig::Ondo {
+                    name,
+                    chain,
+                    oracle,
+                    submission_delay_seconds,
+                } => Box::new(OndoSource {
+                    name: name.clone(),
+                    chain: *chain,
+                    provider,
+                    oracle: *oracle,
+                    submission_delay_seconds: *submission_delay_seconds,
+                    tape: tape.clone(),
+                }),
+                SourceConfig::SolanaClmm { .. } | SourceConfig::CexTicker { .. } => {
+                    unreachable!("non-EVM sources are built above")
+                }
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,380 @@
+use crate::*;
+use ethers::types::{H160, U256};
+
+abigen!(Ondo, "./src/ondo.json");
+
+/// Ondo's oracle reports prices as fixed point numbers with 18 decimals. The oracle
+/// does not expose `decimals()`, so this mirrors the contract's constant.
+pub const ONDO_PRICE_DECIMALS: u32 = 18;
+
+/// Ranges beyond this index are never looked up, Ondo sets roughly one range a month.
+pub const MAX_ONDO_RANGES: u64 = 1 << 16;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// A NAV accrual range of Ondo's `RWADynamicOracle`.
+///
+/// Within a range the price compounds `daily_interest_rate` (a ray, 27 decimals) once per
+/// elapsed day on top of `prev_range_close_price`, the closing price of the previous range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OndoRange {
+    pub start: u64,
+    pub end: u64,
+    pub daily_interest_rate: U256,
+    pub prev_range_close_price: U256,
+}
+
+impl OndoRange {
+    /// Port of the oracle's `derivePrice`, the price at `time` within this range.
+    pub fn derive_price(&self, time: u64) -> Result<U256, SourceError> {
+        let ray = U256::exp10(27);
+        let elapsed_days = time.saturating_sub(self.start) / SECONDS_PER_DAY;
+        let growth = rpow(self.daily_interest_rate, elapsed_days + 1, ray)?;
+        let price =
+            growth
+                .checked_mul(self.prev_range_close_price)
+                .ok_or(SourceError::InvalidData(
+                    "range price overflows".to_string(),
+                ))?
+                / ray;
+        Ok(round_to_8_decimals(price))
+    }
+}
+
+/// The price `getPrice()` returns at `time`: the price within the latest range that has
+/// started, frozen at the range's last second once it has ended.
+pub fn price_at(ranges: &[OndoRange], time: u64) -> Result<U256, SourceError> {
+    let range = ranges
+        .iter()
+        .rev()
+        .find(|range| range.start <= time)
+        .ok_or(SourceError::InvalidData(format!(
+            "no range has started at {}",
+            time
+        )))?;
+    if range.end <= time {
+        range.derive_price(range.end - 1)
+    } else {
+        range.derive_price(time)
+    }
+}
+
+/// Port of the oracle's `roundUpTo8`, rounding an 18 decimal price half up to 8 decimals.
+fn round_to_8_decimals(value: U256) -> U256 {
+    let unit = U256::exp10(10);
+    let remainder = value % unit;
+    let rounded = if remainder >= unit / 2 {
+        value + unit
+    } else {
+        value
+    };
+    rounded - remainder
+}
+
+/// Port of MakerDAO's `rpow`, `x^n` in fixed point with `base` as one, rounding half up
+/// after every multiplication like the on-chain assembly.
+pub fn rpow(mut x: U256, mut n: u64, base: U256) -> Result<U256, SourceError> {
+    let overflow = || SourceError::InvalidData("interest rate overflows".to_string());
+    if x.is_zero() {
+        return Ok(if n == 0 { base } else { U256::zero() });
+    }
+    let half = base / 2;
+    let mut z = if n % 2 == 0 { base } else { x };
+    n /= 2;
+    while n > 0 {
+        x = x
+            .checked_mul(x)
+            .and_then(|xx| xx.checked_add(half))
+            .ok_or_else(overflow)?
+            / base;
+        if n % 2 == 1 {
+            z = z
+                .checked_mul(x)
+                .and_then(|zx| zx.checked_add(half))
+                .ok_or_else(overflow)?
+                / base;
+        }
+        n /= 2;
+    }
+    Ok(z)
+}
+
+fn price_to_decimal(price: U256) -> Result<Decimal, SourceError> {
+    Some(price)
+        .filter(|price| *price <= U256::from(i128::MAX as u128))
+        .and_then(|price| {
+            Decimal::try_from_i128_with_scale(price.as_u128() as i128, ONDO_PRICE_DECIMALS).ok()
+        })
+        .ok_or(SourceError::InvalidData(format!(
+            "price {} overflows Decimal",
+            price
+        )))
+}
+
+/// USDY's NAV as published by Ondo's on-chain price oracle.
+///
+/// `getPrice()` is checked against the oracle's published accrual ranges, then the NAV is
+/// projected along those ranges to the time the report lands on Solana, so the reported
+/// price doesn't lag behind by the time since the pinned block.
+pub struct OndoSource {
+    pub name: String,
+    pub chain: Chain,
+    pub provider: EvmProvider,
+    pub oracle: H160,
+    /// Expected delay between the run and its report landing on Solana.
+    pub submission_delay_seconds: u64,
+    /// Clock of the run, replayed along with its requests.
+    pub tape: RpcTape,
+}
+
+impl OndoSource {
+    /// Range `index`, or `None` past the last range. Failing to read it is an error, so a
+    /// flaky node can't cut the search short.
+    async fn range(
+        &self,
+        ondo: &Ondo<EvmProvider>,
+        index: u64,
+        block: PinnedBlock,
+    ) -> Result<Option<OndoRange>, SourceError> {
+        let (start, end, daily_interest_rate, prev_range_close_price) =
+            match ondo.ranges(U256::from(index)).block(block.id()).call().await {
+                Ok(range) => range,
+                // ranges(i) reverts past the last range
+                Err(e) if is_revert(&e) => return Ok(None),
+                Err(e) => return Err(SourceError::rpc("ranges")(e)),
+            };
+        if start > U256::from(u64::MAX) || end > U256::from(u64::MAX) {
+            return Err(SourceError::InvalidData(format!(
+                "range {} runs from {} to {}",
+                index, start, end
+            )));
+        }
+        Ok(Some(OndoRange {
+            start: start.as_u64(),
+            end: end.as_u64(),
+            daily_interest_rate,
+            prev_range_close_price,
+        }))
+    }
+
+    /// Index of the last range, found with an exponential then a binary search since the
+    /// oracle doesn't expose the number of ranges.
+    async fn last_range_index(
+        &self,
+        ondo: &Ondo<EvmProvider>,
+        block: PinnedBlock,
+    ) -> Result<u64, SourceError> {
+        if self.range(ondo, 0, block).await?.is_none() {
+            return Err(SourceError::InvalidData(format!(
+                "oracle {:?} has no ranges",
+                self.oracle
+            )));
+        }
+        let (mut low, mut high) = (0, 1);
+        while self.range(ondo, high, block).await?.is_some() {
+            low = high;
+            high *= 2;
+            if high > MAX_ONDO_RANGES {
+                return Err(SourceError::InvalidData(format!(
+                    "oracle {:?} has more than {} ranges",
+                    self.oracle, MAX_ONDO_RANGES
+                )));
+            }
+        }
+        while high - low > 1 {
+            let mid = (low + high) / 2;
+            if self.range(ondo, mid, block).await?.is_some() {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+
+    /// The ranges from the one active at the pinned block to the last one set, in order.
+    async fn current_ranges(
+        &self,
+        ondo: &Ondo<EvmProvider>,
+        block: PinnedBlock,
+    ) -> Result<Vec<OndoRange>, SourceError> {
+        let mut index = self.last_range_index(ondo, block).await?;
+        let mut ranges = vec![];
+        loop {
+            let range = self
+                .range(ondo, index, block)
+                .await?
+                .ok_or(SourceError::InvalidData(format!(
+                    "range {} disappeared",
+                    index
+                )))?;
+            ranges.insert(0, range);
+            if range.start <= block.timestamp as u64 || index == 0 {
+                return Ok(ranges);
+            }
+            index -= 1;
+        }
+    }
+}
+
+impl PriceSource for OndoSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> SourceKind {
+        SourceKind::Nav
+    }
+
+    fn chain(&self) -> Chain {
+        self.chain
+    }
+
+    fn fetch(&self, block: PinnedBlock) -> SourceFuture<'_> {
+        Box::pin(async move {
+            let ondo = Ondo::new(self.oracle, self.provider.clone().into());
+
+            let price = ondo
+                .get_price()
+                .block(block.id())
+                .call()
+                .await
+                .map_err(SourceError::rpc("getPrice"))?;
+            println!("Ondo price: {:?}", price);
+
+            let ranges = self.current_ranges(&ondo, block).await?;
+            let block_time = block.timestamp.max(0) as u64;
+            let expected = price_at(&ranges, block_time)?;
+            if price != expected {
+                return Err(SourceError::InvalidData(format!(
+                    "getPrice() {} doesn't match {} accrued along {:?}",
+                    price, expected, ranges
+                )));
+            }
+
+            let submission_time = (self.tape.now().timestamp.max(0) as u64
+                + self.submission_delay_seconds)
+                .max(block_time);
+            let projected = price_at(&ranges, submission_time)?;
+            println!(
+                "Ondo price projected to {}: {:?}",
+                submission_time, projected
+            );
+            Ok(Quote::new(price_to_decimal(projected)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_evm::EvmFixture;
+
+    const ONDO_ORACLE: &str = "0xa0219aa5b31e65bc920b5b6dfb8edf0988121de0";
+    const RANGES: &str = "ranges(uint256) returns (uint256,uint256,uint256,uint256)";
+
+    async fn fetch(fixture: EvmFixture) -> Result<Quote, SourceError> {
+        let block = PinnedBlock {
+            number: fixture.block.number,
+            timestamp: fixture.block.timestamp as i64,
+        };
+        let server = fixture.serve().await;
+        let rpc = RpcConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        let source = OndoSource {
+            name: "ondo".to_string(),
+            chain: Chain::Ethereum,
+            provider: EvmProvider::new(FailoverClient::new(&[server.url.clone()], rpc).unwrap()),
+            oracle: ONDO_ORACLE.parse().unwrap(),
+            submission_delay_seconds: 0,
+            tape: RpcTape::Off,
+        };
+        source.fetch(block).await
+    }
+
+    #[tokio::test]
+    async fn node_failures_are_not_taken_for_the_last_range() {
+        let fixture = EvmFixture::load(include_str!("../fixtures/ethereum.json"));
+        // the fixture has a single range, ranges(1) reverts
+        assert!(fetch(fixture.clone()).await.is_ok());
+
+        let mut failing = fixture;
+        failing.fail(ONDO_ORACLE, RANGES, &["1"]);
+        let e = fetch(failing).await.unwrap_err();
+        assert!(matches!(e, SourceError::Rpc { call: "ranges", .. }));
+    }
+
+    const START: u64 = 1_700_000_000;
+    const DAY: u64 = SECONDS_PER_DAY;
+
+    fn ranges() -> Vec<OndoRange> {
+        let first = OndoRange {
+            start: START,
+            end: START + 30 * DAY,
+            daily_interest_rate: U256::from_dec_str("1000131849000000000000000000").unwrap(),
+            prev_range_close_price: U256::from(1_040_000_000_000_000_000_u64),
+        };
+        let second = OndoRange {
+            start: first.end,
+            end: first.end + 30 * DAY,
+            daily_interest_rate: U256::from_dec_str("1000135000000000000000000000").unwrap(),
+            prev_range_close_price: first.derive_price(first.end - 1).unwrap(),
+        };
+        vec![first, second]
+    }
+
+    fn price(time: u64) -> u64 {
+        price_at(&ranges(), time).unwrap().as_u64()
+    }
+
+    #[test]
+    fn accrues_once_per_day() {
+        // one day of interest is applied from the start of a range
+        assert_eq!(price(START), 1_040_137_120_000_000_000);
+        assert_eq!(price(START + DAY - 1), 1_040_137_120_000_000_000);
+        assert_eq!(price(START + DAY), 1_040_274_260_000_000_000);
+        assert_eq!(price(START + 10 * DAY + DAY / 2), 1_041_509_350_000_000_000);
+    }
+
+    #[test]
+    fn continues_from_the_previous_range_close() {
+        assert_eq!(price(START + 30 * DAY - 1), 1_044_121_560_000_000_000);
+        assert_eq!(price(START + 30 * DAY), 1_044_262_520_000_000_000);
+        assert_eq!(price(START + 35 * DAY), 1_044_967_580_000_000_000);
+        // frozen at the last second of the last range once it has ended
+        assert_eq!(price(START + 60 * DAY + 100), 1_048_358_540_000_000_000);
+        assert!(price_at(&ranges(), START - 1).is_err());
+    }
+
+    #[test]
+    fn rounds_half_up_to_8_decimals() {
+        assert_eq!(
+            round_to_8_decimals(U256::from(1_000_000_004_999_999_999_u64)),
+            U256::from(1_000_000_000_000_000_000_u64)
+        );
+        assert_eq!(
+            round_to_8_decimals(U256::from(1_000_000_005_000_000_000_u64)),
+            U256::from(1_000_000_010_000_000_000_u64)
+        );
+    }
+
+    #[test]
+    fn rpow_matches_fixed_point_powers() {
+        let ray = U256::exp10(27);
+        assert_eq!(rpow(ray * 2, 10, ray).unwrap(), ray * 1024);
+        assert_eq!(rpow(U256::zero(), 0, ray).unwrap(), ray);
+        assert_eq!(rpow(U256::zero(), 3, ray).unwrap(), U256::zero());
+        assert!(rpow(U256::max_value(), 2, ray).is_err());
+    }
+
+    #[test]
+    fn prices_are_scaled() {
+        assert_eq!(
+            price_to_decimal(U256::from(1_040_137_120_000_000_000_u64)).unwrap(),
+            Decimal::from_str("1.04013712").unwrap()
+        );
+        assert!(price_to_decimal(U256::max_value()).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use crate::*;
+use ethers::types::U256;
+use serde::Deserialize;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::account::Account;
+
+/// Length of an SPL token mint account, for both the token and token-2022 programs.
+pub const MINT_LEN: usize = 82;
+const MINT_DECIMALS_OFFSET: usize = 44;
+const MINT_INITIALIZED_OFFSET: usize = 45;
+
+pub const WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+pub const RAYDIUM_CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+
+/// The concentrated liquidity program a Solana pool belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClmmProgram {
+    /// Orca Whirlpools.
+    Whirlpool,
+    /// Raydium Concentrated Liquidity.
+    RaydiumClmm,
+}
+
+/// Byte offsets of the fields read from a pool account, including the 8 byte discriminator.
+struct PoolLayout {
+    liquidity: usize,
+    sqrt_price: usize,
+    mint_a: usize,
+    mint_b: usize,
+}
+
+impl ClmmProgram {
+    pub fn program_id(&self) -> Pubkey {
+        let id = match self {
+            ClmmProgram::Whirlpool => WHIRLPOOL_PROGRAM_ID,
+            ClmmProgram::RaydiumClmm => RAYDIUM_CLMM_PROGRAM_ID,
+        };
+        Pubkey::from_str(id).unwrap()
+    }
+
+    /// Name of the Anchor account type holding a pool.
+    fn account_name(&self) -> &'static str {
+        match self {
+            ClmmProgram::Whirlpool => "Whirlpool",
+            ClmmProgram::RaydiumClmm => "PoolState",
+        }
+    }
+
+    fn layout(&self) -> PoolLayout {
+        match self {
+            // whirlpools_config, bump, tick_spacing, tick_spacing_seed, fee_rate and
+            // protocol_fee_rate come first, token_mint_a follows the protocol fees owed
+            ClmmProgram::Whirlpool => PoolLayout {
+                liquidity: 49,
+                sqrt_price: 65,
+                mint_a: 101,
+                mint_b: 181,
+            },
+            // bump, amm_config and owner come first, liquidity follows the vaults,
+            // observation key, mint decimals and tick spacing
+            ClmmProgram::RaydiumClmm => PoolLayout {
+                liquidity: 237,
+                sqrt_price: 253,
+                mint_a: 73,
+                mint_b: 105,
+            },
+        }
+    }
+
+    /// The Anchor discriminator of the pool account, `sha256("account:<name>")[..8]`.
+    pub fn discriminator(&self) -> [u8; 8] {
+        let hash =
+            solana_program::hash::hash(format!("account:{}", self.account_name()).as_bytes());
+        let mut discriminator = [0u8; 8];
+        discriminator.copy_from_slice(&hash.to_bytes()[..8]);
+        discriminator
+    }
+
+    /// Decodes a pool account owned by this program.
+    pub fn decode(&self, owner: &Pubkey, data: &[u8]) -> Result<ClmmPool, SourceError> {
+        if *owner != self.program_id() {
+            return Err(SourceError::InvalidData(format!(
+                "account is owned by {}, not the {:?} program",
+                owner, self
+            )));
+        }
+        let layout = self.layout();
+        let min_len = (layout.sqrt_price + 16)
+            .max(layout.mint_a + 32)
+            .max(layout.mint_b + 32);
+        if data.len() < min_len || data[..8] != self.discriminator() {
+            return Err(SourceError::InvalidData(format!(
+                "account is not a {:?} pool",
+                self
+            )));
+        }
+        Ok(ClmmPool {
+            mint_a: read_pubkey(data, layout.mint_a),
+            mint_b: read_pubkey(data, layout.mint_b),
+            liquidity: read_u128(data, layout.liquidity),
+            sqrt_price_x64: read_u128(data, layout.sqrt_price),
+        })
+    }
+}
+
+fn read_u128(data: &[u8], offset: usize) -> u128 {
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&data[offset..offset + 16]);
+    u128::from_le_bytes(bytes)
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Pubkey {
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&data[offset..offset + 32]);
+    Pubkey::new_from_array(bytes)
+}
+
+/// The state of a Whirlpool or Raydium CLMM pool needed to price it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClmmPool {
+    /// Token a of a Whirlpool, mint 0 of a Raydium pool.
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    /// In-range liquidity, in the same units as Uniswap V3 liquidity.
+    pub liquidity: u128,
+    /// `sqrt(token_b / token_a) * 2^64` in raw token units.
+    pub sqrt_price_x64: u128,
+}
+
+impl ClmmPool {
+    /// Depth of the pool in `quote`, which must be one of its mints, see `clmm_quote_depth`.
+    pub fn quote_depth(&self, quote: Pubkey, quote_decimals: u8) -> Result<Decimal, SourceError> {
+        let quote_is_token_a = if self.mint_a == quote {
+            true
+        } else if self.mint_b == quote {
+            false
+        } else {
+            return Err(SourceError::InvalidData(format!(
+                "pool holds {} / {}, not {}",
+                self.mint_a, self.mint_b, quote
+            )));
+        };
+        Ok(clmm_quote_depth(
+            self.liquidity,
+            U256::from(self.sqrt_price_x64),
+            64,
+            quote_is_token_a,
+            quote_decimals,
+        )?)
+    }
+
+    /// Price of `base` in `quote`, which must be the two mints of the pool.
+    pub fn price(
+        &self,
+        base: Pubkey,
+        quote: Pubkey,
+        base_decimals: u8,
+        quote_decimals: u8,
+    ) -> Result<Decimal, SourceError> {
+        let base_is_token_a = if (self.mint_a, self.mint_b) == (base, quote) {
+            true
+        } else if (self.mint_a, self.mint_b) == (quote, base) {
+            false
+        } else {
+            return Err(SourceError::InvalidData(format!(
+                "pool holds {} / {}, not {} / {}",
+                self.mint_a, self.mint_b, base, quote
+            )));
+        };
+        let (decimals_a, decimals_b) = if base_is_token_a {
+            (base_decimals, quote_decimals)
+        } else {
+            (quote_decimals, base_decimals)
+        };
+        Ok(sqrt_price_x64_to_price(
+            self.sqrt_price_x64,
+            decimals_a,
+            decimals_b,
+            base_is_token_a,
+        )?)
+    }
+}
+
+/// Decimals of an SPL token mint account.
+pub fn mint_decimals(data: &[u8]) -> Result<u8, SourceError> {
+    if data.len() < MINT_LEN || data[MINT_INITIALIZED_OFFSET] != 1 {
+        return Err(SourceError::InvalidData(
+            "account is not an initialized mint".to_string(),
+        ));
+    }
+    Ok(data[MINT_DECIMALS_OFFSET])
+}
+
+/// USDY/USDC price from an Orca Whirlpool or Raydium CLMM pool on Solana.
+///
+/// The pool and both mints are read with a single `getMultipleAccounts` call. Solana nodes
+/// only serve current account state, so the read is issued with the pinned slot as its
+/// minimum context slot: the state is at least as new as the pinned slot, never older.
+pub struct SolanaClmmSource {
+    pub name: String,
+    pub client: SolanaClient,
+    pub program: ClmmProgram,
+    pub pool: Pubkey,
+    pub usdy: Pubkey,
+    pub usdc: Pubkey,
+    pub block_tag: BlockTag,
+    /// Pools with less in-range liquidity than this are ignored.
+    pub min_liquidity: u128,
+}
+
+impl SolanaClmmSource {
+    async fn accounts(&self, block: PinnedBlock) -> Result<Vec<Account>, SourceError> {
+        let client = self.client.clone();
+        let keys = vec![self.pool, self.usdy, self.usdc];
+        let config = RpcAccountInfoConfig {
+            encoding: Some(solana_account_decoder::UiAccountEncoding::Base64),
+            data_slice: None,
+            commitment: Some(self.block_tag.commitment()),
+            min_context_slot: Some(block.number),
+        };
+        // the runner's client is blocking
+        let accounts = tokio::task::spawn_blocking(move || {
+            client.get_multiple_accounts_with_config(&keys, config)
+        })
+        .await
+        .map_err(SourceError::rpc("getMultipleAccounts"))?
+        .map_err(SourceError::rpc("getMultipleAccounts"))?;
+
+        accounts
+            .value
+            .into_iter()
+            .zip([self.pool, self.usdy, self.usdc])
+            .map(|(account, key)| {
+                account.ok_or(SourceError::InvalidData(format!(
+                    "account {} does not exist",
+                    key
+                )))
+            })
+            .collect()
+    }
+}
+
+impl PriceSource for SolanaClmmSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> SourceKind {
+        SourceKind::Market
+    }
+
+    fn chain(&self) -> Chain {
+        Chain::Solana
+    }
+
+    fn fetch(&self, block: PinnedBlock) -> SourceFuture<'_> {
+        Box::pin(async move {
+            let accounts = self.accounts(block).await?;
+            let pool = self.program.decode(&accounts[0].owner, &accounts[0].data)?;
+            let usdy_decimals = mint_decimals(&accounts[1].data)?;
+            let usdc_decimals = mint_decimals(&accounts[2].data)?;
+
+            println!(
+                "{}: {:?} pool {} liquidity {} sqrt_price {}",
+                self.name, self.program, self.pool, pool.liquidity, pool.sqrt_price_x64
+            );
+            if pool.liquidity < self.min_liquidity {
+                return Err(SourceError::NoLiquidity(format!(
+                    "pool {} liquidity {} is below {}",
+                    self.pool, pool.liquidity, self.min_liquidity
+                )));
+            }
+            Ok(Quote {
+                price: pool.price(self.usdy, self.usdc, usdy_decimals, usdc_decimals)?,
+                weight: Some(pool.quote_depth(self.usdc, usdc_decimals)?),
+                flags: vec![],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usdy() -> Pubkey {
+        Pubkey::from_str("A1KLoBrKBde8Ty9qtNQUtq3C2ortoC3u7twggz7sEto6").unwrap()
+    }
+
+    fn usdc() -> Pubkey {
+        Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap()
+    }
+
+    // floor(sqrt(1.05) * 2^64)
+    const SQRT_PRICE_1_05: u128 = 18902287831555877210;
+
+    /// A pool account with every field the source reads set, the rest zeroed.
+    fn pool_fixture(
+        program: ClmmProgram,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+        sqrt_price: u128,
+    ) -> Vec<u8> {
+        let len = match program {
+            ClmmProgram::Whirlpool => 653,
+            ClmmProgram::RaydiumClmm => 1544,
+        };
+        let layout = program.layout();
+        let mut data = vec![0u8; len];
+        data[..8].copy_from_slice(&program.discriminator());
+        data[layout.liquidity..layout.liquidity + 16]
+            .copy_from_slice(&5_000_000_000_u128.to_le_bytes());
+        data[layout.sqrt_price..layout.sqrt_price + 16].copy_from_slice(&sqrt_price.to_le_bytes());
+        data[layout.mint_a..layout.mint_a + 32].copy_from_slice(mint_a.as_ref());
+        data[layout.mint_b..layout.mint_b + 32].copy_from_slice(mint_b.as_ref());
+        data
+    }
+
+    fn mint_fixture(decimals: u8) -> Vec<u8> {
+        let mut data = vec![0u8; MINT_LEN];
+        data[MINT_DECIMALS_OFFSET] = decimals;
+        data[MINT_INITIALIZED_OFFSET] = 1;
+        data
+    }
+
+    #[test]
+    fn decodes_pool_accounts() {
+        for program in [ClmmProgram::Whirlpool, ClmmProgram::RaydiumClmm] {
+            let data = pool_fixture(program, usdy(), usdc(), SQRT_PRICE_1_05);
+            let pool = program.decode(&program.program_id(), &data).unwrap();
+            assert_eq!(
+                pool,
+                ClmmPool {
+                    mint_a: usdy(),
+                    mint_b: usdc(),
+                    liquidity: 5_000_000_000,
+                    sqrt_price_x64: SQRT_PRICE_1_05,
+                }
+            );
+            assert_eq!(
+                pool.price(usdy(), usdc(), 6, 6).unwrap(),
+                Decimal::from_str("1.049999999999999999").unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn prices_usdy_as_either_token() {
+        // USDY as token b at 1.05 USDC, so the pool prices USDC at 1/1.05 USDY
+        let data = pool_fixture(ClmmProgram::Whirlpool, usdc(), usdy(), SQRT_PRICE_1_05);
+        let pool = ClmmProgram::Whirlpool
+            .decode(&ClmmProgram::Whirlpool.program_id(), &data)
+            .unwrap();
+        assert_eq!(
+            pool.price(usdy(), usdc(), 6, 6).unwrap(),
+            Decimal::from_str("0.952380952380952381").unwrap()
+        );
+        assert!(pool.price(usdy(), Pubkey::new_unique(), 6, 6).is_err());
+    }
+
+    #[test]
+    fn depth_is_measured_in_usdc() {
+        // USDC as token b: twice L * sqrt_price / 2^64
+        let data = pool_fixture(ClmmProgram::Whirlpool, usdy(), usdc(), SQRT_PRICE_1_05);
+        let pool = ClmmProgram::Whirlpool
+            .decode(&ClmmProgram::Whirlpool.program_id(), &data)
+            .unwrap();
+        assert_eq!(
+            pool.quote_depth(usdc(), 6).unwrap(),
+            Decimal::from_str("10246.950764").unwrap()
+        );
+        // USDC as token a: twice L * 2^64 / sqrt_price
+        let data = pool_fixture(ClmmProgram::Whirlpool, usdc(), usdy(), SQRT_PRICE_1_05);
+        let pool = ClmmProgram::Whirlpool
+            .decode(&ClmmProgram::Whirlpool.program_id(), &data)
+            .unwrap();
+        assert_eq!(
+            pool.quote_depth(usdc(), 6).unwrap(),
+            Decimal::from_str("9759.000728").unwrap()
+        );
+        assert!(pool.quote_depth(Pubkey::new_unique(), 6).is_err());
+    }
+
+    #[test]
+    fn rejects_foreign_accounts() {
+        let data = pool_fixture(ClmmProgram::Whirlpool, usdy(), usdc(), SQRT_PRICE_1_05);
+        // wrong owner
+        assert!(ClmmProgram::Whirlpool
+            .decode(&Pubkey::new_unique(), &data)
+            .is_err());
+        // a Whirlpool decoded as a Raydium pool
+        assert!(ClmmProgram::RaydiumClmm
+            .decode(&ClmmProgram::RaydiumClmm.program_id(), &data)
+            .is_err());
+        // truncated
+        assert!(ClmmProgram::Whirlpool
+            .decode(&ClmmProgram::Whirlpool.program_id(), &data[..100])
+            .is_err());
+    }
+
+    #[test]
+    fn decodes_mint_decimals() {
+        assert_eq!(mint_decimals(&mint_fixture(6)).unwrap(), 6);
+        assert!(mint_decimals(&[0u8; MINT_LEN]).is_err());
+        assert!(mint_decimals(&mint_fixture(6)[..40]).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::*;
+use ethers::types::{H160, U512};
+
+// kept private, the generated call types would clash with the Uniswap V3 pool bindings
+mod bindings {
+    use super::*;
+
+    abigen!(
+        Pair,
+        r#"[
+            function token0() external view returns (address)
+            function token1() external view returns (address)
+            function getReserves() external view returns (uint256, uint256, uint256)
+        ]"#
+    );
+}
+use bindings::Pair;
+
+/// USDY/USDC price from a single Uniswap V2-style pair, including Solidly volatile
+/// (`x * y = k`) and stable (`x^3 * y + x * y^3 = k`) pairs.
+///
+/// The quote is weighted by twice the pair's USDC reserve, its depth in USDC like the
+/// weight of every other pool source, so they can be aggregated together.
+pub struct UniswapV2Source {
+    pub name: String,
+    pub chain: Chain,
+    pub provider: EvmProvider,
+    pub pair: H160,
+    pub usdy: H160,
+    pub usdc: H160,
+    /// Prices the pair with the Solidly stable-swap invariant.
+    pub stable: bool,
+    /// Pairs with less liquidity than this are ignored.
+    pub min_liquidity: u128,
+}
+
+impl PriceSource for UniswapV2Source {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> SourceKind {
+        SourceKind::Market
+    }
+
+    fn chain(&self) -> Chain {
+        self.chain
+    }
+
+    fn fetch(&self, block: PinnedBlock) -> SourceFuture<'_> {
+        Box::pin(async move {
+            let pair = Pair::new(self.pair, self.provider.clone().into());
+            let (token0, token1) = futures::future::try_join(
+                pair.token_0().block(block.id()).call(),
+                pair.token_1().block(block.id()).call(),
+            )
+            .await
+            .map_err(SourceError::rpc("token0/token1"))?;
+            let tokens =
+                PoolTokens::resolve(&self.provider, self.pair, token0, token1, self.usdy, self.usdc, block)
+                    .await?;
+
+            let (reserve0, reserve1, _) = pair
+                .get_reserves()
+                .block(block.id())
+                .call()
+                .await
+                .map_err(SourceError::rpc("getReserves"))?;
+
+            let liquidity = (U512::from(reserve0) * U512::from(reserve1)).integer_sqrt();
+            println!(
+                "{}: pair {:?} reserves {} / {} liquidity {}",
+                self.name, self.pair, reserve0, reserve1, liquidity
+            );
+            if liquidity < U512::from(self.min_liquidity) {
+                return Err(SourceError::NoLiquidity(format!(
+                    "pair {:?} liquidity {} is below {}",
+                    self.pair, liquidity, self.min_liquidity
+                )));
+            }
+            let (reserve_base, reserve_quote, base_decimals, quote_decimals) = if tokens.base_is_token0 {
+                (reserve0, reserve1, tokens.token0_decimals, tokens.token1_decimals)
+            } else {
+                (reserve1, reserve0, tokens.token1_decimals, tokens.token0_decimals)
+            };
+            let price = if self.stable {
+                stable_swap_price(reserve_base, reserve_quote, base_decimals, quote_decimals)?
+            } else {
+                constant_product_price(reserve_base, reserve_quote, base_decimals, quote_decimals)?
+            };
+            let weight = token_amount(reserve_quote, quote_decimals)? * Decimal::TWO;
+
+            Ok(Quote {
+                price,
+                weight: Some(weight),
+                flags: vec![],
+            })
+        })
+    }
+}
@@ -0,0 +1,329 @@
+use crate::*;
+use ethers::types::{H160, U256};
+
+abigen!(Factory, "./src/factory.json");
+abigen!(Pool, "./src/pool.json");
+
+/// Fee tiers enabled on Uniswap V3 and its forks, in hundredths of a bip.
+pub const DEFAULT_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+
+/// USDY/USDC price from a Uniswap V3-style factory, e.g. Agni or FusionX on Mantle.
+///
+/// Every fee tier pool on the factory with at least `min_liquidity` in-range liquidity is
+/// read, and the pool prices are weighted by their USDC depth at the spot price, see
+/// `clmm_quote_depth`. The quote's weight is the depth of all pools read.
+///
+/// Each pool is priced from the geometric-mean TWAP over `twap_seconds` from its
+/// `observe()`, so a single block can't move the price. Falls back to the slot0 spot price,
+/// flagged with `QuoteFlag::SpotPriceFallback`, when a pool's observations don't cover
+/// the window.
+pub struct UniswapV3Source {
+    pub name: String,
+    pub chain: Chain,
+    pub provider: EvmProvider,
+    pub factory: H160,
+    pub usdy: H160,
+    pub usdc: H160,
+    /// TWAP window in seconds. Zero always uses the slot0 spot price.
+    pub twap_seconds: u32,
+    pub fee_tiers: Vec<u32>,
+    /// Pools with less in-range liquidity than this are ignored.
+    pub min_liquidity: u128,
+}
+
+/// Price, in-range liquidity and USDC depth of a single pool.
+struct PoolQuote {
+    pool: H160,
+    fee: u32,
+    price: Decimal,
+    liquidity: u128,
+    depth: Decimal,
+    flags: Vec<QuoteFlag>,
+}
+
+impl UniswapV3Source {
+    /// Returns the pool for every configured fee tier that exists on the factory.
+    async fn discover_pools(&self, block: PinnedBlock) -> Result<Vec<(u32, H160)>, SourceError> {
+        let factory_contract = Factory::new(self.factory, self.provider.clone().into());
+
+        let mut pools = vec![];
+        for fee in self.fee_tiers.iter() {
+            let pool = factory_contract
+                .get_pool(self.usdc, self.usdy, *fee)
+                .block(block.id())
+                .call()
+                .await
+                .map_err(SourceError::rpc("getPool"))?;
+            if !pool.is_zero() {
+                pools.push((*fee, pool));
+            }
+        }
+        println!("{}: pools {:?}", self.name, &pools);
+
+        Ok(pools)
+    }
+
+    /// Prices a pool, or returns `None` without reading anything else when it has less
+    /// in-range liquidity than `min_liquidity`.
+    async fn quote_pool(
+        &self,
+        fee: u32,
+        pool: H160,
+        block: PinnedBlock,
+    ) -> Result<Option<PoolQuote>, SourceError> {
+        let pool_contract = Pool::new(pool, self.provider.clone().into());
+        let liquidity = pool_contract
+            .liquidity()
+            .block(block.id())
+            .call()
+            .await
+            .map_err(SourceError::rpc("liquidity"))?;
+        if liquidity < self.min_liquidity {
+            println!(
+                "{}: skipping pool {:?} ({} fee), liquidity {} is below {}",
+                self.name, pool, fee, liquidity, self.min_liquidity
+            );
+            return Ok(None);
+        }
+
+        let (token0, token1) = futures::future::try_join(
+            pool_contract.token_0().block(block.id()).call(),
+            pool_contract.token_1().block(block.id()).call(),
+        )
+        .await
+        .map_err(SourceError::rpc("token0/token1"))?;
+        let tokens =
+            PoolTokens::resolve(&self.provider, pool, token0, token1, self.usdy, self.usdc, block)
+                .await?;
+
+        let slot0 = pool_contract
+            .slot_0()
+            .block(block.id())
+            .call()
+            .await
+            .map_err(SourceError::rpc("slot0"))?;
+        //    sqrtPriceX96 = sqrt(price) * 2 ** 96
+        let spot_sqrt_price_x96: U256 = slot0.0;
+        let observation_cardinality: u16 = slot0.3;
+        let (usdc_is_token0, usdc_decimals) = if tokens.base_is_token0 {
+            (false, tokens.token1_decimals)
+        } else {
+            (true, tokens.token0_decimals)
+        };
+        let depth = clmm_quote_depth(liquidity, spot_sqrt_price_x96, 96, usdc_is_token0, usdc_decimals)?;
+
+        let mut flags = vec![];
+        let sqrt_price_x96 = if self.twap_seconds == 0 {
+            spot_sqrt_price_x96
+        } else if observation_cardinality < 2 {
+            println!(
+                "{}: observation cardinality {} is too low for a TWAP, using slot0",
+                self.name, observation_cardinality
+            );
+            flags.push(QuoteFlag::SpotPriceFallback);
+            spot_sqrt_price_x96
+        } else {
+            match pool_contract
+                .observe(vec![self.twap_seconds, 0])
+                .block(block.id())
+                .call()
+                .await
+            {
+                Ok((tick_cumulatives, _)) if tick_cumulatives.len() == 2 => {
+                    let tick =
+                        mean_tick(tick_cumulatives[0], tick_cumulatives[1], self.twap_seconds);
+                    sqrt_ratio_at_tick(tick)?
+                }
+                // observe() reverts with "OLD" when the window predates the oldest observation
+                Err(e) if revert_reason(&e).as_deref() == Some("OLD") => {
+                    println!(
+                        "{}: observations don't cover {}s, using slot0",
+                        self.name, self.twap_seconds
+                    );
+                    flags.push(QuoteFlag::SpotPriceFallback);
+                    spot_sqrt_price_x96
+                }
+                Ok(_) => {
+                    return Err(SourceError::InvalidData(
+                        "observe returned an unexpected number of observations".to_string(),
+                    ))
+                }
+                Err(e) => return Err(SourceError::rpc("observe")(e)),
+            }
+        };
+
+        let price = sqrt_price_x96_to_price(
+            sqrt_price_x96,
+            tokens.token0_decimals,
+            tokens.token1_decimals,
+            tokens.base_is_token0,
+        )?;
+
+        Ok(Some(PoolQuote {
+            pool,
+            fee,
+            price,
+            liquidity,
+            depth,
+            flags,
+        }))
+    }
+}
+
+impl PriceSource for UniswapV3Source {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> SourceKind {
+        SourceKind::Market
+    }
+
+    fn chain(&self) -> Chain {
+        self.chain
+    }
+
+    fn fetch(&self, block: PinnedBlock) -> SourceFuture<'_> {
+        Box::pin(async move {
+            let pools = self.discover_pools(block).await?;
+            let pool_quotes = futures::future::join_all(
+                pools.iter().map(|(fee, pool)| self.quote_pool(*fee, *pool, block)),
+            )
+            .await;
+
+            // a pool that can't be read, e.g. with a broken token, doesn't fail the factory
+            let mut prices = vec![];
+            let mut flags = vec![];
+            let mut last_error = None;
+            for ((fee, pool), pool_quote) in pools.iter().zip(pool_quotes) {
+                let pool_quote = match pool_quote {
+                    Ok(Some(pool_quote)) => pool_quote,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        println!("{}: skipping pool {:?} ({} fee), {}", self.name, pool, fee, e);
+                        last_error = Some(e);
+                        continue;
+                    }
+                };
+                println!(
+                    "{}: pool {:?} ({} fee) price {} liquidity {} depth {} USDC",
+                    self.name,
+                    pool_quote.pool,
+                    pool_quote.fee,
+                    pool_quote.price,
+                    pool_quote.liquidity,
+                    pool_quote.depth
+                );
+                prices.push((pool_quote.price, pool_quote.depth));
+                flags.extend(pool_quote.flags);
+            }
+            if prices.is_empty() {
+                if let Some(e) = last_error {
+                    return Err(e);
+                }
+                return Err(SourceError::NoLiquidity(format!(
+                    "no USDY/USDC pool with enough liquidity on factory {:?}",
+                    self.factory
+                )));
+            }
+            flags.dedup();
+
+            let price = weighted_mean(&prices)?;
+            let depth: Decimal = prices.iter().map(|(_, depth)| *depth).sum();
+            println!("Uniswap price: {}", price);
+            Ok(Quote {
+                price,
+                weight: Some(depth),
+                flags,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_evm::EvmFixture;
+    use crate::mock_server::MockServer;
+
+    const AGNI: &str = "0x25780dc8Fc3cfBD75F33bFDAB65e969b603b2035";
+    const USDY: &str = "0x5bE26527e817998A7206475496fDE1E68957c5A6";
+    const USDC: &str = "0x09Bc4E0D864854c6aFB6eB9A9cdF58aC190D0dF9";
+    /// A pool below `min_liquidity` with nothing but `liquidity()` in the fixture.
+    const SHALLOW_POOL: &str = "0x3333333333333333333333333333333333333333";
+    /// A pool whose `token0()` reverts.
+    const BROKEN_POOL: &str = "0x4444444444444444444444444444444444444444";
+
+    fn fixture(pools: &[(&str, &str)]) -> EvmFixture {
+        let mut fixture = EvmFixture::load(include_str!("../fixtures/mantle.json"));
+        for (fee, pool) in pools {
+            fixture.set(
+                AGNI,
+                "getPool(address,address,uint24) returns (address)",
+                &[USDC, USDY, fee],
+                &[pool],
+            );
+        }
+        fixture.set(SHALLOW_POOL, "liquidity() returns (uint128)", &[], &["1"]);
+        fixture.set(BROKEN_POOL, "liquidity() returns (uint128)", &[], &["5000000000000000"]);
+        fixture
+    }
+
+    async fn fetch(fixture: EvmFixture) -> Result<Quote, SourceError> {
+        let block = PinnedBlock {
+            number: fixture.block.number,
+            timestamp: fixture.block.timestamp as i64,
+        };
+        let server: MockServer = fixture.serve().await;
+        let rpc = RpcConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        let source = UniswapV3Source {
+            name: "agni".to_string(),
+            chain: Chain::Mantle,
+            provider: EvmProvider::new(FailoverClient::new(&[server.url.clone()], rpc).unwrap()),
+            factory: AGNI.parse().unwrap(),
+            usdy: USDY.parse().unwrap(),
+            usdc: USDC.parse().unwrap(),
+            twap_seconds: 1800,
+            fee_tiers: DEFAULT_FEE_TIERS.to_vec(),
+            min_liquidity: 1_000_000_000_000,
+        };
+        source.fetch(block).await
+    }
+
+    #[tokio::test]
+    async fn shallow_and_broken_pools_are_skipped() {
+        let quote = fetch(fixture(&[("3000", SHALLOW_POOL), ("10000", BROKEN_POOL)]))
+            .await
+            .unwrap();
+        let price = sqrt_price_x96_to_price(sqrt_ratio_at_tick(275836).unwrap(), 6, 18, false).unwrap();
+        assert_eq!(quote.price, price);
+        // twice the pool's virtual USDC reserve, L * 2^96 / sqrtPriceX96
+        assert_eq!(quote.weight, Some(Decimal::from_str("4098.80088").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn shallow_pools_are_not_read() {
+        // the shallow pool's tokens aren't in the fixture, reading them would fail the source
+        let e = fetch(fixture(&[
+            ("500", "0x0000000000000000000000000000000000000000"),
+            ("3000", SHALLOW_POOL),
+        ]))
+        .await
+        .unwrap_err();
+        assert!(matches!(e, SourceError::NoLiquidity(_)));
+    }
+
+    #[tokio::test]
+    async fn fails_when_no_pool_can_be_read() {
+        let e = fetch(fixture(&[
+            ("500", "0x0000000000000000000000000000000000000000"),
+            ("10000", BROKEN_POOL),
+        ]))
+        .await
+        .unwrap_err();
+        assert!(matches!(e, SourceError::Rpc { call: "token0/token1", .. }));
+    }
+}